@@ -0,0 +1,124 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Expr, Fields,
+    spanned::Spanned
+};
+
+
+/// A check on a field declared with a `#[validate(...)]` attribute.
+enum Check {
+    Range { min: Option<Box<Expr>>, max: Option<Box<Expr>> },
+    NonEmpty,
+}
+
+
+/// Parses the checks declared on a field by its `#[validate(...)]` attributes.
+fn parse_checks(field: &syn::Field) -> syn::Result<Vec<Check>> {
+    let mut checks = Vec::new();
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("validate")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("non_empty") {
+                checks.push(Check::NonEmpty);
+                return Ok(())
+            }
+            if meta.path.is_ident("range") {
+                let mut min = None;
+                let mut max = None;
+                meta.parse_nested_meta(|bound| {
+                    if bound.path.is_ident("min") {
+                        min = Some(Box::new(bound.value()?.parse::<Expr>()?));
+                        Ok(())
+                    } else if bound.path.is_ident("max") {
+                        max = Some(Box::new(bound.value()?.parse::<Expr>()?));
+                        Ok(())
+                    } else {
+                        Err(bound.error("expected `min` or `max`"))
+                    }
+                })?;
+                if min.is_none() && max.is_none() {
+                    return Err(meta.error("range needs a `min`, a `max`, or both"))
+                }
+                checks.push(Check::Range { min, max });
+                return Ok(())
+            }
+            Err(meta.error("unsupported validation, expected `range(...)` or `non_empty`"))
+        })?;
+    }
+    Ok(checks)
+}
+
+
+#[proc_macro_derive(ContractValidate, attributes(validate))]
+pub fn derive_contract_validate(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    // Only structs with named fields can name the failing field
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return syn::Error::new(input.span(), "ContractValidate needs a struct with named fields")
+                .to_compile_error()
+                .into()
+        },
+        _ => return syn::Error::new(input.span(), "ContractValidate can only be derived for structs")
+            .to_compile_error()
+            .into()
+    };
+
+    // Generate the checks in the order the fields are declared so the first failing field is returned
+    let mut checks = Vec::new();
+    for field in fields {
+        let field_name = field.ident.as_ref().unwrap();
+        let field_checks = match parse_checks(field) {
+            Ok(field_checks) => field_checks,
+            Err(e) => return e.to_compile_error().into()
+        };
+        for check in field_checks {
+            let (failed, message) = match check {
+                Check::NonEmpty => (
+                    quote! { self.#field_name.is_empty() },
+                    quote! { format!("{} must not be empty", stringify!(#field_name)) }
+                ),
+                Check::Range { min: Some(min), max: Some(max) } => (
+                    quote! { !(self.#field_name >= #min && self.#field_name <= #max) },
+                    quote! {
+                        format!("{} must be between {} and {}, got {}", stringify!(#field_name), #min, #max, self.#field_name)
+                    }
+                ),
+                Check::Range { min: Some(min), max: None } => (
+                    quote! { !(self.#field_name >= #min) },
+                    quote! { format!("{} must be at least {}, got {}", stringify!(#field_name), #min, self.#field_name) }
+                ),
+                Check::Range { min: None, max: Some(max) } => (
+                    quote! { !(self.#field_name <= #max) },
+                    quote! { format!("{} must be at most {}, got {}", stringify!(#field_name), #max, self.#field_name) }
+                ),
+                Check::Range { min: None, max: None } => unreachable!("a range always has a bound")
+            };
+            checks.push(quote! {
+                if #failed {
+                    return Err(nanoservices_utils::errors::NanoServiceError::new(
+                        #message,
+                        nanoservices_utils::errors::NanoServiceErrorStatus::BadRequest
+                    ))
+                }
+            });
+        }
+    }
+
+    let expanded = quote! {
+        impl #impl_generics nanoservices_utils::validation::ContractValidate for #name #ty_generics #where_clause {
+            fn validate(&self) -> Result<(), nanoservices_utils::errors::NanoServiceError> {
+                #(#checks)*
+                Ok(())
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
@@ -73,11 +73,18 @@ pub fn subscribe_to_event(_attr: TokenStream, item: TokenStream) -> TokenStream
         // Inline trait checks
         #check_traits
 
-        // Define a router function that accepts bincode and returns a boxed future
+        // Define a router function that accepts bincode and returns a boxed future. Data that does not
+        // deserialize is logged and dropped rather than panicking the task
         #[doc(hidden)]
         fn #routed_func_name(data: Vec<u8>) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> {
             std::boxed::Box::pin(async move {
-                let deserialized: #param_type = nanoservices_utils::bincode::deserialize(&data).unwrap();
+                let deserialized: #param_type = match nanoservices_utils::tokio_pub_sub::deserialize_event(
+                    #param_name,
+                    &data
+                ) {
+                    Some(deserialized) => deserialized,
+                    None => return
+                };
                 #func_name(deserialized).await;
             })
         }
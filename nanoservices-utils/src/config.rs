@@ -17,6 +17,9 @@
 //! 
 //! let _ = 
 use std::env;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::OnceLock;
 use crate::errors::{
     NanoServiceError,
     NanoServiceErrorStatus
@@ -34,6 +37,29 @@ pub trait GetConfigVariable {
     /// # Returns
     /// * `Result<String, NanoServiceError>` - The result of getting the config variable
     fn get_config_variable(variable: String) -> Result<String, NanoServiceError>;
+
+    /// Names the source the config variables are read from so a failed lookup can say where it looked.
+    ///
+    /// # Returns
+    /// * `String` - The name of the source, the name of the type if it is not overridden
+    fn config_source() -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+}
+
+
+/// Builds the error for a config variable that a source does not have, naming the source.
+///
+/// # Arguments
+/// * `variable` - The name of the config variable that was not found
+///
+/// # Returns
+/// * `NanoServiceError` - The error naming the variable and the source consulted
+pub fn config_variable_not_found<X: GetConfigVariable + ?Sized>(variable: &str) -> NanoServiceError {
+    NanoServiceError::new(
+        format!("{} not found in {}", variable, X::config_source()),
+        NanoServiceErrorStatus::Unknown
+    )
 }
 
 
@@ -52,12 +78,181 @@ impl GetConfigVariable for EnvConfig {
     fn get_config_variable(variable: String) -> Result<String, NanoServiceError> {
         match env::var(&variable) {
             Ok(val) => Ok(val),
-            Err(_) => Err(
-                NanoServiceError::new(
-                    format!("{} not found in environment", variable),
-                    NanoServiceErrorStatus::Unknown
-                )
+            Err(_) => Err(config_variable_not_found::<Self>(&variable))
+        }
+    }
+
+    fn config_source() -> String {
+        "environment".to_string()
+    }
+}
+
+
+/// Defines a config that reads a variable from the `First` source and falls back to the `Second` source if the
+/// first does not have it. Layers can be nested to consult more sources, for example
+/// `LayeredConfig<EnvConfig, LayeredConfig<SnapshotConfig, FileConfig>>`.
+pub struct LayeredConfig<First: GetConfigVariable, Second: GetConfigVariable> {
+    phantom: PhantomData<(First, Second)>,
+}
+
+impl<First: GetConfigVariable, Second: GetConfigVariable> GetConfigVariable for LayeredConfig<First, Second> {
+
+    /// Gets the config variable from the first source that has it
+    ///
+    /// # Arguments
+    /// * `variable` - The name of the config variable to get
+    ///
+    /// # Returns
+    /// * `Result<String, NanoServiceError>` - The value from the first source that has it, or an error naming
+    ///   every source consulted
+    fn get_config_variable(variable: String) -> Result<String, NanoServiceError> {
+        First::get_config_variable(variable.clone())
+            .or_else(|_| Second::get_config_variable(variable.clone()))
+            .map_err(|_| config_variable_not_found::<Self>(&variable))
+    }
+
+    fn config_source() -> String {
+        format!("{} or {}", First::config_source(), Second::config_source())
+    }
+}
+
+
+/// The process wide snapshot served by `SnapshotConfig` through the `GetConfigVariable` trait.
+static ENV_SNAPSHOT: OnceLock<SnapshotConfig> = OnceLock::new();
+
+
+/// Defines a config that captures the variables once at construction and serves the same values for the rest
+/// of the process. Unlike `EnvConfig`, a variable changed after the snapshot was taken is not picked up.
+///
+/// # Fields
+/// * `variables` - The captured config variables.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SnapshotConfig {
+    variables: HashMap<String, String>,
+}
+
+impl SnapshotConfig {
+
+    /// Captures every variable in the environment.
+    ///
+    /// # Returns
+    /// * `SnapshotConfig` - The snapshot of the environment.
+    pub fn from_env() -> Self {
+        SnapshotConfig {
+            variables: env::vars().collect(),
+        }
+    }
+
+    /// Captures a set of variables from a config source.
+    ///
+    /// # Arguments
+    /// * `keys` - The names of the config variables to capture.
+    ///
+    /// # Returns
+    /// * `Result<SnapshotConfig, NanoServiceError>` - The snapshot, or the error of the first variable that could
+    ///   not be read.
+    pub fn from_config<X: GetConfigVariable>(keys: &[&str]) -> Result<Self, NanoServiceError> {
+        let mut variables = HashMap::with_capacity(keys.len());
+        for key in keys {
+            variables.insert(key.to_string(), X::get_config_variable(key.to_string())?);
+        }
+        Ok(SnapshotConfig { variables })
+    }
+
+    /// Gets a config variable from the snapshot.
+    ///
+    /// # Arguments
+    /// * `variable` - The name of the config variable to get
+    ///
+    /// # Returns
+    /// * `Result<String, NanoServiceError>` - The value captured when the snapshot was taken
+    pub fn get(&self, variable: &str) -> Result<String, NanoServiceError> {
+        self.variables.get(variable).cloned().ok_or_else(|| config_variable_not_found::<Self>(variable))
+    }
+
+    /// Installs the snapshot as the one served through the `GetConfigVariable` trait. If nothing is installed
+    /// the whole environment is captured on the first lookup.
+    ///
+    /// # Returns
+    /// * `Result<(), NanoServiceError>` - An error if a snapshot has already been installed or captured.
+    pub fn install(self) -> Result<(), NanoServiceError> {
+        ENV_SNAPSHOT.set(self).map_err(|_| {
+            NanoServiceError::new(
+                "Config snapshot has already been taken".to_string(),
+                NanoServiceErrorStatus::Unknown
             )
+        })
+    }
+}
+
+impl GetConfigVariable for SnapshotConfig {
+
+    /// Gets the config variable from the process wide snapshot
+    ///
+    /// # Arguments
+    /// * `variable` - The name of the config variable to get
+    ///
+    /// # Returns
+    /// * `Result<String, NanoServiceError>` - The result of getting the config variable
+    fn get_config_variable(variable: String) -> Result<String, NanoServiceError> {
+        ENV_SNAPSHOT.get_or_init(SnapshotConfig::from_env).get(&variable)
+    }
+
+    fn config_source() -> String {
+        "config snapshot".to_string()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_snapshot_ignores_later_changes() {
+        env::set_var("NANOSERVICES_SNAPSHOT_TEST", "before");
+        let snapshot = SnapshotConfig::from_config::<EnvConfig>(&["NANOSERVICES_SNAPSHOT_TEST"]).unwrap();
+        let full_snapshot = SnapshotConfig::from_env();
+        env::set_var("NANOSERVICES_SNAPSHOT_TEST", "after");
+
+        assert_eq!(snapshot.get("NANOSERVICES_SNAPSHOT_TEST").unwrap(), "before");
+        assert_eq!(full_snapshot.get("NANOSERVICES_SNAPSHOT_TEST").unwrap(), "before");
+        assert_eq!(EnvConfig::get_config_variable("NANOSERVICES_SNAPSHOT_TEST".to_string()).unwrap(), "after");
+        assert!(snapshot.get("NANOSERVICES_SNAPSHOT_MISSING").is_err());
+        assert!(SnapshotConfig::from_config::<EnvConfig>(&["NANOSERVICES_SNAPSHOT_MISSING"]).is_err());
+    }
+
+    struct DefaultsConfig;
+
+    impl GetConfigVariable for DefaultsConfig {
+        fn get_config_variable(variable: String) -> Result<String, NanoServiceError> {
+            match variable.as_str() {
+                "NANOSERVICES_LAYERED_DEFAULT" => Ok("default".to_string()),
+                _ => Err(config_variable_not_found::<Self>(&variable))
+            }
+        }
+
+        fn config_source() -> String {
+            "defaults".to_string()
         }
     }
+
+    #[test]
+    fn test_layered_config() {
+        type Layered = LayeredConfig<EnvConfig, LayeredConfig<SnapshotConfig, DefaultsConfig>>;
+        env::set_var("NANOSERVICES_LAYERED_ENV", "env");
+
+        assert_eq!(Layered::get_config_variable("NANOSERVICES_LAYERED_ENV".to_string()).unwrap(), "env");
+        assert_eq!(Layered::get_config_variable("NANOSERVICES_LAYERED_DEFAULT".to_string()).unwrap(), "default");
+        assert_eq!(
+            Layered::get_config_variable("NANOSERVICES_LAYERED_MISSING".to_string()).unwrap_err().message,
+            "NANOSERVICES_LAYERED_MISSING not found in environment or config snapshot or defaults"
+        );
+        assert_eq!(
+            EnvConfig::get_config_variable("NANOSERVICES_LAYERED_MISSING".to_string()).unwrap_err().message,
+            "NANOSERVICES_LAYERED_MISSING not found in environment"
+        );
+    }
+
 }
@@ -17,7 +17,6 @@ macro_rules! define_dal_transactions {
 #[cfg(test)]
 mod tests {
 
-    use super::*;
     use crate::impl_transaction;
     use crate::errors::NanoServiceError;
     use std::future::Future;
@@ -60,7 +59,7 @@ mod tests {
         struct PostgresHandle;
 
         #[impl_transaction(PostgresHandle, CreateUser, create)]
-        async fn create_user_postgres(user: NewUser) -> Result<i32, NanoServiceError> {
+        async fn create_user_postgres(_user: NewUser) -> Result<i32, NanoServiceError> {
             Ok(1)
         }
         let new_user = NewUser {
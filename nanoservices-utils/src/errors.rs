@@ -1,11 +1,18 @@
 //! `NanoServiceError` structs are the way in which nanoservices can pass errors between each other and to the client
 //! if the `ResponseError` trait is implemented for the specific web-framework being used. The `NanoServiceErrorStatus`
 //! enum is used to define the status of the error.
-use serde::{Deserialize, Serialize};
+//!
+//! Without the `std` feature the errors are built on `core` and `alloc`, the bitcode and revisioned formats and the
+//! web framework conversions need `std`.
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "std")]
 use bitcode::{Encode, Decode};
 use thiserror::Error;
-use std::fmt;
-use revision::revisioned;
+use core::fmt;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use revision::{revisioned, Revisioned};
 
 #[cfg(feature = "actix")]
 use actix_web::{
@@ -16,7 +23,7 @@ use actix_web::{
 
 #[cfg(feature = "rocket")]
 use rocket::{
-    http::Status,
+    http::{ContentType, Status},
     response::{Responder, Response},
     Request,
 };
@@ -38,9 +45,16 @@ use hyper::{
 #[cfg(feature = "hyper")]
 use http_body_util::Full;
 
+#[cfg(feature = "tonic")]
+use tonic::{
+    Code as TonicCode,
+    Status as TonicStatus
+};
+
 
+#[cfg(feature = "std")]
 #[derive(Error, Debug, Serialize, Deserialize, PartialEq, Clone, Encode, Decode)]
-#[revisioned(revision = 1)]
+#[revisioned(revision = 3)]
 pub enum NanoServiceErrorStatus {
     #[error("Requested resource was not found")]
     NotFound,
@@ -56,6 +70,66 @@ pub enum NanoServiceErrorStatus {
     Unauthorized,
     #[error("Contract not supported")]
     ContractNotSupported,
+    #[error("Payload too large")]
+    #[revision(start = 2)]
+    PayloadTooLarge,
+    #[error("Too many requests")]
+    #[revision(start = 3)]
+    TooManyRequests,
+    #[error("Service unavailable")]
+    #[revision(start = 3)]
+    ServiceUnavailable,
+}
+
+/// The status of a `NanoServiceError` without `std`. The bitcode and revisioned formats need `std`, and their
+/// attributes can not be switched off by feature, so the variants are repeated here without them. Every match on the
+/// status lists all of the variants so the two definitions can not drift apart.
+#[cfg(not(feature = "std"))]
+#[derive(Error, Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub enum NanoServiceErrorStatus {
+    #[error("Requested resource was not found")]
+    NotFound,
+    #[error("You are forbidden to access requested resource.")]
+    Forbidden,
+    #[error("Unknown Internal Error")]
+    Unknown,
+    #[error("Bad Request")]
+    BadRequest,
+    #[error("Conflict")]
+    Conflict,
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("Contract not supported")]
+    ContractNotSupported,
+    #[error("Payload too large")]
+    PayloadTooLarge,
+    #[error("Too many requests")]
+    TooManyRequests,
+    #[error("Service unavailable")]
+    ServiceUnavailable,
+}
+
+
+impl NanoServiceErrorStatus {
+
+    /// Maps the status to the HTTP status code that the web framework responses use.
+    ///
+    /// # Returns
+    /// * `u16` - The HTTP status code.
+    pub fn http_status(&self) -> u16 {
+        match self {
+            NanoServiceErrorStatus::NotFound => 404,
+            NanoServiceErrorStatus::Forbidden => 403,
+            NanoServiceErrorStatus::Unknown => 500,
+            NanoServiceErrorStatus::BadRequest => 400,
+            NanoServiceErrorStatus::Conflict => 409,
+            NanoServiceErrorStatus::Unauthorized => 401,
+            NanoServiceErrorStatus::ContractNotSupported => 501,
+            NanoServiceErrorStatus::PayloadTooLarge => 413,
+            NanoServiceErrorStatus::TooManyRequests => 429,
+            NanoServiceErrorStatus::ServiceUnavailable => 503,
+        }
+    }
 }
 
 
@@ -64,11 +138,116 @@ pub enum NanoServiceErrorStatus {
 /// # Fields
 /// * `message` - The message of the error.
 /// * `status` - The status of the error.
-#[derive(Serialize, Deserialize, Debug, Error, PartialEq, Clone, Encode, Decode)]
-#[revisioned(revision = 1)]
+/// * `code` - A stable machine-readable code for the error (e.g. `"user.not_found"`), empty if not set.
+/// * `source` - The error that caused this error, returned by `std::error::Error::source`.
+///
+/// # Notes
+/// The `code` is carried by the self-describing formats (e.g. JSON) and by the revisioned format, which records the
+/// revision so peers can tell the layouts apart. The compact binary formats (bincode, MessagePack, and bitcode) have
+/// no room for a new field without breaking peers that decode the old layout, so they keep the message and status
+/// only and the code is empty when an error is decoded from them.
+#[cfg(feature = "std")]
+#[derive(Debug, PartialEq, Clone, Encode, Decode)]
+#[revisioned(revision = 2)]
+pub struct NanoServiceError {
+    pub message: String,
+    pub status: NanoServiceErrorStatus,
+    #[revision(start = 2)]
+    #[bitcode(skip)]
+    pub code: String,
+    #[bitcode(skip)]
+    source: ErrorSource
+}
+
+/// A `NanoServiceError` without `std`, the same fields without the attributes of the bitcode and revisioned formats
+/// (see `NanoServiceErrorStatus`).
+#[cfg(not(feature = "std"))]
+#[derive(Debug, PartialEq, Clone)]
 pub struct NanoServiceError {
     pub message: String,
-    pub status: NanoServiceErrorStatus
+    pub status: NanoServiceErrorStatus,
+    pub code: String,
+    source: ErrorSource
+}
+
+
+/// The serde layout of a `NanoServiceError` for the compact binary formats, unchanged since before the code was
+/// added so the error contracts of older peers still decode.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "NanoServiceError")]
+struct CompactError<M, S> {
+    message: M,
+    status: S,
+}
+
+/// The serde layout of a `NanoServiceError` for the self-describing formats, which ignore unknown fields and
+/// default missing ones so the code can be added without breaking older peers.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "NanoServiceError")]
+struct ReadableError<M, S, C> {
+    message: M,
+    status: S,
+    #[serde(default)]
+    code: C,
+}
+
+impl Serialize for NanoServiceError {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        match serializer.is_human_readable() {
+            true => ReadableError {
+                message: &self.message,
+                status: &self.status,
+                code: &self.code
+            }.serialize(serializer),
+            false => CompactError {
+                message: &self.message,
+                status: &self.status
+            }.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for NanoServiceError {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (message, status, code) = match deserializer.is_human_readable() {
+            true => {
+                let error = ReadableError::<String, NanoServiceErrorStatus, String>::deserialize(deserializer)?;
+                (error.message, error.status, error.code)
+            },
+            false => {
+                let error = CompactError::<String, NanoServiceErrorStatus>::deserialize(deserializer)?;
+                (error.message, error.status, String::new())
+            }
+        };
+        Ok(NanoServiceError::new(message, status).with_code(code))
+    }
+}
+
+
+/// The error that caused a `NanoServiceError`. The source only lives in the process that created the error, it is
+/// never written to the wire by any of the serialization formats and is ignored when comparing errors.
+#[derive(Debug, Clone, Default)]
+struct ErrorSource(Option<Arc<dyn core::error::Error + Send + Sync>>);
+
+impl PartialEq for ErrorSource {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(feature = "std")]
+impl Revisioned for ErrorSource {
+    fn revision() -> u16 {
+        1
+    }
+
+    fn serialize_revisioned<W: std::io::Write>(&self, _: &mut W) -> Result<(), revision::Error> {
+        Ok(())
+    }
+
+    fn deserialize_revisioned<R: std::io::Read>(_: &mut R) -> Result<Self, revision::Error> {
+        Ok(ErrorSource::default())
+    }
 }
 
 impl NanoServiceError {
@@ -84,14 +263,220 @@ impl NanoServiceError {
     pub fn new(message: String, status: NanoServiceErrorStatus) -> NanoServiceError {
         NanoServiceError {
             message,
-            status
+            status,
+            code: String::new(),
+            source: ErrorSource::default()
+        }
+    }
+
+    /// Constructs a new error from the error that caused it, keeping the cause so it can be reached through
+    /// `std::error::Error::source` when debugging. The message is the message of the cause.
+    ///
+    /// # Arguments
+    /// * `source` - The error that caused this error.
+    /// * `status` - The status of the error.
+    ///
+    /// # Returns
+    /// * `NanoServiceError` - The new error.
+    pub fn from_source<E>(source: E, status: NanoServiceErrorStatus) -> NanoServiceError
+    where
+        E: core::error::Error + Send + Sync + 'static
+    {
+        NanoServiceError {
+            message: source.to_string(),
+            status,
+            code: String::new(),
+            source: ErrorSource(Some(Arc::new(source)))
+        }
+    }
+
+    /// Converts an I/O error, keeping a `NanoServiceError` the I/O error wraps so the status it was raised with
+    /// (e.g. the `PayloadTooLarge` of a codec size guard) is not lost, and using `status` for any other I/O error.
+    ///
+    /// # Arguments
+    /// * `error` - The I/O error, e.g. from reading or writing a framed connection.
+    /// * `status` - The status used when the I/O error does not wrap a `NanoServiceError`.
+    ///
+    /// # Returns
+    /// * `NanoServiceError` - The wrapped error, or a new error with the I/O error as its source.
+    #[cfg(feature = "std")]
+    pub fn from_io_error(error: std::io::Error, status: NanoServiceErrorStatus) -> NanoServiceError {
+        match error.get_ref().and_then(|inner| inner.downcast_ref::<NanoServiceError>()) {
+            Some(inner) => inner.clone(),
+            None => NanoServiceError::from_source(error, status)
+        }
+    }
+
+    /// Sets the machine-readable code of the error so clients can handle it without parsing the message.
+    ///
+    /// # Arguments
+    /// * `code` - The code of the error, e.g. `"user.not_found"`.
+    ///
+    /// # Returns
+    /// * `NanoServiceError` - The error with the code.
+    pub fn with_code(mut self, code: impl Into<String>) -> NanoServiceError {
+        self.code = code.into();
+        self
+    }
+
+    /// Checks if the error was caused by the client (a 4xx HTTP status).
+    ///
+    /// # Returns
+    /// * `bool` - `true` if the error is a client error.
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.status.http_status())
+    }
+
+    /// Checks if the error was caused by the server (a 5xx HTTP status).
+    ///
+    /// # Returns
+    /// * `bool` - `true` if the error is a server error.
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.status.http_status())
+    }
+
+    /// Checks if the request that caused the error can be retried. Only errors that could be transient are
+    /// retryable, errors caused by the request itself will fail again if retried.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if the request can be retried.
+    pub fn is_retryable(&self) -> bool {
+        match self.status {
+            NanoServiceErrorStatus::Unknown
+            | NanoServiceErrorStatus::TooManyRequests
+            | NanoServiceErrorStatus::ServiceUnavailable => true,
+            NanoServiceErrorStatus::NotFound
+            | NanoServiceErrorStatus::Forbidden
+            | NanoServiceErrorStatus::BadRequest
+            | NanoServiceErrorStatus::Conflict
+            | NanoServiceErrorStatus::Unauthorized
+            | NanoServiceErrorStatus::ContractNotSupported
+            | NanoServiceErrorStatus::PayloadTooLarge => false,
+        }
+    }
+
+    /// Checks if the error has the status, ignoring the message and code. Use this when the message includes a
+    /// volatile underlying error string.
+    ///
+    /// # Arguments
+    /// * `status` - The status to compare against.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if the error has the status.
+    pub fn matches_status(&self, status: &NanoServiceErrorStatus) -> bool {
+        &self.status == status
+    }
+
+    /// Checks if two errors have the same status, ignoring the message and code.
+    ///
+    /// # Arguments
+    /// * `other` - The error to compare against.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if both errors have the same status.
+    pub fn same_status(&self, other: &NanoServiceError) -> bool {
+        self.matches_status(&other.status)
+    }
+}
+
+/// The JSON body of the HTTP responses for an error. The body is the message as a JSON string unless the error has a
+/// code, then it is an object with the message and the code so existing clients keep working.
+#[cfg(all(
+    any(feature = "actix", feature = "rocket", feature = "axum", feature = "hyper"),
+    not(feature = "structured-errors")
+))]
+#[derive(Serialize)]
+#[serde(untagged)]
+pub(crate) enum ErrorBody<'a> {
+    Message(&'a str),
+    WithCode {
+        message: &'a str,
+        code: &'a str
+    }
+}
+
+/// The JSON body of the HTTP responses for an error with the `structured-errors` feature, an object with every
+/// field of the error so clients can handle errors the same way whatever framework the service uses.
+///
+/// # Fields
+/// * `message` - The message of the error.
+/// * `status` - The status of the error.
+/// * `code` - The machine-readable code of the error, empty if not set.
+#[cfg(all(
+    any(feature = "actix", feature = "rocket", feature = "axum", feature = "hyper"),
+    feature = "structured-errors"
+))]
+#[derive(Serialize)]
+pub(crate) struct ErrorBody<'a> {
+    message: &'a str,
+    status: &'a NanoServiceErrorStatus,
+    code: &'a str
+}
+
+#[cfg(any(feature = "actix", feature = "rocket", feature = "axum", feature = "hyper"))]
+impl NanoServiceError {
+
+    /// Builds the JSON body of the HTTP responses for the error.
+    ///
+    /// # Returns
+    /// * `ErrorBody` - The body borrowing the fields of the error.
+    #[cfg(not(feature = "structured-errors"))]
+    pub(crate) fn body(&self) -> ErrorBody<'_> {
+        match self.code.is_empty() {
+            true => ErrorBody::Message(&self.message),
+            false => ErrorBody::WithCode {
+                message: &self.message,
+                code: &self.code
+            }
+        }
+    }
+
+    /// Builds the JSON body of the HTTP responses for the error.
+    ///
+    /// # Returns
+    /// * `ErrorBody` - The body borrowing the fields of the error.
+    #[cfg(feature = "structured-errors")]
+    pub(crate) fn body(&self) -> ErrorBody<'_> {
+        ErrorBody {
+            message: &self.message,
+            status: &self.status,
+            code: &self.code
         }
     }
 }
 
+/// Formats the error for logs with the status as a tag (e.g. `[NotFound] resource missing`). HTTP responses are
+/// built from the `message` field so the status tag never ends up in a response body.
 impl fmt::Display for NanoServiceError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.message)
+        write!(f, "[{:?}] {}", self.status, self.message)
+    }
+}
+
+impl core::error::Error for NanoServiceError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match &self.source.0 {
+            Some(source) => Some(source.as_ref()),
+            None => None
+        }
+    }
+}
+
+/// Converts I/O errors with the `Unknown` status so `?` can be used on reads and writes, unless the I/O error wraps
+/// a `NanoServiceError` (e.g. a codec size guard) in which case that error is returned. Use `safe_eject!` when
+/// the error needs a different status.
+#[cfg(feature = "std")]
+impl From<std::io::Error> for NanoServiceError {
+    fn from(error: std::io::Error) -> Self {
+        NanoServiceError::from_io_error(error, NanoServiceErrorStatus::Unknown)
+    }
+}
+
+/// Converts JSON errors with the `BadRequest` status as they are normally caused by a malformed body.
+#[cfg(any(feature = "hyper", feature = "jwt", feature = "json"))]
+impl From<serde_json::Error> for NanoServiceError {
+    fn from(error: serde_json::Error) -> Self {
+        NanoServiceError::from_source(error, NanoServiceErrorStatus::BadRequest)
     }
 }
 
@@ -118,7 +503,13 @@ impl ResponseError for NanoServiceError {
             NanoServiceErrorStatus::Unauthorized =>
                 StatusCode::UNAUTHORIZED,
             NanoServiceErrorStatus::ContractNotSupported =>
-                StatusCode::NOT_IMPLEMENTED
+                StatusCode::NOT_IMPLEMENTED,
+            NanoServiceErrorStatus::PayloadTooLarge =>
+                StatusCode::PAYLOAD_TOO_LARGE,
+            NanoServiceErrorStatus::TooManyRequests =>
+                StatusCode::TOO_MANY_REQUESTS,
+            NanoServiceErrorStatus::ServiceUnavailable =>
+                StatusCode::SERVICE_UNAVAILABLE
         }
     }
 
@@ -128,28 +519,74 @@ impl ResponseError for NanoServiceError {
     /// * `HttpResponse` - The HTTP response for the error.
     fn error_response(&self) -> HttpResponse {
         let status_code = self.status_code();
-        HttpResponse::build(status_code).json(self.message.clone())
+        HttpResponse::build(status_code).json(self.body())
     }
 }
 
 
 #[cfg(feature = "rocket")]
-#[rocket::async_trait]
-impl<'r> Responder<'r, 'static> for NanoServiceError {
-    fn respond_to(self, _: &'r Request<'_>) -> rocket::response::Result<'static> {
-        let status = match self.status {
+impl NanoServiceError {
+
+    /// Yields the rocket status for the error.
+    ///
+    /// # Returns
+    /// * `Status` - The status for the error.
+    fn rocket_status(&self) -> Status {
+        match self.status {
             NanoServiceErrorStatus::NotFound => Status::NotFound,
             NanoServiceErrorStatus::Forbidden => Status::Forbidden,
             NanoServiceErrorStatus::Unknown => Status::InternalServerError,
             NanoServiceErrorStatus::BadRequest => Status::BadRequest,
             NanoServiceErrorStatus::Conflict => Status::Conflict,
             NanoServiceErrorStatus::Unauthorized => Status::Unauthorized,
-            NanoServiceErrorStatus::ContractNotSupported => Status::NotImplemented
+            NanoServiceErrorStatus::ContractNotSupported => Status::NotImplemented,
+            NanoServiceErrorStatus::PayloadTooLarge => Status::PayloadTooLarge,
+            NanoServiceErrorStatus::TooManyRequests => Status::TooManyRequests,
+            NanoServiceErrorStatus::ServiceUnavailable => Status::ServiceUnavailable
+        }
+    }
+}
+
+/// Responds with the message as a JSON body, matching the axum and hyper responses. Wrap the error in
+/// `PlainTextNanoServiceError` to respond with a plain text body instead.
+#[cfg(feature = "rocket")]
+#[rocket::async_trait]
+impl<'r> Responder<'r, 'static> for NanoServiceError {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        let json_body = match rocket::serde::json::to_string(&self.body()) {
+            Ok(json_body) => json_body,
+            Err(_) => return PlainTextNanoServiceError(self).respond_to(request)
         };
 
         Response::build()
-            .status(status)
-            .sized_body(self.message.len(), std::io::Cursor::new(self.message))
+            .status(self.rocket_status())
+            .header(ContentType::JSON)
+            .sized_body(json_body.len(), std::io::Cursor::new(json_body))
+            .ok()
+    }
+}
+
+/// Wraps a `NanoServiceError` so rocket responds with the message as a plain text body.
+#[cfg(feature = "rocket")]
+#[derive(Debug)]
+pub struct PlainTextNanoServiceError(pub NanoServiceError);
+
+#[cfg(feature = "rocket")]
+impl From<NanoServiceError> for PlainTextNanoServiceError {
+    fn from(error: NanoServiceError) -> Self {
+        PlainTextNanoServiceError(error)
+    }
+}
+
+#[cfg(feature = "rocket")]
+#[rocket::async_trait]
+impl<'r> Responder<'r, 'static> for PlainTextNanoServiceError {
+    fn respond_to(self, _: &'r Request<'_>) -> rocket::response::Result<'static> {
+        let error = self.0;
+        Response::build()
+            .status(error.rocket_status())
+            .header(ContentType::Plain)
+            .sized_body(error.message.len(), std::io::Cursor::new(error.message))
             .ok()
     }
 }
@@ -165,15 +602,24 @@ impl IntoResponse for NanoServiceError {
             NanoServiceErrorStatus::BadRequest => AxumStatusCode::BAD_REQUEST,
             NanoServiceErrorStatus::Conflict => AxumStatusCode::CONFLICT,
             NanoServiceErrorStatus::Unauthorized => AxumStatusCode::UNAUTHORIZED,
-            NanoServiceErrorStatus::ContractNotSupported => AxumStatusCode::NOT_IMPLEMENTED
+            NanoServiceErrorStatus::ContractNotSupported => AxumStatusCode::NOT_IMPLEMENTED,
+            NanoServiceErrorStatus::PayloadTooLarge => AxumStatusCode::PAYLOAD_TOO_LARGE,
+            NanoServiceErrorStatus::TooManyRequests => AxumStatusCode::TOO_MANY_REQUESTS,
+            NanoServiceErrorStatus::ServiceUnavailable => AxumStatusCode::SERVICE_UNAVAILABLE
         };
         
-        (status_code, Json(self.message)).into_response()
+        (status_code, Json(self.body())).into_response()
     }
 }
 
 #[cfg(feature = "hyper")]
 impl NanoServiceError {
+
+    /// Constructs a hyper response for the error with the message as a JSON body. Building the response
+    /// never panics, if the JSON body or the response cannot be built a plain text 500 response is returned.
+    ///
+    /// # Returns
+    /// * `HyperResponse<Full<Bytes>>` - The HTTP response for the error.
     pub fn into_hyper_response(self) -> HyperResponse<Full<Bytes>> {
         let status_code = match self.status {
             NanoServiceErrorStatus::NotFound => HyperStatusCode::NOT_FOUND,
@@ -182,20 +628,90 @@ impl NanoServiceError {
             NanoServiceErrorStatus::BadRequest => HyperStatusCode::BAD_REQUEST,
             NanoServiceErrorStatus::Conflict => HyperStatusCode::CONFLICT,
             NanoServiceErrorStatus::Unauthorized => HyperStatusCode::UNAUTHORIZED,
-            NanoServiceErrorStatus::ContractNotSupported => HyperStatusCode::NOT_IMPLEMENTED
+            NanoServiceErrorStatus::ContractNotSupported => HyperStatusCode::NOT_IMPLEMENTED,
+            NanoServiceErrorStatus::PayloadTooLarge => HyperStatusCode::PAYLOAD_TOO_LARGE,
+            NanoServiceErrorStatus::TooManyRequests => HyperStatusCode::TOO_MANY_REQUESTS,
+            NanoServiceErrorStatus::ServiceUnavailable => HyperStatusCode::SERVICE_UNAVAILABLE
         };
 
-        let json_body = serde_json::to_string(&self.message).unwrap();
+        let json_body = match serde_json::to_string(&self.body()) {
+            Ok(json_body) => json_body,
+            Err(_) => return Self::fallback_hyper_response()
+        };
 
         HyperResponse::builder()
                 .header(header::CONTENT_TYPE, "application/json")
                 .status(status_code)
                 .body(Full::new(Bytes::from(json_body)))
-                .unwrap()
+                .unwrap_or_else(|_| Self::fallback_hyper_response())
+    }
+
+    /// Constructs a plain text 500 response without going through the response builder so it cannot fail.
+    ///
+    /// # Returns
+    /// * `HyperResponse<Full<Bytes>>` - The fallback HTTP response.
+    fn fallback_hyper_response() -> HyperResponse<Full<Bytes>> {
+        let mut response = HyperResponse::new(Full::new(Bytes::from_static(b"Internal Server Error")));
+        *response.status_mut() = HyperStatusCode::INTERNAL_SERVER_ERROR;
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("text/plain")
+        );
+        response
     }
 }
 
 
+/// Converts the error into a gRPC status with the closest gRPC code so the same error type can be returned from
+/// tonic services.
+#[cfg(feature = "tonic")]
+impl From<NanoServiceError> for TonicStatus {
+    fn from(error: NanoServiceError) -> Self {
+        let code = match error.status {
+            NanoServiceErrorStatus::NotFound => TonicCode::NotFound,
+            NanoServiceErrorStatus::Forbidden => TonicCode::PermissionDenied,
+            NanoServiceErrorStatus::Unknown => TonicCode::Internal,
+            NanoServiceErrorStatus::BadRequest => TonicCode::InvalidArgument,
+            NanoServiceErrorStatus::Conflict => TonicCode::AlreadyExists,
+            NanoServiceErrorStatus::Unauthorized => TonicCode::Unauthenticated,
+            NanoServiceErrorStatus::ContractNotSupported => TonicCode::Unimplemented,
+            NanoServiceErrorStatus::PayloadTooLarge => TonicCode::ResourceExhausted,
+            NanoServiceErrorStatus::TooManyRequests => TonicCode::ResourceExhausted,
+            NanoServiceErrorStatus::ServiceUnavailable => TonicCode::Unavailable
+        };
+        TonicStatus::new(code, error.message)
+    }
+}
+
+#[cfg(feature = "tonic")]
+impl NanoServiceError {
+
+    /// Constructs an error from the gRPC status returned by a tonic client. gRPC codes without a matching status
+    /// become `Unknown`.
+    ///
+    /// # Arguments
+    /// * `status` - The gRPC status to convert.
+    ///
+    /// # Returns
+    /// * `NanoServiceError` - The error with the message of the status.
+    pub fn from_status(status: &TonicStatus) -> NanoServiceError {
+        let error_status = match status.code() {
+            TonicCode::NotFound => NanoServiceErrorStatus::NotFound,
+            TonicCode::PermissionDenied => NanoServiceErrorStatus::Forbidden,
+            TonicCode::InvalidArgument
+            | TonicCode::OutOfRange
+            | TonicCode::FailedPrecondition => NanoServiceErrorStatus::BadRequest,
+            TonicCode::AlreadyExists | TonicCode::Aborted => NanoServiceErrorStatus::Conflict,
+            TonicCode::Unauthenticated => NanoServiceErrorStatus::Unauthorized,
+            TonicCode::Unimplemented => NanoServiceErrorStatus::ContractNotSupported,
+            TonicCode::ResourceExhausted => NanoServiceErrorStatus::TooManyRequests,
+            TonicCode::Unavailable | TonicCode::DeadlineExceeded => NanoServiceErrorStatus::ServiceUnavailable,
+            _ => NanoServiceErrorStatus::Unknown
+        };
+        NanoServiceError::new(status.message().to_string(), error_status)
+    }
+}
+
 
 #[macro_export]
 macro_rules! safe_eject {
@@ -210,3 +726,414 @@ macro_rules! safe_eject {
         )
     };
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_display_includes_status() {
+        let error = NanoServiceError::new(
+            "resource missing".to_string(),
+            NanoServiceErrorStatus::NotFound
+        );
+        assert_eq!(error.to_string(), "[NotFound] resource missing");
+        assert_eq!(error.message, "resource missing");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_from_io_error() {
+        fn read_missing_file() -> Result<String, NanoServiceError> {
+            Ok(std::fs::read_to_string("/nanoservices/missing/file")?)
+        }
+        assert_eq!(read_missing_file().unwrap_err().status, NanoServiceErrorStatus::Unknown);
+
+        // an error wrapped in the I/O error keeps its status
+        let too_large = NanoServiceError::new("Payload too large".to_string(), NanoServiceErrorStatus::PayloadTooLarge);
+        let io_error = std::io::Error::new(std::io::ErrorKind::InvalidData, too_large.clone());
+        assert_eq!(NanoServiceError::from(io_error), too_large);
+        let io_error = std::io::Error::other("malformed");
+        let error = NanoServiceError::from_io_error(io_error, NanoServiceErrorStatus::BadRequest);
+        assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_error_source() {
+        use std::error::Error;
+
+        let io_error = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "connection refused");
+        let error = NanoServiceError::from_source(io_error, NanoServiceErrorStatus::ServiceUnavailable);
+        assert_eq!(error.message, "connection refused");
+        let source = error.source().unwrap().downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(source.kind(), std::io::ErrorKind::ConnectionRefused);
+        assert!(error.clone().source().is_some());
+
+        // the source is not part of the wire format or equality
+        let plain = NanoServiceError::new("connection refused".to_string(), NanoServiceErrorStatus::ServiceUnavailable);
+        assert!(plain.source().is_none());
+        assert_eq!(error, plain);
+
+        let mut bytes = Vec::new();
+        error.serialize_revisioned(&mut bytes).unwrap();
+        let mut plain_bytes = Vec::new();
+        plain.serialize_revisioned(&mut plain_bytes).unwrap();
+        assert_eq!(bytes, plain_bytes);
+        assert!(NanoServiceError::deserialize_revisioned(&mut bytes.as_slice()).unwrap().source().is_none());
+        assert_eq!(bitcode::encode(&error), bitcode::encode(&plain));
+
+        let missing_file = std::fs::read_to_string("/nanoservices/missing/file").map_err(NanoServiceError::from);
+        assert!(missing_file.unwrap_err().source().unwrap().is::<std::io::Error>());
+    }
+
+    #[cfg(any(feature = "hyper", feature = "jwt"))]
+    #[test]
+    fn test_from_serde_json_error() {
+        fn parse(body: &str) -> Result<u32, NanoServiceError> {
+            Ok(serde_json::from_str(body)?)
+        }
+        assert_eq!(parse("12").unwrap(), 12);
+        assert_eq!(parse("not json").unwrap_err().status, NanoServiceErrorStatus::BadRequest);
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let cases = [
+            (NanoServiceErrorStatus::NotFound, false),
+            (NanoServiceErrorStatus::Forbidden, false),
+            (NanoServiceErrorStatus::Unknown, true),
+            (NanoServiceErrorStatus::BadRequest, false),
+            (NanoServiceErrorStatus::Conflict, false),
+            (NanoServiceErrorStatus::Unauthorized, false),
+            (NanoServiceErrorStatus::ContractNotSupported, false),
+            (NanoServiceErrorStatus::PayloadTooLarge, false),
+            (NanoServiceErrorStatus::TooManyRequests, true),
+            (NanoServiceErrorStatus::ServiceUnavailable, true),
+        ];
+        for (status, retryable) in cases {
+            let error = NanoServiceError::new("error".to_string(), status.clone());
+            assert_eq!(error.is_retryable(), retryable, "{:?}", status);
+        }
+    }
+
+    #[test]
+    fn test_same_status() {
+        let error = NanoServiceError::new(
+            "failed to connect: Connection refused (os error 111)".to_string(),
+            NanoServiceErrorStatus::Unknown
+        );
+        let other = NanoServiceError::new(
+            "failed to connect: Connection reset by peer (os error 104)".to_string(),
+            NanoServiceErrorStatus::Unknown
+        ).with_code("connection.failed");
+        assert_ne!(error, other);
+        assert!(error.same_status(&other));
+        assert!(other.same_status(&error));
+        assert!(error.matches_status(&NanoServiceErrorStatus::Unknown));
+
+        let not_found = NanoServiceError::new(
+            "failed to connect: Connection refused (os error 111)".to_string(),
+            NanoServiceErrorStatus::NotFound
+        );
+        assert!(!error.same_status(&not_found));
+        assert!(!not_found.matches_status(&NanoServiceErrorStatus::Unknown));
+    }
+
+    #[test]
+    fn test_error_classification() {
+        let cases = [
+            (NanoServiceErrorStatus::NotFound, 404, true),
+            (NanoServiceErrorStatus::Forbidden, 403, true),
+            (NanoServiceErrorStatus::Unknown, 500, false),
+            (NanoServiceErrorStatus::BadRequest, 400, true),
+            (NanoServiceErrorStatus::Conflict, 409, true),
+            (NanoServiceErrorStatus::Unauthorized, 401, true),
+            (NanoServiceErrorStatus::ContractNotSupported, 501, false),
+            (NanoServiceErrorStatus::PayloadTooLarge, 413, true),
+            (NanoServiceErrorStatus::TooManyRequests, 429, true),
+            (NanoServiceErrorStatus::ServiceUnavailable, 503, false),
+        ];
+        for (status, code, client_error) in cases {
+            assert_eq!(status.http_status(), code);
+            let error = NanoServiceError::new("error".to_string(), status.clone());
+            assert_eq!(error.is_client_error(), client_error, "{:?}", status);
+            assert_eq!(error.is_server_error(), !client_error, "{:?}", status);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_payload_too_large_mapping() {
+        use revision::Revisioned;
+
+        let error = NanoServiceError::new("too big".to_string(), NanoServiceErrorStatus::PayloadTooLarge);
+        let mut bytes = Vec::new();
+        error.serialize_revisioned(&mut bytes).unwrap();
+        assert_eq!(NanoServiceError::deserialize_revisioned(&mut bytes.as_slice()).unwrap(), error);
+        assert_eq!(NanoServiceErrorStatus::revision(), 3);
+
+        #[cfg(feature = "actix")]
+        assert_eq!(error.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+        #[cfg(feature = "rocket")]
+        assert_eq!(error.rocket_status(), Status::PayloadTooLarge);
+        #[cfg(feature = "hyper")]
+        assert_eq!(error.clone().into_hyper_response().status(), HyperStatusCode::PAYLOAD_TOO_LARGE);
+        #[cfg(feature = "axum")]
+        assert_eq!(error.into_response().status(), AxumStatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_backpressure_mapping() {
+        use revision::Revisioned;
+
+        for (status, code) in [
+            (NanoServiceErrorStatus::TooManyRequests, 429),
+            (NanoServiceErrorStatus::ServiceUnavailable, 503),
+        ] {
+            let error = NanoServiceError::new("slow down".to_string(), status);
+            let mut bytes = Vec::new();
+            error.serialize_revisioned(&mut bytes).unwrap();
+            assert_eq!(NanoServiceError::deserialize_revisioned(&mut bytes.as_slice()).unwrap(), error);
+
+            #[cfg(feature = "actix")]
+            assert_eq!(error.status_code().as_u16(), code);
+            #[cfg(feature = "rocket")]
+            assert_eq!(error.rocket_status().code, code);
+            #[cfg(feature = "hyper")]
+            assert_eq!(error.clone().into_hyper_response().status().as_u16(), code);
+            #[cfg(feature = "axum")]
+            assert_eq!(error.into_response().status().as_u16(), code);
+            #[cfg(not(any(feature = "actix", feature = "rocket", feature = "hyper", feature = "axum")))]
+            assert_eq!(error.status.http_status(), code);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_error_code() {
+        use revision::Revisioned;
+
+        let error = NanoServiceError::new("user 1 missing".to_string(), NanoServiceErrorStatus::NotFound);
+        assert_eq!(error.code, "");
+
+        let error = error.with_code("user.not_found");
+        assert_eq!(error.code, "user.not_found");
+        assert_eq!(error.message, "user 1 missing");
+
+        let mut bytes = Vec::new();
+        error.serialize_revisioned(&mut bytes).unwrap();
+        assert_eq!(NanoServiceError::deserialize_revisioned(&mut bytes.as_slice()).unwrap(), error);
+        assert_eq!(NanoServiceError::revision(), 2);
+
+        #[cfg(any(feature = "hyper", feature = "jwt"))]
+        {
+            // errors serialized before the code was added default to an empty code
+            let error: NanoServiceError = serde_json::from_str(
+                r#"{"message": "user 1 missing", "status": "NotFound"}"#
+            ).unwrap();
+            assert_eq!(error.code, "");
+        }
+
+        #[cfg(feature = "axum")]
+        {
+            let response = NanoServiceError::new("user 1 missing".to_string(), NanoServiceErrorStatus::NotFound)
+                .with_code("user.not_found")
+                .into_response();
+            let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+            let body = runtime.block_on(async {
+                axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()
+            });
+            #[cfg(not(feature = "structured-errors"))]
+            assert_eq!(&body[..], br#"{"message":"user 1 missing","code":"user.not_found"}"#);
+            #[cfg(feature = "structured-errors")]
+            assert_eq!(&body[..], br#"{"message":"user 1 missing","status":"NotFound","code":"user.not_found"}"#);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_binary_layouts_match_before_code() {
+        // the layout of the error before the code was added, as older peers encode and decode it
+        #[derive(Debug, PartialEq, Serialize, Deserialize, Encode, Decode)]
+        struct OldNanoServiceError {
+            message: String,
+            status: NanoServiceErrorStatus,
+        }
+
+        let old = OldNanoServiceError {
+            message: "user 1 missing".to_string(),
+            status: NanoServiceErrorStatus::NotFound
+        };
+        let error = NanoServiceError::new("user 1 missing".to_string(), NanoServiceErrorStatus::NotFound)
+            .with_code("user.not_found");
+        let decoded_error = NanoServiceError::new("user 1 missing".to_string(), NanoServiceErrorStatus::NotFound);
+
+        let old_bytes = bitcode::encode(&old);
+        assert_eq!(bitcode::decode::<NanoServiceError>(&old_bytes).unwrap(), decoded_error);
+        assert_eq!(bitcode::encode(&error), old_bytes);
+        assert_eq!(bitcode::decode::<OldNanoServiceError>(&bitcode::encode(&error)).unwrap(), old);
+
+        #[cfg(feature = "networking")]
+        {
+            let old_bytes = bincode::serialize(&old).unwrap();
+            assert_eq!(bincode::deserialize::<NanoServiceError>(&old_bytes).unwrap(), decoded_error);
+            assert_eq!(bincode::serialize(&error).unwrap(), old_bytes);
+            assert_eq!(bincode::deserialize::<OldNanoServiceError>(&bincode::serialize(&error).unwrap()).unwrap(), old);
+        }
+
+        #[cfg(feature = "messagepack")]
+        {
+            let old_bytes = rmp_serde::to_vec(&old).unwrap();
+            assert_eq!(rmp_serde::from_slice::<NanoServiceError>(&old_bytes).unwrap(), decoded_error);
+            assert_eq!(rmp_serde::to_vec(&error).unwrap(), old_bytes);
+        }
+
+        #[cfg(any(feature = "hyper", feature = "jwt"))]
+        {
+            // the self-describing formats carry the code
+            let json = serde_json::to_string(&error).unwrap();
+            assert_eq!(json, r#"{"message":"user 1 missing","status":"NotFound","code":"user.not_found"}"#);
+            assert_eq!(serde_json::from_str::<NanoServiceError>(&json).unwrap(), error);
+        }
+    }
+
+    #[cfg(feature = "actix")]
+    #[test]
+    fn test_actix_response() {
+        let error = NanoServiceError::new("resource missing".to_string(), NanoServiceErrorStatus::NotFound)
+            .with_code("resource.not_found");
+        let response = error.error_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let body = runtime.block_on(async {
+            actix_web::body::to_bytes(response.into_body()).await.unwrap()
+        });
+        #[cfg(not(feature = "structured-errors"))]
+        assert_eq!(&body[..], br#"{"message":"resource missing","code":"resource.not_found"}"#);
+        #[cfg(feature = "structured-errors")]
+        assert_eq!(&body[..], br#"{"message":"resource missing","status":"NotFound","code":"resource.not_found"}"#);
+    }
+
+    #[cfg(feature = "tonic")]
+    #[test]
+    fn test_tonic_status() {
+        let cases = [
+            (NanoServiceErrorStatus::NotFound, TonicCode::NotFound),
+            (NanoServiceErrorStatus::Forbidden, TonicCode::PermissionDenied),
+            (NanoServiceErrorStatus::Unknown, TonicCode::Internal),
+            (NanoServiceErrorStatus::BadRequest, TonicCode::InvalidArgument),
+            (NanoServiceErrorStatus::Conflict, TonicCode::AlreadyExists),
+            (NanoServiceErrorStatus::Unauthorized, TonicCode::Unauthenticated),
+            (NanoServiceErrorStatus::ContractNotSupported, TonicCode::Unimplemented),
+            (NanoServiceErrorStatus::PayloadTooLarge, TonicCode::ResourceExhausted),
+            (NanoServiceErrorStatus::TooManyRequests, TonicCode::ResourceExhausted),
+            (NanoServiceErrorStatus::ServiceUnavailable, TonicCode::Unavailable),
+        ];
+        for (status, code) in cases {
+            let error = NanoServiceError::new("error".to_string(), status.clone());
+            let tonic_status = TonicStatus::from(error.clone());
+            assert_eq!(tonic_status.code(), code, "{:?}", status);
+            assert_eq!(tonic_status.message(), "error");
+
+            // every status apart from `PayloadTooLarge` survives the round trip
+            if status != NanoServiceErrorStatus::PayloadTooLarge {
+                assert_eq!(NanoServiceError::from_status(&tonic_status), error);
+            }
+        }
+
+        let error = NanoServiceError::from_status(&TonicStatus::new(TonicCode::DataLoss, "corrupted"));
+        assert_eq!(error.status, NanoServiceErrorStatus::Unknown);
+        assert_eq!(error.message, "corrupted");
+    }
+
+    #[cfg(feature = "hyper")]
+    #[test]
+    fn test_hyper_response() {
+        use http_body_util::BodyExt;
+
+        let error = NanoServiceError::new(
+            "resource \"missing\"".to_string(),
+            NanoServiceErrorStatus::NotFound
+        );
+        let response = error.into_hyper_response();
+        assert_eq!(response.status(), HyperStatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let body = runtime.block_on(async {
+            response.into_body().collect().await.unwrap().to_bytes()
+        });
+        #[cfg(not(feature = "structured-errors"))]
+        assert_eq!(&body[..], br#""resource \"missing\"""#);
+        #[cfg(feature = "structured-errors")]
+        assert_eq!(&body[..], br#"{"message":"resource \"missing\"","status":"NotFound","code":""}"#);
+    }
+
+    #[cfg(feature = "hyper")]
+    #[test]
+    fn test_fallback_hyper_response() {
+        let response = NanoServiceError::fallback_hyper_response();
+        assert_eq!(response.status(), HyperStatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/plain");
+    }
+
+    #[cfg(feature = "rocket")]
+    #[rocket::get("/json")]
+    fn rocket_json_error() -> Result<String, NanoServiceError> {
+        Err(NanoServiceError::new("resource missing".to_string(), NanoServiceErrorStatus::NotFound))
+    }
+
+    #[cfg(feature = "rocket")]
+    #[rocket::get("/plain")]
+    fn rocket_plain_error() -> Result<String, PlainTextNanoServiceError> {
+        Err(NanoServiceError::new("resource missing".to_string(), NanoServiceErrorStatus::NotFound).into())
+    }
+
+    #[cfg(feature = "rocket")]
+    #[test]
+    fn test_rocket_responses() {
+        use rocket::local::blocking::Client;
+
+        let rocket = rocket::build().mount("/", rocket::routes![rocket_json_error, rocket_plain_error]);
+        let client = Client::tracked(rocket).unwrap();
+
+        let response = client.get("/json").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        #[cfg(not(feature = "structured-errors"))]
+        assert_eq!(response.into_string().unwrap(), "\"resource missing\"");
+        #[cfg(feature = "structured-errors")]
+        assert_eq!(response.into_string().unwrap(), r#"{"message":"resource missing","status":"NotFound","code":""}"#);
+
+        let response = client.get("/plain").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.content_type(), Some(ContentType::Plain));
+        assert_eq!(response.into_string().unwrap(), "resource missing");
+    }
+
+    #[cfg(feature = "axum")]
+    #[test]
+    fn test_axum_body_excludes_status() {
+        let error = NanoServiceError::new(
+            "resource missing".to_string(),
+            NanoServiceErrorStatus::NotFound
+        );
+        let response = error.into_response();
+        assert_eq!(response.status(), AxumStatusCode::NOT_FOUND);
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let body = runtime.block_on(async {
+            axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()
+        });
+        #[cfg(not(feature = "structured-errors"))]
+        assert_eq!(&body[..], b"\"resource missing\"");
+        #[cfg(feature = "structured-errors")]
+        assert_eq!(&body[..], br#"{"message":"resource missing","status":"NotFound","code":""}"#);
+    }
+
+}
@@ -0,0 +1,137 @@
+//! Defines feature flags read from a config source through the `GetConfigVariable` trait. A flag named
+//! `new-checkout` is read from the `FLAG_NEW_CHECKOUT` variable as `true` or `false`, and can be rolled out to a
+//! percentage of users with the `FLAG_NEW_CHECKOUT_ROLLOUT` variable set to a number from 0 to 100. A user lands in
+//! the same bucket every time, so the flag does not flip between requests.
+//!
+//! ```rust
+//! use nanoservices_utils::config::EnvConfig;
+//! use nanoservices_utils::feature_flags::FeatureFlags;
+//!
+//! if FeatureFlags::<EnvConfig>::is_enabled_for("new-checkout", 42) {
+//!     // serve the new checkout
+//! }
+//! ```
+use std::fmt::Display;
+use std::marker::PhantomData;
+use crate::config::GetConfigVariable;
+
+
+/// Evaluates feature flags read from the config source `T`.
+pub struct FeatureFlags<T: GetConfigVariable> {
+    phantom: PhantomData<T>,
+}
+
+impl<T: GetConfigVariable> FeatureFlags<T> {
+
+    /// Checks if a flag is turned on for everyone. A flag that is not set or is not `true` is off.
+    ///
+    /// # Arguments
+    /// * `flag` - The name of the flag.
+    ///
+    /// # Returns
+    /// * `bool` - Whether the flag is turned on.
+    pub fn is_enabled(flag: &str) -> bool {
+        T::get_config_variable(Self::variable_name(flag, ""))
+            .map(|value| value.trim().eq_ignore_ascii_case("true"))
+            .unwrap_or(false)
+    }
+
+    /// Checks if a flag is turned on for a user. If the flag has a rollout percentage the user is in the rollout
+    /// when their bucket, worked out from a hash of the flag and the user ID, is below the percentage. Otherwise
+    /// the flag is on for the user if it is on for everyone.
+    ///
+    /// # Arguments
+    /// * `flag` - The name of the flag.
+    /// * `user_id` - The ID of the user.
+    ///
+    /// # Returns
+    /// * `bool` - Whether the flag is turned on for the user.
+    pub fn is_enabled_for<U: Display>(flag: &str, user_id: U) -> bool {
+        let percentage = match T::get_config_variable(Self::variable_name(flag, "_ROLLOUT")) {
+            Ok(percentage) => percentage,
+            Err(_) => return Self::is_enabled(flag)
+        };
+        match percentage.trim().parse::<u64>() {
+            Ok(percentage) => rollout_bucket(flag, &user_id.to_string()) < percentage,
+            Err(_) => false
+        }
+    }
+
+    /// Builds the name of the config variable for a flag, `new-checkout` becoming `FLAG_NEW_CHECKOUT`.
+    ///
+    /// # Arguments
+    /// * `flag` - The name of the flag.
+    /// * `suffix` - Appended to the name of the variable.
+    ///
+    /// # Returns
+    /// * `String` - The name of the config variable.
+    fn variable_name(flag: &str, suffix: &str) -> String {
+        let name: String = flag.chars().map(|c| {
+            if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }
+        }).collect();
+        format!("FLAG_{}{}", name, suffix)
+    }
+}
+
+
+/// Places a user in a bucket from 0 to 99 for a flag. FNV-1a is used rather than the standard library hasher as
+/// its output is the same across builds and processes. The flag is part of the hash so the same users are not
+/// always the first to get every flag.
+///
+/// # Arguments
+/// * `flag` - The name of the flag.
+/// * `user_id` - The ID of the user.
+///
+/// # Returns
+/// * `u64` - The bucket of the user.
+fn rollout_bucket(flag: &str, user_id: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in flag.bytes().chain(std::iter::once(b':')).chain(user_id.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash % 100
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::errors::NanoServiceError;
+    use crate::config::config_variable_not_found;
+
+    struct FlagConfig;
+
+    impl GetConfigVariable for FlagConfig {
+        fn get_config_variable(variable: String) -> Result<String, NanoServiceError> {
+            match variable.as_str() {
+                "FLAG_NEW_CHECKOUT" => Ok("true".to_string()),
+                "FLAG_DARK_MODE" => Ok("false".to_string()),
+                "FLAG_DARK_MODE_ROLLOUT" => Ok("50".to_string()),
+                _ => Err(config_variable_not_found::<Self>(&variable))
+            }
+        }
+    }
+
+    #[test]
+    fn test_boolean_flag() {
+        assert!(FeatureFlags::<FlagConfig>::is_enabled("new-checkout"));
+        assert!(FeatureFlags::<FlagConfig>::is_enabled_for("new-checkout", 7));
+        assert!(!FeatureFlags::<FlagConfig>::is_enabled("dark-mode"));
+        assert!(!FeatureFlags::<FlagConfig>::is_enabled("missing"));
+        assert!(!FeatureFlags::<FlagConfig>::is_enabled_for("missing", 7));
+    }
+
+    #[test]
+    fn test_percentage_rollout() {
+        let first: Vec<bool> = (0..1000).map(|id| FeatureFlags::<FlagConfig>::is_enabled_for("dark-mode", id)).collect();
+        let second: Vec<bool> = (0..1000).map(|id| FeatureFlags::<FlagConfig>::is_enabled_for("dark-mode", id)).collect();
+        assert_eq!(first, second);
+
+        let enabled = first.iter().filter(|enabled| **enabled).count();
+        assert!((400..600).contains(&enabled), "{} of 1000 users enabled", enabled);
+        assert_eq!(rollout_bucket("dark-mode", "1"), rollout_bucket("dark-mode", "1"));
+    }
+
+}
@@ -1,17 +1,35 @@
 //! This crate is a basic utils crate that helps glue nanoservices together.
+//!
+//! With the default `std` feature disabled only the `errors` module is built, without `std`, so the errors can be
+//! shared with `no_std` crates (e.g. embedded or WASM without `std`).
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+// lets the code generated by the derive macros refer to this crate by name in the tests
+#[cfg(test)]
+extern crate self as nanoservices_utils;
+
 #[allow(dead_code)]
 pub mod errors;
 
 #[cfg(feature = "jwt")]
 #[allow(dead_code)]
 pub mod jwt;
+#[cfg(feature = "std")]
 #[allow(dead_code)]
 pub mod config;
+#[cfg(feature = "std")]
+#[allow(dead_code)]
+pub mod feature_flags;
 
 #[cfg(feature = "networking")]
 #[allow(dead_code)]
 pub mod networking;
 
+#[cfg(feature = "validate")]
+#[allow(dead_code)]
+pub mod validation;
+
 
 #[cfg(feature = "dal")]
 #[allow(dead_code)]
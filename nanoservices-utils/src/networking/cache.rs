@@ -0,0 +1,263 @@
+//! Defines a bounded cache for the responses of read-heavy contract handlers. Responses are keyed by the
+//! serialized bytes of the inbound contract so identical contracts are only handled once until the cached
+//! response expires or is evicted.
+use serde::{Serialize, de::DeserializeOwned};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+
+
+/// A response that has been stored in the cache.
+///
+/// # Fields
+/// * `response` - The serialized response of the handler.
+/// * `inserted_at` - When the response was stored, used for TTL eviction.
+struct CachedResponse {
+    response: Vec<u8>,
+    inserted_at: Instant,
+}
+
+/// The entries of the cache.
+///
+/// # Fields
+/// * `responses` - The cached responses keyed by the serialized contract.
+/// * `order` - The keys ordered from least to most recently used.
+struct CacheEntries {
+    responses: HashMap<Vec<u8>, CachedResponse>,
+    order: VecDeque<Vec<u8>>,
+}
+
+impl CacheEntries {
+
+    fn touch(&mut self, key: &[u8]) {
+        if let Some(position) = self.order.iter().position(|k| k.as_slice() == key) {
+            if let Some(k) = self.order.remove(position) {
+                self.order.push_back(k);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.responses.remove(key);
+        self.order.retain(|k| k.as_slice() != key);
+    }
+}
+
+
+/// A least recently used cache with TTL eviction for the responses of a contract handler.
+///
+/// # Fields
+/// * `capacity` - The maximum number of responses held by the cache.
+/// * `ttl` - How long a response is served from the cache before the handler is called again.
+/// * `entries` - The cached responses.
+pub struct ContractCache<H> {
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<CacheEntries>,
+    phantom: PhantomData<fn() -> H>,
+}
+
+impl<H: Serialize + DeserializeOwned> ContractCache<H> {
+
+    /// Constructs a new empty `ContractCache`.
+    ///
+    /// # Arguments
+    /// * `capacity` - The maximum number of responses held by the cache.
+    /// * `ttl` - How long a response is served from the cache.
+    ///
+    /// # Returns
+    /// * `ContractCache<H>` - The new cache.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        ContractCache {
+            capacity,
+            ttl,
+            entries: Mutex::new(CacheEntries {
+                responses: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the cached response for the contract, or calls the handler and caches its response if there is
+    /// no fresh response for the contract. Errors returned by the handler are not cached.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to be handled.
+    /// * `handler` - The handler that is called on a cache miss (e.g. the function made by `register_contract_routes!`).
+    ///
+    /// # Returns
+    /// * `Result<H, NanoServiceError>` - The response of the handler.
+    pub async fn handle<F, Fut>(&self, contract: H, handler: F) -> Result<H, NanoServiceError>
+    where
+        F: FnOnce(H) -> Fut,
+        Fut: Future<Output = Result<H, NanoServiceError>>,
+    {
+        let key = bincode::serialize(&contract).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })?;
+        if let Some(response) = self.get(&key) {
+            return bincode::deserialize::<H>(&response).map_err(|e| {
+                NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+            })
+        }
+        let response = handler(contract).await?;
+        let response_bytes = bincode::serialize(&response).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+        })?;
+        self.insert(key, response_bytes);
+        Ok(response)
+    }
+
+    /// Gets the number of responses held by the cache (including expired responses that have not been evicted yet).
+    ///
+    /// # Returns
+    /// * `usize` - The number of responses in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().responses.len()
+    }
+
+    /// Checks if the cache is empty.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if there are no responses in the cache.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all the responses from the cache.
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.responses.clear();
+        entries.order.clear();
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let mut entries = self.entries.lock().unwrap();
+        let expired = match entries.responses.get(key) {
+            Some(cached) => cached.inserted_at.elapsed() >= self.ttl,
+            None => return None,
+        };
+        if expired {
+            entries.remove(key);
+            return None
+        }
+        entries.touch(key);
+        entries.responses.get(key).map(|cached| cached.response.clone())
+    }
+
+    fn insert(&self, key: Vec<u8>, response: Vec<u8>) {
+        if self.capacity == 0 {
+            return
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(&key);
+        while entries.responses.len() >= self.capacity {
+            match entries.order.pop_front() {
+                Some(oldest) => {
+                    entries.responses.remove(&oldest);
+                },
+                None => break,
+            }
+        }
+        entries.order.push_back(key.clone());
+        entries.responses.insert(key, CachedResponse {
+            response,
+            inserted_at: Instant::now(),
+        });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde::Deserialize;
+    use crate::create_contract_handler;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::runtime::Builder;
+
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct ContractOne {
+        pub value: i32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct ContractTwo;
+
+    create_contract_handler!(
+        ContractHandler,
+        ContractOne,
+        ContractTwo
+    );
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    static EVICTION_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    async fn handle_contract(contract: ContractHandler) -> Result<ContractHandler, NanoServiceError> {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        let mut inner = contract.ContractOne()?;
+        inner.value += 1;
+        Ok(ContractHandler::ContractOne(inner))
+    }
+
+    async fn handle_contract_for_eviction(contract: ContractHandler) -> Result<ContractHandler, NanoServiceError> {
+        EVICTION_CALLS.fetch_add(1, Ordering::SeqCst);
+        Ok(contract)
+    }
+
+    #[test]
+    fn test_cache_until_ttl_expiry() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let cache = ContractCache::<ContractHandler>::new(10, Duration::from_millis(200));
+
+            for _ in 0..3 {
+                let contract = ContractHandler::ContractOne(ContractOne { value: 1 });
+                let response = cache.handle(contract, handle_contract).await.unwrap();
+                assert_eq!(response, ContractHandler::ContractOne(ContractOne { value: 2 }));
+            }
+            assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+            assert_eq!(cache.len(), 1);
+
+            tokio::time::sleep(Duration::from_millis(250)).await;
+
+            let contract = ContractHandler::ContractOne(ContractOne { value: 1 });
+            let response = cache.handle(contract, handle_contract).await.unwrap();
+            assert_eq!(response, ContractHandler::ContractOne(ContractOne { value: 2 }));
+            assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+        });
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let cache = ContractCache::<ContractHandler>::new(2, Duration::from_secs(60));
+
+            for value in [1, 2, 1, 3, 1, 2] {
+                let contract = ContractHandler::ContractOne(ContractOne { value });
+                cache.handle(contract, handle_contract_for_eviction).await.unwrap();
+            }
+            // 1, 2 and 3 are all misses, 1 stays hot, so 2 is evicted when 3 is inserted
+            assert_eq!(EVICTION_CALLS.load(Ordering::SeqCst), 4);
+            assert_eq!(cache.len(), 2);
+        });
+    }
+
+}
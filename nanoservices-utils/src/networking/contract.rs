@@ -37,22 +37,98 @@
 //! ```
 //! This enables you to pass one of multiple contracts from one handler to another over a network. A `NanoserviceError` is
 //! also attached to the handler so errors raw errors can be passed around as well.
+//!
+//! By default a variant wraps the type with the same name. Use `Variant(TypePath)` when the variant name differs
+//! from the type or the type lives in another module, e.g. `create_contract_handler!(Handler, Login(auth::LoginRequest))`.
+//! The wire ref is always built from the variant name.
+//!
+//! With a web framework feature enabled, `into_http_response` converts the handler into the HTTP response of the
+//! framework, the contract as a JSON body or the error through the framework's error mapping (see
+//! `networking::http_response`).
+//!
+//! Every contract type is checked when the handler is defined, so a type that is missing `Serialize`/`Deserialize`
+//! (or `Encode`/`Decode` for `create_bitcode_contract_handler`) fails with an error naming the type instead of an
+//! error inside the generated code.
+//!
+//! A handler that already has the concrete contract it is responding with can skip wrapping it in the enum by
+//! calling `ContractHandler::to_contract_bytes_of(&contract)`, which gives the wire ref and the same bytes as
+//! `to_contract_bytes`. If a type is wrapped by more than one variant, the first variant is used.
+//!
+//! Wire refs are built in `WireRefFormat::CURRENT` and `from_contract_bytes` accepts a ref in any known format, so
+//! `contractone_contract` and `v1:contractone` both decode to `ContractOne` (see `networking::wire_ref`). Use
+//! `to_string_ref_in` to build a ref in a specific format.
+//!
+//! Contracts can also be sent without a separate wire ref using `to_self_describing_bytes` and
+//! `ContractHandler::try_from(bytes)`. The first 4 bytes are the big endian `internal_index` of the variant
+//! (0 for `NanoServiceError`) followed by the contract bytes.
+
+/// Resolves the type wrapped by a variant of a contract handler. This is used by the contract handler macros and
+/// is not part of the public API.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __contract_type {
+    ($variant:ident) => { $variant };
+    ($variant:ident, $contract_type:ty) => { $contract_type };
+}
+
+/// Implemented for every type that can be wrapped by a `create_contract_handler` variant. This is used by the
+/// contract handler macros to name the contract type when it is missing a trait and is not part of the public API.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can not be used as a contract as it does not implement `Serialize` and `Deserialize`",
+    label = "`{Self}` is used as a contract here",
+    note = "derive `Serialize` and `Deserialize` for `{Self}` to use it in `create_contract_handler`"
+)]
+pub trait SerdeContract {}
+
+impl<T: serde::Serialize + serde::de::DeserializeOwned> SerdeContract for T {}
+
+/// Implemented for every type that can be wrapped by a `create_bitcode_contract_handler` variant. This is used by
+/// the contract handler macros to name the contract type when it is missing a trait and is not part of the public API.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can not be used as a contract as it does not implement `Encode` and `Decode`",
+    label = "`{Self}` is used as a contract here",
+    note = "derive `Encode` and `Decode` for `{Self}` to use it in `create_bitcode_contract_handler`"
+)]
+pub trait BitcodeContract {}
+
+impl<T: bitcode::Encode + bitcode::DecodeOwned> BitcodeContract for T {}
+
+/// Checks that every contract type implements the helper trait of a contract handler macro so a missing trait fails
+/// with one error naming the contract type. This is used by the contract handler macros and is not part of the
+/// public API.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __assert_contract_bounds {
+    ($bound:ident; $( $contract_type:ty ),+) => {
+        const _: fn() = || {
+            fn assert_bounds<T: $crate::networking::contract::$bound>() {}
+            $( assert_bounds::<$contract_type>(); )+
+        };
+    };
+}
+
 #[macro_export]
 macro_rules! create_contract_handler {
-    ($enum_name:ident, $( $variant:ident ),*) => {
+    ($enum_name:ident, $( $variant:ident $( ( $contract_type:ty ) )? ),*) => {
+        // checked before anything else is generated so the first error names the contract type missing a trait
+        $crate::__assert_contract_bounds!(SerdeContract; $( $crate::__contract_type!($variant $(, $contract_type)?) ),+);
+
         #[derive(Debug, PartialEq, Serialize, Deserialize)]
         pub enum $enum_name {
-            $( $variant($variant), )+
+            $( $variant($crate::__contract_type!($variant $(, $contract_type)?)), )+
             NanoServiceError(NanoServiceError)
         }
 
         impl $enum_name {
             $(
                 #[allow(non_snake_case)]
-                pub fn $variant(self) -> Result<$variant, NanoServiceError> {
+                pub fn $variant(self) -> Result<$crate::__contract_type!($variant $(, $contract_type)?), NanoServiceError> {
                     match self {
                         $enum_name::$variant(inner) => Ok(inner),
                         $enum_name::NanoServiceError(inner) => Err(inner),
+                        #[allow(unreachable_patterns)]
                         _ => Err(NanoServiceError::new(
                                 format!("Expected variant: {}", stringify!($variant)),
                                 NanoServiceErrorStatus::BadRequest
@@ -74,27 +150,85 @@ macro_rules! create_contract_handler {
                 }
             }
 
+            pub fn dispatch_result(result: Result<$enum_name, NanoServiceError>) -> $enum_name {
+                match result {
+                    Ok(contract) => contract,
+                    Err(error) => $enum_name::NanoServiceError(error),
+                }
+            }
+
+            pub fn into_http_response<R>(self) -> R
+            where
+                R: $crate::networking::http_response::ContractHttpResponse
+            {
+                match self {
+                    $(
+                        $enum_name::$variant(contract) => R::from_contract(contract),
+                    )+
+                    $enum_name::NanoServiceError(error) => R::from_error(error),
+                }
+            }
+
             pub fn to_string_ref(&self) -> String {
+                self.to_string_ref_in($crate::networking::wire_ref::WireRefFormat::CURRENT)
+            }
+
+            pub fn to_string_ref_in(&self, format: $crate::networking::wire_ref::WireRefFormat) -> String {
                 match self {
                     $(
-                        $enum_name::$variant(_) => format!("{}_contract", stringify!($variant).to_lowercase()),
+                        $enum_name::$variant(_) => format.contract_ref(stringify!($variant)),
                     )+
-                    $enum_name::NanoServiceError(_) => "nanoService_error".to_string(),
+                    $enum_name::NanoServiceError(_) => format.error_ref(),
                 }
             }
 
+            pub fn wire_refs() -> Vec<String> {
+                let format = $crate::networking::wire_ref::WireRefFormat::CURRENT;
+                vec![
+                    $( format.contract_ref(stringify!($variant)), )+
+                ]
+            }
+
             pub fn from_contract_bytes(bytes: &[u8], string_ref: String) -> Result<$enum_name, NanoServiceError> {
+                let variant = $crate::networking::wire_ref::variant_of(&string_ref);
                 $(
-                    if string_ref == format!("{}_contract", stringify!($variant).to_lowercase()) {
-                        if let Ok(contract) = bincode::deserialize::<$variant>(bytes) {
-                            return Ok($enum_name::$variant(contract));
-                        }
+                    if variant.as_deref() == Some(stringify!($variant).to_lowercase().as_str()) {
+                        return $crate::networking::serialization::field_path::deserialize_bincode::<
+                            $crate::__contract_type!($variant $(, $contract_type)?)
+                        >(bytes).map($enum_name::$variant);
                     }
                 )+
-                return Err(NanoServiceError::new(
-                    "Failed to deserialize contract".to_string(),
+                if $crate::networking::wire_ref::is_error_ref(&string_ref) {
+                    return $crate::networking::serialization::field_path::deserialize_bincode::<NanoServiceError>(bytes)
+                        .map($enum_name::NanoServiceError);
+                }
+                return Err($crate::networking::wire_ref::contract_not_supported(&string_ref, &$enum_name::wire_refs()))
+            }
+
+            pub fn wire_ref_of<T: 'static>() -> Option<String> {
+                let type_id = std::any::TypeId::of::<T>();
+                let format = $crate::networking::wire_ref::WireRefFormat::CURRENT;
+                $(
+                    if type_id == std::any::TypeId::of::<$crate::__contract_type!($variant $(, $contract_type)?)>() {
+                        return Some(format.contract_ref(stringify!($variant)))
+                    }
+                )+
+                if type_id == std::any::TypeId::of::<NanoServiceError>() {
+                    return Some(format.error_ref())
+                }
+                None
+            }
+
+            pub fn to_contract_bytes_of<T: Serialize + 'static>(contract: &T) -> Result<(String, Vec<u8>), NanoServiceError> {
+                let string_ref = $enum_name::wire_ref_of::<T>().ok_or_else(|| NanoServiceError::new(
+                    format!("Contract not supported: {}", std::any::type_name::<T>()),
+                    NanoServiceErrorStatus::ContractNotSupported
+                ))?;
+                let bytes = bincode::serialize(contract).map_err(|e| NanoServiceError::new(
+                    e.to_string(),
                     NanoServiceErrorStatus::BadRequest
-                ))
+                ))?;
+                Ok((string_ref, bytes))
             }
 
             pub fn to_contract_bytes(&self) -> Result<Vec<u8>, NanoServiceError> {
@@ -118,6 +252,15 @@ macro_rules! create_contract_handler {
                 ))
             }
 
+            pub fn to_self_describing_bytes(&self) -> Result<Vec<u8>, NanoServiceError> {
+                let index = self.internal_index() as u32;
+                let contract_bytes = self.to_contract_bytes()?;
+                let mut bytes = Vec::with_capacity(4 + contract_bytes.len());
+                bytes.extend_from_slice(&index.to_be_bytes());
+                bytes.extend_from_slice(&contract_bytes);
+                Ok(bytes)
+            }
+
             pub fn internal_index(&self) -> i32 {
                 let mut index = 0;
                 $(
@@ -129,6 +272,33 @@ macro_rules! create_contract_handler {
                 return 0
             }
         }
+
+        impl TryFrom<&[u8]> for $enum_name {
+            type Error = NanoServiceError;
+
+            fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+                if bytes.len() < 4 {
+                    return Err(NanoServiceError::new(
+                        "Self describing contract is missing the variant index".to_string(),
+                        NanoServiceErrorStatus::BadRequest
+                    ))
+                }
+                let (index_bytes, contract_bytes) = bytes.split_at(4);
+                let index = u32::from_be_bytes([index_bytes[0], index_bytes[1], index_bytes[2], index_bytes[3]]) as usize;
+                if index == 0 {
+                    return bincode::deserialize::<NanoServiceError>(contract_bytes)
+                        .map($enum_name::NanoServiceError)
+                        .map_err(|e| NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest))
+                }
+                match $enum_name::wire_refs().get(index - 1) {
+                    Some(string_ref) => $enum_name::from_contract_bytes(contract_bytes, string_ref.clone()),
+                    None => Err(NanoServiceError::new(
+                        format!("Contract index not supported: {}", index),
+                        NanoServiceErrorStatus::ContractNotSupported
+                    ))
+                }
+            }
+        }
     }
 }
 
@@ -136,20 +306,24 @@ macro_rules! create_contract_handler {
 //         to reduce code duplication
 #[macro_export]
 macro_rules! create_bitcode_contract_handler {
-    ($enum_name:ident, $( $variant:ident ),*) => {
+    ($enum_name:ident, $( $variant:ident $( ( $contract_type:ty ) )? ),*) => {
+        // checked before anything else is generated so the first error names the contract type missing a trait
+        $crate::__assert_contract_bounds!(BitcodeContract; $( $crate::__contract_type!($variant $(, $contract_type)?) ),+);
+
         #[derive(Debug, PartialEq, Encode, Decode)]
         pub enum $enum_name {
-            $( $variant($variant), )+
+            $( $variant($crate::__contract_type!($variant $(, $contract_type)?)), )+
             NanoServiceError(NanoServiceError)
         }
 
         impl $enum_name {
             $(
                 #[allow(non_snake_case)]
-                pub fn $variant(self) -> Result<$variant, NanoServiceError> {
+                pub fn $variant(self) -> Result<$crate::__contract_type!($variant $(, $contract_type)?), NanoServiceError> {
                     match self {
                         $enum_name::$variant(inner) => Ok(inner),
                         $enum_name::NanoServiceError(inner) => Err(inner),
+                        #[allow(unreachable_patterns)]
                         _ => Err(NanoServiceError::new(
                                 format!("Expected variant: {}", stringify!($variant)),
                                 NanoServiceErrorStatus::BadRequest
@@ -171,48 +345,87 @@ macro_rules! create_bitcode_contract_handler {
                 }
             }
 
+            pub fn dispatch_result(result: Result<$enum_name, NanoServiceError>) -> $enum_name {
+                match result {
+                    Ok(contract) => contract,
+                    Err(error) => $enum_name::NanoServiceError(error),
+                }
+            }
+
             pub fn to_string_ref(&self) -> String {
+                self.to_string_ref_in($crate::networking::wire_ref::WireRefFormat::CURRENT)
+            }
+
+            pub fn to_string_ref_in(&self, format: $crate::networking::wire_ref::WireRefFormat) -> String {
                 match self {
                     $(
-                        $enum_name::$variant(_) => format!("{}_contract", stringify!($variant).to_lowercase()),
+                        $enum_name::$variant(_) => format.contract_ref(stringify!($variant)),
                     )+
-                    $enum_name::NanoServiceError(_) => "nanoService_error".to_string(),
+                    $enum_name::NanoServiceError(_) => format.error_ref(),
                 }
             }
 
+            pub fn wire_refs() -> Vec<String> {
+                let format = $crate::networking::wire_ref::WireRefFormat::CURRENT;
+                vec![
+                    $( format.contract_ref(stringify!($variant)), )+
+                ]
+            }
+
             pub fn from_contract_bytes(bytes: &[u8], string_ref: String) -> Result<$enum_name, NanoServiceError> {
+                let variant = $crate::networking::wire_ref::variant_of(&string_ref);
                 $(
-                    if string_ref == format!("{}_contract", stringify!($variant).to_lowercase()) {
-                        if let Ok(contract) = bincode::deserialize::<$variant>(bytes) {
-                            return Ok($enum_name::$variant(contract));
-                        }
+                    if variant.as_deref() == Some(stringify!($variant).to_lowercase().as_str()) {
+                        return $crate::networking::serialization::field_path::decode_bitcode::<
+                            $crate::__contract_type!($variant $(, $contract_type)?)
+                        >(bytes).map($enum_name::$variant);
                     }
                 )+
-                return Err(NanoServiceError::new(
-                    "Failed to deserialize contract".to_string(),
-                    NanoServiceErrorStatus::BadRequest
-                ))
+                if $crate::networking::wire_ref::is_error_ref(&string_ref) {
+                    return $crate::networking::serialization::field_path::decode_bitcode::<NanoServiceError>(bytes)
+                        .map($enum_name::NanoServiceError);
+                }
+                return Err($crate::networking::wire_ref::contract_not_supported(&string_ref, &$enum_name::wire_refs()))
+            }
+
+            pub fn wire_ref_of<T: 'static>() -> Option<String> {
+                let type_id = std::any::TypeId::of::<T>();
+                let format = $crate::networking::wire_ref::WireRefFormat::CURRENT;
+                $(
+                    if type_id == std::any::TypeId::of::<$crate::__contract_type!($variant $(, $contract_type)?)>() {
+                        return Some(format.contract_ref(stringify!($variant)))
+                    }
+                )+
+                if type_id == std::any::TypeId::of::<NanoServiceError>() {
+                    return Some(format.error_ref())
+                }
+                None
+            }
+
+            pub fn to_contract_bytes_of<T: Encode + 'static>(contract: &T) -> Result<(String, Vec<u8>), NanoServiceError> {
+                let string_ref = $enum_name::wire_ref_of::<T>().ok_or_else(|| NanoServiceError::new(
+                    format!("Contract not supported: {}", std::any::type_name::<T>()),
+                    NanoServiceErrorStatus::ContractNotSupported
+                ))?;
+                Ok((string_ref, bitcode::encode(contract)))
             }
 
             pub fn to_contract_bytes(&self) -> Result<Vec<u8>, NanoServiceError> {
                 match self {
                     $(
-                        $enum_name::$variant(contract) => {
-                            if let Ok(bytes) = bincode::serialize(contract) {
-                                return Ok(bytes)
-                            }
-                        }
+                        $enum_name::$variant(contract) => Ok(bitcode::encode(contract)),
                     )+
-                    $enum_name::NanoServiceError(error) => {
-                        if let Ok(bytes) = bincode::serialize(error) {
-                            return Ok(bytes)
-                        }
-                    }
+                    $enum_name::NanoServiceError(error) => Ok(bitcode::encode(error)),
                 }
-                return Err(NanoServiceError::new(
-                    "Failed to serialize contract".to_string(),
-                    NanoServiceErrorStatus::BadRequest
-                ))
+            }
+
+            pub fn to_self_describing_bytes(&self) -> Result<Vec<u8>, NanoServiceError> {
+                let index = self.internal_index() as u32;
+                let contract_bytes = self.to_contract_bytes()?;
+                let mut bytes = Vec::with_capacity(4 + contract_bytes.len());
+                bytes.extend_from_slice(&index.to_be_bytes());
+                bytes.extend_from_slice(&contract_bytes);
+                Ok(bytes)
             }
 
             pub fn internal_index(&self) -> i32 {
@@ -226,6 +439,33 @@ macro_rules! create_bitcode_contract_handler {
                 return 0
             }
         }
+
+        impl TryFrom<&[u8]> for $enum_name {
+            type Error = NanoServiceError;
+
+            fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+                if bytes.len() < 4 {
+                    return Err(NanoServiceError::new(
+                        "Self describing contract is missing the variant index".to_string(),
+                        NanoServiceErrorStatus::BadRequest
+                    ))
+                }
+                let (index_bytes, contract_bytes) = bytes.split_at(4);
+                let index = u32::from_be_bytes([index_bytes[0], index_bytes[1], index_bytes[2], index_bytes[3]]) as usize;
+                if index == 0 {
+                    return bitcode::decode::<NanoServiceError>(contract_bytes)
+                        .map($enum_name::NanoServiceError)
+                        .map_err(|e| NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest))
+                }
+                match $enum_name::wire_refs().get(index - 1) {
+                    Some(string_ref) => $enum_name::from_contract_bytes(contract_bytes, string_ref.clone()),
+                    None => Err(NanoServiceError::new(
+                        format!("Contract index not supported: {}", index),
+                        NanoServiceErrorStatus::ContractNotSupported
+                    ))
+                }
+            }
+        }
     }
 }
 
@@ -235,6 +475,66 @@ mod tests {
 
     use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
     use serde::{Serialize, Deserialize};
+    use bitcode_kernel::BitcodeContractHandler;
+    use typed_kernel::{TypedContractHandler, BitcodeTypedContractHandler, auth};
+    use crate::networking::wire_ref::WireRefFormat;
+
+    mod typed_kernel {
+        use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+        use serde::{Serialize, Deserialize};
+        use bitcode::{Encode, Decode};
+
+        pub mod auth {
+            use super::*;
+
+            #[derive(Debug, PartialEq, Serialize, Deserialize, Encode, Decode)]
+            pub struct LoginRequest {
+                pub user: String,
+            }
+
+            #[derive(Debug, PartialEq, Serialize, Deserialize, Encode, Decode)]
+            pub struct LogoutRequest;
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize, Encode, Decode)]
+        pub struct Ping(pub u32);
+
+        create_contract_handler!(
+            TypedContractHandler,
+            Login(auth::LoginRequest),
+            Logout(auth::LogoutRequest),
+            Ping
+        );
+
+        create_bitcode_contract_handler!(
+            BitcodeTypedContractHandler,
+            Login(auth::LoginRequest),
+            Ping
+        );
+    }
+
+    mod bitcode_kernel {
+        use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+        use bitcode::{Encode, Decode};
+
+        #[derive(Debug, PartialEq, Encode, Decode)]
+        pub struct ContractOne {
+            pub name: String,
+        }
+
+        #[derive(Debug, PartialEq, Encode, Decode)]
+        pub struct ContractTwo;
+
+        #[derive(Debug, PartialEq, Encode, Decode)]
+        pub struct ContractThree;
+
+        create_bitcode_contract_handler!(
+            BitcodeContractHandler,
+            ContractOne,
+            ContractTwo,
+            ContractThree
+        );
+    }
 
 
     #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -280,6 +580,34 @@ mod tests {
         assert_eq!(contract_handler, ContractHandler::ContractThree(contract_three));
     }
 
+    #[test]
+    fn test_legacy_and_v1_refs_decode() {
+        let bytes = bincode::serialize(&ContractTwo).unwrap();
+        for string_ref in ["contracttwo_contract", "v1:contracttwo"] {
+            let contract_handler = ContractHandler::from_contract_bytes(&bytes, string_ref.to_string()).unwrap();
+            assert_eq!(contract_handler, ContractHandler::ContractTwo(ContractTwo));
+        }
+        let contract_handler = ContractHandler::ContractTwo(ContractTwo);
+        assert_eq!(contract_handler.to_string_ref_in(WireRefFormat::Legacy), "contracttwo_contract");
+        assert_eq!(contract_handler.to_string_ref_in(WireRefFormat::V1), "v1:contracttwo");
+
+        let error = ContractHandler::from_contract_bytes(&bytes, "v2:contracttwo".to_string()).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::ContractNotSupported);
+
+        let contract = bitcode_kernel::ContractOne { name: "John".to_string() };
+        let bytes = bitcode::encode(&contract);
+        for string_ref in ["contractone_contract", "v1:contractone"] {
+            let contract_handler = BitcodeContractHandler::from_contract_bytes(&bytes, string_ref.to_string()).unwrap();
+            assert_eq!(contract_handler.ContractOne().unwrap(), contract);
+        }
+        let error = NanoServiceError::new("Test error".to_string(), NanoServiceErrorStatus::BadRequest);
+        let bytes = bitcode::encode(&error);
+        for string_ref in ["nanoService_error", "v1:nanoserviceerror"] {
+            let contract_handler = BitcodeContractHandler::from_contract_bytes(&bytes, string_ref.to_string()).unwrap();
+            assert_eq!(contract_handler.NanoServiceError().unwrap(), error);
+        }
+    }
+
     #[test]
     fn test_to_contract_bytes() {
         let contract_one = ContractOne;
@@ -407,6 +735,66 @@ mod tests {
         assert_eq!(error.NanoServiceError().unwrap().status, NanoServiceErrorStatus::BadRequest);
     }
 
+    #[test]
+    fn test_dispatch_result() {
+        let error = NanoServiceError::new("Test error".to_string(), NanoServiceErrorStatus::BadRequest);
+
+        assert_eq!(
+            ContractHandler::dispatch_result(Ok(ContractHandler::ContractOne(ContractOne))),
+            ContractHandler::ContractOne(ContractOne)
+        );
+        assert_eq!(
+            ContractHandler::dispatch_result(Err(error.clone())),
+            ContractHandler::NanoServiceError(error.clone())
+        );
+        assert_eq!(
+            BitcodeContractHandler::dispatch_result(Err(error.clone())),
+            BitcodeContractHandler::NanoServiceError(error)
+        );
+    }
+
+    #[test]
+    fn test_wire_refs() {
+        assert_eq!(ContractHandler::wire_refs(), vec![
+            "contractone_contract".to_string(),
+            "contracttwo_contract".to_string(),
+            "contractthree_contract".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_error_contract_bytes() {
+        let error = NanoServiceError::new(
+            "Test error".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        );
+        let contract = ContractHandler::NanoServiceError(error.clone());
+        let bytes = contract.to_contract_bytes().unwrap();
+        assert_eq!(bincode::deserialize::<NanoServiceError>(&bytes).unwrap(), error);
+
+        let decoded = ContractHandler::from_contract_bytes(
+            &bytes,
+            contract.to_string_ref()
+        ).unwrap();
+        assert_eq!(decoded.NanoServiceError().unwrap(), error);
+    }
+
+    #[test]
+    fn test_from_contract_bytes_unsupported_ref() {
+        let bytes = bincode::serialize(&ContractOne).unwrap();
+        let error = ContractHandler::from_contract_bytes(
+            &bytes,
+            "contractfour_contract".to_string()
+        ).unwrap_err();
+
+        assert_eq!(error.status, NanoServiceErrorStatus::ContractNotSupported);
+        assert_eq!(
+            error.message,
+            "Contract not supported: contractfour_contract. \
+            Supported contracts: contractone_contract, contracttwo_contract, contractthree_contract"
+        );
+    }
+
     #[test]
     fn test_error_parsing_failure() {
         let contract = ContractHandler::ContractOne(ContractOne);
@@ -420,4 +808,310 @@ mod tests {
         assert_eq!(error.NanoServiceError().unwrap().status, NanoServiceErrorStatus::BadRequest);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_bitcode_contract_bytes() {
+        let contract = BitcodeContractHandler::ContractOne(bitcode_kernel::ContractOne {
+            name: "John".to_string(),
+        });
+        let bytes = contract.to_contract_bytes().unwrap();
+        assert_eq!(bytes, bitcode::encode(&bitcode_kernel::ContractOne { name: "John".to_string() }));
+
+        let decoded = BitcodeContractHandler::from_contract_bytes(
+            &bytes,
+            contract.to_string_ref()
+        ).unwrap();
+        assert_eq!(decoded, contract);
+    }
+
+    #[test]
+    fn test_bitcode_error_contract_bytes() {
+        let error = NanoServiceError::new(
+            "Test error".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        );
+        let contract = BitcodeContractHandler::NanoServiceError(error.clone());
+        let bytes = contract.to_contract_bytes().unwrap();
+        assert_eq!(bitcode::decode::<NanoServiceError>(&bytes).unwrap(), error);
+
+        let decoded = BitcodeContractHandler::from_contract_bytes(
+            &bytes,
+            contract.to_string_ref()
+        ).unwrap();
+        assert_eq!(decoded.NanoServiceError().unwrap(), error);
+    }
+
+    #[test]
+    fn test_bitcode_from_contract_bytes() {
+        let contract_one = bitcode_kernel::ContractOne { name: "John".to_string() };
+        let bytes = bitcode::encode(&contract_one);
+        let contract_handler = BitcodeContractHandler::from_contract_bytes(
+            &bytes,
+            "contractone_contract".to_string()
+        ).unwrap();
+        assert_eq!(contract_handler, BitcodeContractHandler::ContractOne(contract_one));
+
+        let bytes = bitcode::encode(&bitcode_kernel::ContractTwo);
+        let contract_handler = BitcodeContractHandler::from_contract_bytes(
+            &bytes,
+            "contracttwo_contract".to_string()
+        ).unwrap();
+        assert_eq!(contract_handler, BitcodeContractHandler::ContractTwo(bitcode_kernel::ContractTwo));
+
+        let bytes = bitcode::encode(&bitcode_kernel::ContractThree);
+        let contract_handler = BitcodeContractHandler::from_contract_bytes(
+            &bytes,
+            "contractthree_contract".to_string()
+        ).unwrap();
+        assert_eq!(contract_handler, BitcodeContractHandler::ContractThree(bitcode_kernel::ContractThree));
+    }
+
+    #[test]
+    fn test_bitcode_contract_handler_variants() {
+        let contract_one = BitcodeContractHandler::ContractOne(bitcode_kernel::ContractOne { name: "John".to_string() });
+        let contract_two = BitcodeContractHandler::ContractTwo(bitcode_kernel::ContractTwo);
+        let contract_three = BitcodeContractHandler::ContractThree(bitcode_kernel::ContractThree);
+
+        assert_eq!(contract_one.ContractOne().unwrap(), bitcode_kernel::ContractOne { name: "John".to_string() });
+        assert_eq!(contract_two.ContractTwo().unwrap(), bitcode_kernel::ContractTwo);
+        assert_eq!(contract_three.ContractThree().unwrap(), bitcode_kernel::ContractThree);
+    }
+
+    #[test]
+    fn test_bitcode_contract_handler_string_refs() {
+        let contract_one = BitcodeContractHandler::ContractOne(bitcode_kernel::ContractOne { name: "John".to_string() });
+        let contract_two = BitcodeContractHandler::ContractTwo(bitcode_kernel::ContractTwo);
+        let contract_three = BitcodeContractHandler::ContractThree(bitcode_kernel::ContractThree);
+        let nanoservice_error = BitcodeContractHandler::NanoServiceError(NanoServiceError::new(
+            "Test error".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ));
+
+        assert_eq!(contract_one.to_string_ref(), "contractone_contract");
+        assert_eq!(contract_two.to_string_ref(), "contracttwo_contract");
+        assert_eq!(contract_three.to_string_ref(), "contractthree_contract");
+        assert_eq!(nanoservice_error.to_string_ref(), "nanoService_error");
+        assert_eq!(BitcodeContractHandler::wire_refs(), ContractHandler::wire_refs());
+    }
+
+    #[test]
+    fn test_bitcode_contract_indexes() {
+        let contract_one = BitcodeContractHandler::ContractOne(bitcode_kernel::ContractOne { name: "John".to_string() });
+        let contract_two = BitcodeContractHandler::ContractTwo(bitcode_kernel::ContractTwo);
+        let contract_three = BitcodeContractHandler::ContractThree(bitcode_kernel::ContractThree);
+        let nanoservice_error = BitcodeContractHandler::NanoServiceError(NanoServiceError::new(
+            "Test error".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ));
+
+        assert_eq!(contract_one.internal_index(), 1);
+        assert_eq!(contract_two.internal_index(), 2);
+        assert_eq!(contract_three.internal_index(), 3);
+        assert_eq!(nanoservice_error.internal_index(), 0);
+    }
+
+    #[test]
+    fn test_bitcode_contract_serialization() {
+        let contract_one = BitcodeContractHandler::ContractOne(bitcode_kernel::ContractOne { name: "John".to_string() });
+        let contract_two = BitcodeContractHandler::ContractTwo(bitcode_kernel::ContractTwo);
+        let contract_three = BitcodeContractHandler::ContractThree(bitcode_kernel::ContractThree);
+        let nanoservice_error = BitcodeContractHandler::NanoServiceError(NanoServiceError::new(
+            "Test error".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ));
+
+        let deserialized_contract_one: BitcodeContractHandler = bitcode::decode(&bitcode::encode(&contract_one)).unwrap();
+        let deserialized_contract_two: BitcodeContractHandler = bitcode::decode(&bitcode::encode(&contract_two)).unwrap();
+        let deserialized_contract_three: BitcodeContractHandler = bitcode::decode(&bitcode::encode(&contract_three)).unwrap();
+        let deserialized_nanoservice_error: BitcodeContractHandler = bitcode::decode(&bitcode::encode(&nanoservice_error)).unwrap();
+
+        assert_eq!(contract_one, deserialized_contract_one);
+        assert_eq!(contract_two, deserialized_contract_two);
+        assert_eq!(contract_three, deserialized_contract_three);
+        assert_eq!(nanoservice_error, deserialized_nanoservice_error);
+    }
+
+    #[test]
+    fn test_bitcode_error_parsing() {
+        let contract = BitcodeContractHandler::ContractTwo(bitcode_kernel::ContractTwo);
+        let error = BitcodeContractHandler::NanoServiceError(NanoServiceError::new(
+            "Test error".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ));
+
+        assert_eq!(contract.ContractTwo().unwrap(), bitcode_kernel::ContractTwo);
+        assert_eq!(error.NanoServiceError().unwrap().status, NanoServiceErrorStatus::BadRequest);
+    }
+
+    #[test]
+    fn test_bitcode_from_contract_bytes_failures() {
+        let error = BitcodeContractHandler::from_contract_bytes(
+            &bitcode::encode(&bitcode_kernel::ContractTwo),
+            "contractfour_contract".to_string()
+        ).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::ContractNotSupported);
+        assert_eq!(
+            error.message,
+            "Contract not supported: contractfour_contract. \
+            Supported contracts: contractone_contract, contracttwo_contract, contractthree_contract"
+        );
+
+        let error = BitcodeContractHandler::from_contract_bytes(
+            &[],
+            "contractone_contract".to_string()
+        ).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+        assert!(error.message.contains("ContractOne`"), "{}", error.message);
+    }
+
+    #[test]
+    fn test_bitcode_error_parsing_failure() {
+        let contract = BitcodeContractHandler::ContractOne(bitcode_kernel::ContractOne { name: "John".to_string() });
+        let error = BitcodeContractHandler::NanoServiceError(NanoServiceError::new(
+            "Test error".to_string(),
+            NanoServiceErrorStatus::Conflict
+        ));
+
+        // below we are trying to parse contract two when it contains contract one
+        assert_eq!(contract.ContractTwo().unwrap_err().status, NanoServiceErrorStatus::BadRequest);
+        // the embedded error is returned when trying to parse a contract from an error
+        assert_eq!(error.ContractOne().unwrap_err().status, NanoServiceErrorStatus::Conflict);
+    }
+
+    #[test]
+    fn test_typed_variants() {
+        let login = TypedContractHandler::Login(auth::LoginRequest { user: "John".to_string() });
+        let logout = TypedContractHandler::Logout(auth::LogoutRequest);
+        let ping = TypedContractHandler::Ping(typed_kernel::Ping(3));
+
+        assert_eq!(login.to_string_ref(), "login_contract");
+        assert_eq!(logout.to_string_ref(), "logout_contract");
+        assert_eq!(ping.to_string_ref(), "ping_contract");
+        assert_eq!(logout.internal_index(), 2);
+
+        let bytes = login.to_contract_bytes().unwrap();
+        let decoded = TypedContractHandler::from_contract_bytes(&bytes, "login_contract".to_string()).unwrap();
+        assert_eq!(decoded.Login().unwrap(), auth::LoginRequest { user: "John".to_string() });
+        assert_eq!(ping.Ping().unwrap(), typed_kernel::Ping(3));
+        assert_eq!(logout.Login().unwrap_err().status, NanoServiceErrorStatus::BadRequest);
+    }
+
+    #[test]
+    fn test_to_contract_bytes_of() {
+        let login = auth::LoginRequest { user: "maxwell".to_string() };
+        let (string_ref, bytes) = TypedContractHandler::to_contract_bytes_of(&login).unwrap();
+
+        let handler = TypedContractHandler::Login(auth::LoginRequest { user: "maxwell".to_string() });
+        assert_eq!(string_ref, handler.to_string_ref());
+        assert_eq!(bytes, handler.to_contract_bytes().unwrap());
+        assert_eq!(TypedContractHandler::from_contract_bytes(&bytes, string_ref).unwrap(), handler);
+
+        let error = NanoServiceError::new("missing".to_string(), NanoServiceErrorStatus::NotFound);
+        let (string_ref, _) = TypedContractHandler::to_contract_bytes_of(&error).unwrap();
+        assert_eq!(string_ref, "nanoService_error");
+        assert_eq!(
+            TypedContractHandler::to_contract_bytes_of(&"not a contract".to_string()).unwrap_err().status,
+            NanoServiceErrorStatus::ContractNotSupported
+        );
+
+        let (string_ref, bytes) = BitcodeTypedContractHandler::to_contract_bytes_of(&typed_kernel::Ping(3)).unwrap();
+        let handler = BitcodeTypedContractHandler::Ping(typed_kernel::Ping(3));
+        assert_eq!(string_ref, handler.to_string_ref());
+        assert_eq!(BitcodeTypedContractHandler::from_contract_bytes(&bytes, string_ref).unwrap(), handler);
+        assert_eq!(BitcodeTypedContractHandler::wire_ref_of::<auth::LogoutRequest>(), None);
+    }
+
+    #[test]
+    fn test_bitcode_typed_variants() {
+        let login = BitcodeTypedContractHandler::Login(auth::LoginRequest { user: "John".to_string() });
+        assert_eq!(login.to_string_ref(), "login_contract");
+
+        let bytes = login.to_contract_bytes().unwrap();
+        let decoded = BitcodeTypedContractHandler::from_contract_bytes(&bytes, "login_contract".to_string()).unwrap();
+        assert_eq!(decoded.Login().unwrap(), auth::LoginRequest { user: "John".to_string() });
+        assert_eq!(BitcodeTypedContractHandler::wire_refs(), vec!["login_contract".to_string(), "ping_contract".to_string()]);
+    }
+
+    #[test]
+    fn test_self_describing_bytes() {
+        let contracts = vec![
+            ContractHandler::ContractOne(ContractOne),
+            ContractHandler::ContractTwo(ContractTwo),
+            ContractHandler::ContractThree(ContractThree),
+            ContractHandler::NanoServiceError(NanoServiceError::new(
+                "Test error".to_string(),
+                NanoServiceErrorStatus::Conflict
+            )),
+        ];
+        for contract in contracts {
+            let bytes = contract.to_self_describing_bytes().unwrap();
+            assert_eq!(&bytes[..4], &(contract.internal_index() as u32).to_be_bytes());
+            let decoded = ContractHandler::try_from(bytes.as_slice()).unwrap();
+            assert_eq!(decoded, contract);
+        }
+
+        let mut unknown_index = 9u32.to_be_bytes().to_vec();
+        unknown_index.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            ContractHandler::try_from(unknown_index.as_slice()).unwrap_err().status,
+            NanoServiceErrorStatus::ContractNotSupported
+        );
+        assert_eq!(
+            ContractHandler::try_from([0u8, 0].as_slice()).unwrap_err().status,
+            NanoServiceErrorStatus::BadRequest
+        );
+    }
+
+    #[test]
+    fn test_bitcode_self_describing_bytes() {
+        let contracts = vec![
+            BitcodeContractHandler::ContractOne(bitcode_kernel::ContractOne { name: "John".to_string() }),
+            BitcodeContractHandler::ContractTwo(bitcode_kernel::ContractTwo),
+            BitcodeContractHandler::ContractThree(bitcode_kernel::ContractThree),
+            BitcodeContractHandler::NanoServiceError(NanoServiceError::new(
+                "Test error".to_string(),
+                NanoServiceErrorStatus::Conflict
+            )),
+        ];
+        for contract in contracts {
+            let bytes = contract.to_self_describing_bytes().unwrap();
+            let decoded = BitcodeContractHandler::try_from(bytes.as_slice()).unwrap();
+            assert_eq!(decoded, contract);
+        }
+    }
+
+    mod field_kernel {
+        use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+        use serde::{Serialize, Deserialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct ContractOne {
+            pub name: String,
+            pub age: i32,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct ContractTwo;
+
+        create_contract_handler!(
+            FieldContractHandler,
+            ContractOne,
+            ContractTwo
+        );
+    }
+
+    #[test]
+    fn test_from_contract_bytes_names_missing_field() {
+        #[derive(Serialize)]
+        struct OldContractOne {
+            name: String,
+        }
+        let bytes = bincode::serialize(&OldContractOne { name: "John".to_string() }).unwrap();
+        let error = field_kernel::FieldContractHandler::from_contract_bytes(
+            &bytes,
+            "contractone_contract".to_string()
+        ).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+        assert!(error.message.contains("`age`"), "{}", error.message);
+    }
+
+}
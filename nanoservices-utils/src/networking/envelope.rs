@@ -0,0 +1,170 @@
+//! Defines the envelope that wraps a contract handler with the fields that cut across every contract such as the
+//! request ID, the auth token, and the trace ID. The envelope is serialized as a whole so it can be framed with
+//! the same codecs as a bare contract handler.
+use serde::{Serialize, Deserialize};
+use bitcode::{Encode, Decode};
+
+
+/// The cross-cutting fields of an envelope without the contract.
+///
+/// # Fields
+/// * `id` - The ID of the request so the response can be matched to it.
+/// * `token` - The auth token of the caller if there is one.
+/// * `trace` - The trace ID of the request if there is one.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct EnvelopeContext {
+    pub id: u64,
+    pub token: Option<String>,
+    pub trace: Option<String>,
+}
+
+
+/// Wraps a contract handler with the standard cross-cutting fields.
+///
+/// # Fields
+/// * `id` - The ID of the request so the response can be matched to it.
+/// * `token` - The auth token of the caller if there is one.
+/// * `trace` - The trace ID of the request if there is one.
+/// * `contract` - The contract handler being sent.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct Envelope<H> {
+    pub id: u64,
+    pub token: Option<String>,
+    pub trace: Option<String>,
+    pub contract: H,
+}
+
+impl<H> Envelope<H> {
+
+    /// Constructs a new `Envelope` with no token or trace.
+    ///
+    /// # Arguments
+    /// * `id` - The ID of the request.
+    /// * `contract` - The contract handler being sent.
+    ///
+    /// # Returns
+    /// * `Envelope<H>` - The new envelope.
+    pub fn new(id: u64, contract: H) -> Self {
+        Envelope {
+            id,
+            token: None,
+            trace: None,
+            contract,
+        }
+    }
+
+    /// Sets the auth token of the envelope.
+    ///
+    /// # Arguments
+    /// * `token` - The auth token of the caller.
+    ///
+    /// # Returns
+    /// * `Envelope<H>` - The envelope with the token.
+    pub fn with_token(mut self, token: String) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Sets the trace ID of the envelope.
+    ///
+    /// # Arguments
+    /// * `trace` - The trace ID of the request.
+    ///
+    /// # Returns
+    /// * `Envelope<H>` - The envelope with the trace ID.
+    pub fn with_trace(mut self, trace: String) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// Splits the envelope into its context and the contract.
+    ///
+    /// # Returns
+    /// * `(EnvelopeContext, H)` - The cross-cutting fields and the contract handler.
+    pub fn into_parts(self) -> (EnvelopeContext, H) {
+        (
+            EnvelopeContext {
+                id: self.id,
+                token: self.token,
+                trace: self.trace,
+            },
+            self.contract
+        )
+    }
+
+    /// Builds an envelope from a context and a contract. This is used to reply to a request with the same
+    /// ID, token, and trace as the request.
+    ///
+    /// # Arguments
+    /// * `context` - The cross-cutting fields of the request.
+    /// * `contract` - The contract handler being sent.
+    ///
+    /// # Returns
+    /// * `Envelope<H>` - The new envelope.
+    pub fn from_parts(context: EnvelopeContext, contract: H) -> Self {
+        Envelope {
+            id: context.id,
+            token: context.token,
+            trace: context.trace,
+            contract,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+    use crate::create_contract_handler;
+
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct ContractOne {
+        pub name: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct ContractTwo;
+
+    create_contract_handler!(
+        ContractHandler,
+        ContractOne,
+        ContractTwo
+    );
+
+    #[test]
+    fn test_envelope_round_trip() {
+        let envelope = Envelope::new(7, ContractHandler::ContractOne(ContractOne { name: "John".to_string() }))
+            .with_token("token".to_string())
+            .with_trace("trace".to_string());
+
+        let bytes = bincode::serialize(&envelope).unwrap();
+        let deserialized: Envelope<ContractHandler> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(envelope, deserialized);
+
+        let (context, contract) = deserialized.into_parts();
+        assert_eq!(context, EnvelopeContext {
+            id: 7,
+            token: Some("token".to_string()),
+            trace: Some("trace".to_string()),
+        });
+        assert_eq!(contract.ContractOne().unwrap(), ContractOne { name: "John".to_string() });
+
+        let reply = Envelope::from_parts(context, ContractHandler::ContractTwo(ContractTwo));
+        assert_eq!(reply.id, 7);
+        assert_eq!(reply.token, Some("token".to_string()));
+        assert_eq!(reply.trace, Some("trace".to_string()));
+    }
+
+    #[test]
+    fn test_bitcode_envelope_round_trip() {
+        let envelope = Envelope::new(3, "contract".to_string()).with_trace("trace".to_string());
+        let bytes = bitcode::encode(&envelope);
+        let decoded: Envelope<String> = bitcode::decode(&bytes).unwrap();
+        assert_eq!(envelope, decoded);
+        assert_eq!(decoded.token, None);
+    }
+
+}
@@ -0,0 +1,218 @@
+//! Defines the conversion of contracts into the HTTP responses of the supported web frameworks, so services that
+//! serve contracts over both TCP and HTTP can return a contract handler straight from an HTTP view. A contract is
+//! sent as a JSON body with a 200 status, and a `NanoServiceError` is sent through the error mapping of the
+//! framework. The conversion is implemented for the response type of each framework feature that is enabled and
+//! is called through the `into_http_response` method generated by `create_contract_handler!`:
+//!
+//! ```ignore
+//! async fn login(Json(request): Json<LoginRequest>) -> axum::response::Response {
+//!     let response = ContractHandler::dispatch_result(handle_contract(ContractHandler::LoginRequest(request)).await);
+//!     response.into_http_response()
+//! }
+//! ```
+use serde::Serialize;
+use crate::errors::NanoServiceError;
+
+
+/// A HTTP response that a contract or an error can be converted into.
+pub trait ContractHttpResponse {
+
+    /// Builds a 200 response with the contract serialized as a JSON body.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to send.
+    ///
+    /// # Returns
+    /// * `Self` - The response, or a 500 response if the contract could not be serialized.
+    fn from_contract<T: Serialize>(contract: T) -> Self;
+
+    /// Builds a response for an error using the error mapping of the framework.
+    ///
+    /// # Arguments
+    /// * `error` - The error to send.
+    ///
+    /// # Returns
+    /// * `Self` - The response for the error.
+    fn from_error(error: NanoServiceError) -> Self;
+}
+
+
+/// Builds the error for a contract that could not be serialized into a JSON body.
+#[cfg(any(feature = "rocket", feature = "hyper"))]
+fn serialize_error(error: impl std::fmt::Display) -> NanoServiceError {
+    NanoServiceError::new(
+        format!("Failed to serialize contract: {}", error),
+        crate::errors::NanoServiceErrorStatus::Unknown
+    )
+}
+
+
+#[cfg(feature = "axum")]
+impl ContractHttpResponse for axum::response::Response {
+
+    fn from_contract<T: Serialize>(contract: T) -> Self {
+        use axum::response::IntoResponse;
+        (axum::http::StatusCode::OK, axum::Json(contract)).into_response()
+    }
+
+    fn from_error(error: NanoServiceError) -> Self {
+        use axum::response::IntoResponse;
+        error.into_response()
+    }
+}
+
+
+#[cfg(feature = "actix")]
+impl ContractHttpResponse for actix_web::HttpResponse {
+
+    fn from_contract<T: Serialize>(contract: T) -> Self {
+        actix_web::HttpResponse::Ok().json(contract)
+    }
+
+    fn from_error(error: NanoServiceError) -> Self {
+        use actix_web::ResponseError;
+        error.error_response()
+    }
+}
+
+
+#[cfg(feature = "rocket")]
+impl ContractHttpResponse for rocket::Response<'static> {
+
+    fn from_contract<T: Serialize>(contract: T) -> Self {
+        match rocket::serde::json::to_string(&contract) {
+            Ok(json_body) => rocket_json_response(rocket::http::Status::Ok, json_body),
+            Err(e) => Self::from_error(serialize_error(e))
+        }
+    }
+
+    fn from_error(error: NanoServiceError) -> Self {
+        // the same body as the `Responder` of the error so the code and `structured-errors` are respected
+        let status = rocket::http::Status::new(error.status.http_status());
+        match rocket::serde::json::to_string(&error.body()) {
+            Ok(json_body) => rocket_json_response(status, json_body),
+            Err(_) => rocket::Response::build()
+                .status(status)
+                .header(rocket::http::ContentType::Plain)
+                .sized_body(error.message.len(), std::io::Cursor::new(error.message))
+                .finalize()
+        }
+    }
+}
+
+
+/// Builds a rocket response with a JSON body.
+///
+/// # Arguments
+/// * `status` - The status of the response.
+/// * `json_body` - The JSON body of the response.
+///
+/// # Returns
+/// * `rocket::Response<'static>` - The response.
+#[cfg(feature = "rocket")]
+fn rocket_json_response(status: rocket::http::Status, json_body: String) -> rocket::Response<'static> {
+    rocket::Response::build()
+        .status(status)
+        .header(rocket::http::ContentType::JSON)
+        .sized_body(json_body.len(), std::io::Cursor::new(json_body))
+        .finalize()
+}
+
+
+#[cfg(feature = "hyper")]
+impl ContractHttpResponse for hyper::Response<http_body_util::Full<hyper::body::Bytes>> {
+
+    fn from_contract<T: Serialize>(contract: T) -> Self {
+        let json_body = match serde_json::to_vec(&contract) {
+            Ok(json_body) => json_body,
+            Err(e) => return Self::from_error(serialize_error(e))
+        };
+        hyper::Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .status(hyper::StatusCode::OK)
+            .body(http_body_util::Full::new(hyper::body::Bytes::from(json_body)))
+            .unwrap_or_else(|e| Self::from_error(serialize_error(e)))
+    }
+
+    fn from_error(error: NanoServiceError) -> Self {
+        error.into_hyper_response()
+    }
+}
+
+
+#[cfg(all(test, any(feature = "axum", feature = "rocket")))]
+mod tests {
+
+    use crate::create_contract_handler;
+    use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Greeting {
+        pub message: String,
+    }
+
+    create_contract_handler!(
+        ContractHandler,
+        Greeting
+    );
+
+    #[cfg(feature = "axum")]
+    fn body_of(response: axum::response::Response) -> Vec<u8> {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()
+        })
+    }
+
+    #[cfg(feature = "axum")]
+    #[test]
+    fn test_into_axum_response() {
+        use axum::http::StatusCode;
+        use axum::response::Response;
+
+        let contract = ContractHandler::Greeting(Greeting { message: "hello".to_string() });
+        let response: Response = contract.into_http_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_of(response), br#"{"message":"hello"}"#);
+
+        let error = ContractHandler::NanoServiceError(NanoServiceError::new(
+            "greeting missing".to_string(),
+            NanoServiceErrorStatus::NotFound
+        ));
+        let response: Response = error.into_http_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        #[cfg(not(feature = "structured-errors"))]
+        assert_eq!(body_of(response), b"\"greeting missing\"");
+        #[cfg(feature = "structured-errors")]
+        assert_eq!(body_of(response), br#"{"message":"greeting missing","status":"NotFound","code":""}"#);
+    }
+
+    #[cfg(feature = "rocket")]
+    #[test]
+    fn test_into_rocket_response() {
+        use rocket::http::Status;
+        use rocket::Response;
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let contract = ContractHandler::Greeting(Greeting { message: "hello".to_string() });
+            let mut response: Response<'static> = contract.into_http_response();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(response.body_mut().to_string().await.unwrap(), r#"{"message":"hello"}"#);
+
+            let error = ContractHandler::NanoServiceError(NanoServiceError::new(
+                "greeting missing".to_string(),
+                NanoServiceErrorStatus::NotFound
+            ).with_code("greeting.missing"));
+            let mut response: Response<'static> = error.into_http_response();
+            assert_eq!(response.status(), Status::NotFound);
+            let body = response.body_mut().to_string().await.unwrap();
+            #[cfg(not(feature = "structured-errors"))]
+            assert_eq!(body, r#"{"message":"greeting missing","code":"greeting.missing"}"#);
+            #[cfg(feature = "structured-errors")]
+            assert_eq!(body, r#"{"message":"greeting missing","status":"NotFound","code":"greeting.missing"}"#);
+        });
+    }
+
+}
@@ -1,7 +1,17 @@
+pub mod cache;
 pub mod contract;
+pub mod envelope;
+pub mod http_response;
+pub mod recording;
+pub mod router;
 pub mod serialization;
+#[cfg(feature = "tower")]
+pub mod service;
 pub mod utils;
 #[cfg(feature = "tcp-messaging")]
 pub mod tcp;
+#[cfg(feature = "udp-messaging")]
+pub mod udp;
 #[cfg(feature = "wasm-messaging")]
 pub mod wasm;
+pub mod wire_ref;
@@ -0,0 +1,339 @@
+//! Defines the recording of inbound contracts to a file and the replaying of them through a handler so a contract
+//! that triggered a bug in production can be reproduced offline. Each record in the file is the length of the
+//! record (4 bytes, little endian) followed by a bincode serialized `RecordedContract`.
+//!
+//! # Example
+//!
+//! ```rust
+//! use nanoservices_utils::errors::{NanoServiceError, NanoServiceErrorStatus};
+//! use nanoservices_utils::create_contract_handler;
+//! use nanoservices_utils::networking::recording::ContractRecorder;
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! pub struct Login {
+//!     pub password: String,
+//! }
+//!
+//! create_contract_handler!(
+//!    ContractHandler,
+//!    Login
+//! );
+//!
+//! let path = std::env::temp_dir().join("nanoservices_recording_example.bin");
+//! let mut recorder = ContractRecorder::create(&path, 1024 * 1024, ContractHandler::to_string_ref)
+//!     .unwrap()
+//!     .redact(|contract: &mut ContractHandler| {
+//!         if let ContractHandler::Login(login) = contract {
+//!             login.password = "<redacted>".to_string();
+//!         }
+//!     });
+//! recorder.record(&ContractHandler::Login(Login { password: "secret".to_string() })).unwrap();
+//! ```
+use std::fs::File;
+use std::future::Future;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use crate::networking::utils::check_payload_size;
+
+
+/// The number of bytes used to record the length of a record.
+const LENGTH_BYTES: usize = 4;
+
+
+/// A contract captured by the `ContractRecorder`.
+///
+/// # Fields
+/// * `string_ref` - The wire ref of the contract.
+/// * `bytes` - The bincode serialized contract handler enum after redaction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedContract {
+    pub string_ref: String,
+    pub bytes: Vec<u8>,
+}
+
+impl RecordedContract {
+
+    /// Deserializes the recorded contract.
+    ///
+    /// # Returns
+    /// * `Result<H, NanoServiceError>` - The contract handler enum that was recorded.
+    pub fn decode<H: DeserializeOwned>(&self) -> Result<H, NanoServiceError> {
+        bincode::deserialize(&self.bytes).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })
+    }
+}
+
+
+/// Records inbound contracts to a file until the size limit of the file is reached.
+///
+/// # Fields
+/// * `writer` - The file the contracts are recorded to.
+/// * `max_bytes` - The maximum number of bytes recorded to the file.
+/// * `written` - The number of bytes recorded to the file so far.
+/// * `string_ref` - Gets the wire ref of a contract (e.g. `ContractHandler::to_string_ref`).
+/// * `redactors` - Blank out sensitive fields of a copy of the contract before it is recorded.
+pub struct ContractRecorder<H> {
+    writer: BufWriter<File>,
+    max_bytes: u64,
+    written: u64,
+    string_ref: fn(&H) -> String,
+    redactors: Vec<fn(&mut H)>,
+}
+
+impl<H: Serialize + DeserializeOwned> ContractRecorder<H> {
+
+    /// Creates a recorder, truncating the file if it already exists.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the file to record to.
+    /// * `max_bytes` - The maximum number of bytes recorded to the file.
+    /// * `string_ref` - Gets the wire ref of a contract (e.g. `ContractHandler::to_string_ref`).
+    ///
+    /// # Returns
+    /// * `Result<ContractRecorder<H>, NanoServiceError>` - The recorder.
+    pub fn create<P: AsRef<Path>>(path: P, max_bytes: u64, string_ref: fn(&H) -> String) -> Result<Self, NanoServiceError> {
+        let file = File::create(path).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+        })?;
+        Ok(ContractRecorder {
+            writer: BufWriter::new(file),
+            max_bytes,
+            written: 0,
+            string_ref,
+            redactors: Vec::new(),
+        })
+    }
+
+    /// Adds a redactor that is run on a copy of every contract before it is recorded.
+    ///
+    /// # Arguments
+    /// * `redactor` - Blanks out the sensitive fields of the contract.
+    ///
+    /// # Returns
+    /// * `ContractRecorder<H>` - The recorder with the redactor added.
+    pub fn redact(mut self, redactor: fn(&mut H)) -> Self {
+        self.redactors.push(redactor);
+        self
+    }
+
+    /// Records a contract. The contract passed in is not changed, the redactors are run on a copy.
+    ///
+    /// # Arguments
+    /// * `contract` - The inbound contract.
+    ///
+    /// # Returns
+    /// * `Result<bool, NanoServiceError>` - `true` if the contract was recorded, `false` if it would have taken
+    ///   the file over the size limit.
+    pub fn record(&mut self, contract: &H) -> Result<bool, NanoServiceError> {
+        let mut bytes = bincode::serialize(contract).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })?;
+        if !self.redactors.is_empty() {
+            let mut copy: H = bincode::deserialize(&bytes).map_err(|e| {
+                NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+            })?;
+            for redactor in &self.redactors {
+                redactor(&mut copy);
+            }
+            bytes = bincode::serialize(&copy).map_err(|e| {
+                NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+            })?;
+        }
+        let record = bincode::serialize(&RecordedContract {
+            string_ref: (self.string_ref)(contract),
+            bytes,
+        }).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })?;
+
+        let record_len = (LENGTH_BYTES + record.len()) as u64;
+        if self.written + record_len > self.max_bytes {
+            return Ok(false)
+        }
+        check_payload_size(record.len(), u32::MAX as usize)?;
+        let length = record.len() as u32;
+        self.writer.write_all(&length.to_le_bytes()).and_then(|_| {
+            self.writer.write_all(&record)
+        }).and_then(|_| {
+            self.writer.flush()
+        }).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+        })?;
+        self.written += record_len;
+        Ok(true)
+    }
+}
+
+
+/// Reads the contracts recorded to a file.
+///
+/// # Arguments
+/// * `path` - The path of the recording.
+///
+/// # Returns
+/// * `Result<Vec<RecordedContract>, NanoServiceError>` - The recorded contracts in the order they were recorded.
+pub fn read_recording<P: AsRef<Path>>(path: P) -> Result<Vec<RecordedContract>, NanoServiceError> {
+    let mut bytes = Vec::new();
+    File::open(path).and_then(|mut file| file.read_to_end(&mut bytes)).map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    })?;
+
+    let mut recorded = Vec::new();
+    let mut rest = bytes.as_slice();
+    while !rest.is_empty() {
+        if rest.len() < LENGTH_BYTES {
+            return Err(NanoServiceError::new(
+                "Recording ends with a truncated record".to_string(),
+                NanoServiceErrorStatus::BadRequest
+            ))
+        }
+        let (length_bytes, remaining) = rest.split_at(LENGTH_BYTES);
+        let length = u32::from_le_bytes([length_bytes[0], length_bytes[1], length_bytes[2], length_bytes[3]]) as usize;
+        let record = remaining.get(..length).ok_or_else(|| {
+            NanoServiceError::new(
+                "Recording ends with a truncated record".to_string(),
+                NanoServiceErrorStatus::BadRequest
+            )
+        })?;
+        recorded.push(bincode::deserialize(record).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })?);
+        rest = &remaining[length..];
+    }
+    Ok(recorded)
+}
+
+
+/// Feeds the contracts recorded to a file through a handler one at a time in the order they were recorded.
+///
+/// # Arguments
+/// * `path` - The path of the recording.
+/// * `handler` - The handler for the contracts (e.g. the function made by `register_contract_routes!`).
+///
+/// # Returns
+/// * `Result<Vec<Result<H, NanoServiceError>>, NanoServiceError>` - The outcome of the handler for each recorded
+///   contract, or an error if the recording could not be read.
+pub async fn replay<H, F, Fut, P>(path: P, handler: F) -> Result<Vec<Result<H, NanoServiceError>>, NanoServiceError>
+where
+    H: DeserializeOwned,
+    F: Fn(H) -> Fut,
+    Fut: Future<Output = Result<H, NanoServiceError>>,
+    P: AsRef<Path>,
+{
+    let mut outcomes = Vec::new();
+    for recorded in read_recording(path)? {
+        outcomes.push(handler(recorded.decode()?).await);
+    }
+    Ok(outcomes)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::create_contract_handler;
+    use tokio::runtime::Builder;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Login {
+        pub username: String,
+        pub password: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Divide {
+        pub numerator: i32,
+        pub denominator: i32,
+    }
+
+    create_contract_handler!(
+        ContractHandler,
+        Login,
+        Divide
+    );
+
+    async fn handle_contract(contract: ContractHandler) -> Result<ContractHandler, NanoServiceError> {
+        match contract {
+            ContractHandler::Divide(divide) => {
+                if divide.denominator == 0 {
+                    return Err(NanoServiceError::new("Division by zero".to_string(), NanoServiceErrorStatus::BadRequest))
+                }
+                Ok(ContractHandler::Divide(Divide {
+                    numerator: divide.numerator / divide.denominator,
+                    denominator: 1,
+                }))
+            },
+            other => Ok(other)
+        }
+    }
+
+    fn redact_password(contract: &mut ContractHandler) {
+        if let ContractHandler::Login(login) = contract {
+            login.password = "<redacted>".to_string();
+        }
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let path = std::env::temp_dir().join("nanoservices_test_record_and_replay.bin");
+            let mut recorder = ContractRecorder::create(&path, 1024, ContractHandler::to_string_ref)
+                .unwrap()
+                .redact(redact_password);
+
+            let inbound = vec![
+                ContractHandler::Divide(Divide { numerator: 10, denominator: 2 }),
+                ContractHandler::Divide(Divide { numerator: 10, denominator: 0 }),
+                ContractHandler::Login(Login { username: "john".to_string(), password: "secret".to_string() }),
+            ];
+            let mut live_outcomes = Vec::new();
+            for contract in inbound {
+                assert!(recorder.record(&contract).unwrap());
+                live_outcomes.push(handle_contract(contract).await);
+            }
+
+            let recorded = read_recording(&path).unwrap();
+            assert_eq!(
+                recorded.iter().map(|r| r.string_ref.as_str()).collect::<Vec<_>>(),
+                vec!["divide_contract", "divide_contract", "login_contract"]
+            );
+            assert_eq!(
+                recorded[2].decode::<ContractHandler>().unwrap(),
+                ContractHandler::Login(Login { username: "john".to_string(), password: "<redacted>".to_string() })
+            );
+
+            let replayed_outcomes = replay(&path, handle_contract).await.unwrap();
+            // the outcomes match apart from the redacted password
+            assert_eq!(replayed_outcomes[..2], live_outcomes[..2]);
+            assert_eq!(replayed_outcomes[2], Ok(
+                ContractHandler::Login(Login { username: "john".to_string(), password: "<redacted>".to_string() })
+            ));
+            std::fs::remove_file(&path).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_recording_size_is_bounded() {
+        let path = std::env::temp_dir().join("nanoservices_test_recording_size_is_bounded.bin");
+        let mut recorder = ContractRecorder::create(&path, 64, ContractHandler::to_string_ref).unwrap();
+        let contract = ContractHandler::Divide(Divide { numerator: 1, denominator: 1 });
+
+        assert!(recorder.record(&contract).unwrap());
+        assert!(!recorder.record(&contract).unwrap());
+        assert_eq!(read_recording(&path).unwrap().len(), 1);
+        assert!(std::fs::metadata(&path).unwrap().len() <= 64);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+}
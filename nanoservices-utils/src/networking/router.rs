@@ -0,0 +1,278 @@
+//! Defines a router that binds contract handlers at runtime. This complements the `register_contract_routes!`
+//! macro for plugin-style services where the routes are not known at compile time. The `ContractRoute` trait is
+//! also defined here for generic servers that want to dispatch to handlers without boxing.
+//!
+//! # Example
+//!
+//! ```rust
+//! use nanoservices_utils::errors::{NanoServiceError, NanoServiceErrorStatus};
+//! use nanoservices_utils::create_contract_handler;
+//! use nanoservices_utils::networking::router::ContractRouter;
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! pub struct ContractOne;
+//!
+//! create_contract_handler!(
+//!    ContractHandler,
+//!    ContractOne
+//! );
+//!
+//! let mut router = ContractRouter::new(ContractHandler::to_string_ref);
+//! router.register("contractone_contract", |contract: ContractHandler| async move {
+//!     Ok(contract)
+//! });
+//! ```
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use crate::errors::NanoServiceError;
+use crate::networking::wire_ref::contract_not_supported;
+
+
+/// The boxed future that is returned by a handler registered with the `ContractRouter`.
+pub type ContractRouteFuture<H> = Pin<Box<dyn Future<Output = Result<H, NanoServiceError>> + Send>>;
+
+/// A handler that has been registered with the `ContractRouter`.
+pub type ContractRouteHandler<H> = Box<dyn Fn(H) -> ContractRouteFuture<H> + Send + Sync>;
+
+
+/// A handler for a contract handler enum that can be statically dispatched by generic servers. Any function
+/// or closure taking the contract handler and returning a future of the result implements this trait, including
+/// the functions made by `register_contract_routes!`.
+pub trait ContractRoute<H> {
+    type Fut: Future<Output = Result<H, NanoServiceError>> + Send;
+
+    /// Handles a contract.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to be handled.
+    ///
+    /// # Returns
+    /// * `Self::Fut` - The future that resolves to the response of the handler.
+    fn handle(&self, contract: H) -> Self::Fut;
+}
+
+impl<H, F, Fut> ContractRoute<H> for F
+where
+    F: Fn(H) -> Fut,
+    Fut: Future<Output = Result<H, NanoServiceError>> + Send,
+{
+    type Fut = Fut;
+
+    fn handle(&self, contract: H) -> Self::Fut {
+        self(contract)
+    }
+}
+
+/// Passes a contract to a route without boxing the future of the route.
+///
+/// # Arguments
+/// * `route` - The route that handles the contract.
+/// * `contract` - The contract to be handled.
+///
+/// # Returns
+/// * `Result<H, NanoServiceError>` - The response of the route.
+pub async fn dispatch_route<H, R: ContractRoute<H>>(route: &R, contract: H) -> Result<H, NanoServiceError> {
+    route.handle(contract).await
+}
+
+
+/// Routes contracts to handlers that have been registered at runtime.
+///
+/// # Fields
+/// * `routes` - The handlers keyed by the wire ref of the contract they handle.
+/// * `string_ref` - The function that extracts the wire ref from a contract (e.g. `ContractHandler::to_string_ref`).
+pub struct ContractRouter<H> {
+    routes: HashMap<String, ContractRouteHandler<H>>,
+    string_ref: fn(&H) -> String,
+}
+
+impl<H> ContractRouter<H> {
+
+    /// Constructs a new empty `ContractRouter`.
+    ///
+    /// # Arguments
+    /// * `string_ref` - The function that extracts the wire ref from a contract.
+    ///
+    /// # Returns
+    /// * `ContractRouter<H>` - The new router with no routes registered.
+    pub fn new(string_ref: fn(&H) -> String) -> Self {
+        ContractRouter {
+            routes: HashMap::new(),
+            string_ref,
+        }
+    }
+
+    /// Registers a handler for a contract. If a handler is already registered for the wire ref, it is replaced.
+    ///
+    /// # Arguments
+    /// * `string_ref` - The wire ref of the contract that the handler handles (e.g. `contractone_contract`).
+    /// * `handler` - The async function that handles the contract.
+    pub fn register<F, Fut>(&mut self, string_ref: &str, handler: F)
+    where
+        F: Fn(H) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<H, NanoServiceError>> + Send + 'static,
+    {
+        let handler: ContractRouteHandler<H> = Box::new(move |contract: H| Box::pin(handler(contract)));
+        self.routes.insert(string_ref.to_string(), handler);
+    }
+
+    /// Checks if a handler is registered for a wire ref.
+    ///
+    /// # Arguments
+    /// * `string_ref` - The wire ref of the contract.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if a handler is registered for the wire ref.
+    pub fn contains(&self, string_ref: &str) -> bool {
+        self.routes.contains_key(string_ref)
+    }
+
+    /// Passes a contract to the handler registered for its wire ref.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to be handled.
+    ///
+    /// # Returns
+    /// * `Result<H, NanoServiceError>` - The contract returned by the handler or an error listing the registered wire
+    ///   refs if no handler is registered.
+    pub async fn dispatch(&self, contract: H) -> Result<H, NanoServiceError> {
+        let string_ref = (self.string_ref)(&contract);
+        match self.routes.get(&string_ref) {
+            Some(handler) => handler(contract).await,
+            None => Err(contract_not_supported(&string_ref, &self.registered_refs())),
+        }
+    }
+
+    /// Gets the wire refs that have a handler registered, sorted so errors listing them are stable.
+    ///
+    /// # Returns
+    /// * `Vec<String>` - The registered wire refs.
+    pub fn registered_refs(&self) -> Vec<String> {
+        let mut refs: Vec<String> = self.routes.keys().cloned().collect();
+        refs.sort();
+        refs
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::errors::NanoServiceErrorStatus;
+    use serde::{Serialize, Deserialize};
+    use crate::create_contract_handler;
+    use std::future::{ready, Ready};
+    use tokio::runtime::Builder;
+
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct ContractOne {
+        pub value: i32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct ContractTwo {
+        pub value: i32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct ContractThree;
+
+    create_contract_handler!(
+        ContractHandler,
+        ContractOne,
+        ContractTwo,
+        ContractThree
+    );
+
+    async fn handle_contract_one(mut contract: ContractOne) -> Result<ContractOne, NanoServiceError> {
+        contract.value += 1;
+        Ok(contract)
+    }
+
+    async fn handle_contract_two(mut contract: ContractTwo) -> Result<ContractTwo, NanoServiceError> {
+        contract.value += 2;
+        Ok(contract)
+    }
+
+    struct AddRoute {
+        amount: i32,
+    }
+
+    impl ContractRoute<ContractHandler> for AddRoute {
+        type Fut = Ready<Result<ContractHandler, NanoServiceError>>;
+
+        fn handle(&self, contract: ContractHandler) -> Self::Fut {
+            ready(contract.ContractOne().map(|mut inner| {
+                inner.value += self.amount;
+                ContractHandler::ContractOne(inner)
+            }))
+        }
+    }
+
+    async fn handle_contract(contract: ContractHandler) -> Result<ContractHandler, NanoServiceError> {
+        let executed_contract = handle_contract_two(contract.ContractTwo()?).await?;
+        Ok(ContractHandler::ContractTwo(executed_contract))
+    }
+
+    #[test]
+    fn test_dispatch_route() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let route = AddRoute { amount: 5 };
+            let handled = dispatch_route(&route, ContractHandler::ContractOne(ContractOne { value: 1 })).await;
+            assert_eq!(handled, Ok(ContractHandler::ContractOne(ContractOne { value: 6 })));
+
+            let handled = dispatch_route(&route, ContractHandler::ContractThree(ContractThree)).await;
+            assert_eq!(handled.unwrap_err().status, NanoServiceErrorStatus::BadRequest);
+
+            // plain async functions are routes as well
+            let handled = dispatch_route(&handle_contract, ContractHandler::ContractTwo(ContractTwo { value: 1 })).await;
+            assert_eq!(handled, Ok(ContractHandler::ContractTwo(ContractTwo { value: 3 })));
+        });
+    }
+
+    #[test]
+    fn test_register_and_dispatch() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let mut router = ContractRouter::new(ContractHandler::to_string_ref);
+            router.register("contractone_contract", |contract: ContractHandler| async move {
+                let executed_contract = handle_contract_one(contract.ContractOne()?).await?;
+                Ok(ContractHandler::ContractOne(executed_contract))
+            });
+            router.register("contracttwo_contract", |contract: ContractHandler| async move {
+                let executed_contract = handle_contract_two(contract.ContractTwo()?).await?;
+                Ok(ContractHandler::ContractTwo(executed_contract))
+            });
+            assert!(router.contains("contractone_contract"));
+            assert!(router.contains("contracttwo_contract"));
+            assert!(!router.contains("contractthree_contract"));
+
+            let handled_contract_one = router.dispatch(ContractHandler::ContractOne(ContractOne { value: 1 })).await;
+            let handled_contract_two = router.dispatch(ContractHandler::ContractTwo(ContractTwo { value: 1 })).await;
+            let handled_contract_three = router.dispatch(ContractHandler::ContractThree(ContractThree)).await;
+
+            assert_eq!(handled_contract_one, Ok(ContractHandler::ContractOne(ContractOne { value: 2 })));
+            assert_eq!(handled_contract_two, Ok(ContractHandler::ContractTwo(ContractTwo { value: 3 })));
+            assert_eq!(handled_contract_three, Err(NanoServiceError::new(
+                "Contract not supported: contractthree_contract. Supported contracts: contractone_contract, contracttwo_contract".to_string(),
+                NanoServiceErrorStatus::ContractNotSupported
+            )));
+        });
+    }
+
+}
@@ -5,15 +5,40 @@ use tokio_util::codec::{Decoder, Encoder};
 use bytes::{BufMut, BytesMut};
 use std::{io, marker::PhantomData};
 use bitcode::{DecodeOwned, Encode};
+use crate::networking::serialization::codec::check_message_size;
+use crate::networking::serialization::DEFAULT_MAX_MESSAGE_SIZE;
 
 
+/// A codec that serializes and deserializes data using the bitcode format.
+///
+/// # Fields
+/// * `max_len` - The maximum length of a serialized contract in bytes, `DEFAULT_MAX_MESSAGE_SIZE` by default.
 pub struct BitcodeCodec<T> {
+    max_len: usize,
     phantom: PhantomData<T>,
 }
 
 impl<T> BitcodeCodec<T> {
     pub fn new() -> Self {
-        BitcodeCodec { phantom: PhantomData }
+        BitcodeCodec { max_len: DEFAULT_MAX_MESSAGE_SIZE, phantom: PhantomData }
+    }
+
+    /// Sets the maximum length of a serialized contract that the codec will encode or decode.
+    ///
+    /// # Arguments
+    /// * `max_len` - The maximum length in bytes.
+    ///
+    /// # Returns
+    /// * `BitcodeCodec<T>` - The codec with the new maximum length.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+}
+
+impl<T> Default for BitcodeCodec<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -25,9 +50,10 @@ where
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        check_message_size(src.len(), self.max_len, io::ErrorKind::InvalidData)?;
         bitcode::decode(&src[..]).map(Some).map_err(|e| {
             eprintln!("Decode failed: {:?}", e);
-            io::Error::new(io::ErrorKind::Other, "deserialize failed")
+            io::Error::other("deserialize failed")
         })
     }
 }
@@ -40,6 +66,7 @@ where
 
     fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Self::Error> {
         let encoded = bitcode::encode(&item);
+        check_message_size(encoded.len(), self.max_len, io::ErrorKind::InvalidInput)?;
         dst.reserve(encoded.len());
         dst.put_slice(&encoded);
         Ok(())
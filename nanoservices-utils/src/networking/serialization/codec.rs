@@ -1,18 +1,98 @@
-//! Defines the TCP framing for the bincode serialization format.
+//! Defines the TCP framing for the bincode serialization format. A frame is the length of the serialized contract
+//! (4 bytes, little endian) followed by the serialized contract, so a contract split over several reads is only
+//! decoded once all of it has arrived and several contracts in one read are decoded one after the other. Frames
+//! longer than the maximum message size of the codec are rejected before any buffer is reserved for them.
 use tokio_util::codec::{Decoder, Encoder};
-use bytes::{BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use std::{io, marker::PhantomData};
 use serde::Serialize;
+use crate::networking::serialization::field_path::deserialize_bincode;
+use crate::networking::serialization::DEFAULT_MAX_MESSAGE_SIZE;
+use crate::networking::utils::check_payload_size;
 
 
-/// A codec that serializes and deserializes data using the bincode format for framing.
+/// The number of bytes used to record the length of a frame.
+pub(crate) const LENGTH_BYTES: usize = 4;
+
+
+/// Checks that a message is within the maximum message size of a codec. The `PayloadTooLarge` error is wrapped in
+/// the IO error so `NanoServiceError::from_io_error` can tell it apart from a malformed message.
+///
+/// # Arguments
+/// * `len` - The length of the message in bytes.
+/// * `max_len` - The maximum length of a message in bytes.
+/// * `kind` - The kind of IO error to return if the message is too long.
+///
+/// # Returns
+/// * `io::Result<()>` - An error if the message is over the maximum length.
+pub(crate) fn check_message_size(len: usize, max_len: usize, kind: io::ErrorKind) -> io::Result<()> {
+    check_payload_size(len, max_len).map_err(|e| io::Error::new(kind, e))
+}
+
+
+/// Splits the next complete length prefixed frame off the buffer without copying it.
+///
+/// # Arguments
+/// * `src` - The buffer of received bytes.
+/// * `max_len` - The maximum length of a frame in bytes.
+///
+/// # Returns
+/// * `io::Result<Option<Bytes>>` - The serialized contract of the next frame, `None` if the frame has not fully
+///   arrived, or an error if the advertised length is over `max_len`.
+pub(crate) fn next_length_prefixed_frame(src: &mut BytesMut, max_len: usize) -> io::Result<Option<Bytes>> {
+    if src.len() < LENGTH_BYTES {
+        return Ok(None)
+    }
+    let frame_len = u32::from_le_bytes([src[0], src[1], src[2], src[3]]) as usize;
+    // the length is checked before reserving so a peer cannot make us allocate an arbitrary amount of memory
+    check_message_size(frame_len, max_len, io::ErrorKind::InvalidData)?;
+    if src.len() < LENGTH_BYTES + frame_len {
+        src.reserve(LENGTH_BYTES + frame_len - src.len());
+        return Ok(None)
+    }
+    src.advance(LENGTH_BYTES);
+    Ok(Some(src.split_to(frame_len).freeze()))
+}
+
+
+/// A codec that serializes and deserializes data using the bincode format with length prefixed framing.
+///
+/// # Fields
+/// * `max_len` - The maximum length of a serialized contract in bytes, `DEFAULT_MAX_MESSAGE_SIZE` by default.
 pub struct BincodeCodec<T> {
+    max_len: usize,
     phantom: PhantomData<T>,
 }
 
 impl<T> BincodeCodec<T> {
     pub fn new() -> Self {
-        BincodeCodec { phantom: PhantomData }
+        BincodeCodec { max_len: DEFAULT_MAX_MESSAGE_SIZE, phantom: PhantomData }
+    }
+
+    /// Sets the maximum length of a serialized contract that the codec will encode or decode.
+    ///
+    /// # Arguments
+    /// * `max_len` - The maximum length in bytes.
+    ///
+    /// # Returns
+    /// * `BincodeCodec<T>` - The codec with the new maximum length.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Gets the maximum length of a serialized contract that the codec will encode or decode.
+    ///
+    /// # Returns
+    /// * `usize` - The maximum length in bytes.
+    pub(crate) fn max_len(&self) -> usize {
+        self.max_len
+    }
+}
+
+impl<T> Default for BincodeCodec<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -24,10 +104,18 @@ where
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        bincode::deserialize(&src[..]).map(Some).map_err(|e| {
-            eprintln!("Decode failed: {:?}", e);
-            io::Error::new(io::ErrorKind::Other, "deserialize failed")
-        })
+        // a short buffer means the rest of the contract has not arrived yet, not that it is corrupt
+        let frame = match next_length_prefixed_frame(src, self.max_len)? {
+            Some(frame) => frame,
+            None => return Ok(None)
+        };
+        match deserialize_bincode(&frame) {
+            Ok(item) => Ok(Some(item)),
+            Err(e) => {
+                eprintln!("Decode failed: {}", e.message);
+                Err(io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
     }
 }
 
@@ -40,9 +128,14 @@ where
     fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Self::Error> {
         let encoded = bincode::serialize(&item).map_err(|e| {
             eprintln!("Encode failed: {:?}", e);
-            io::Error::new(io::ErrorKind::Other, "serialize failed")
+            io::Error::other("serialize failed")
         })?;
-        dst.reserve(encoded.len());
+        check_message_size(encoded.len(), self.max_len, io::ErrorKind::InvalidInput)?;
+        let frame_len = u32::try_from(encoded.len()).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+        })?;
+        dst.reserve(LENGTH_BYTES + encoded.len());
+        dst.put_u32_le(frame_len);
         dst.put_slice(&encoded);
         Ok(())
     }
@@ -52,6 +145,7 @@ where
 mod tests {
 
     use super::*;
+    use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
     use tokio_util::codec::Decoder;
     use tokio_util::codec::Framed;
     use futures::{sink::SinkExt, StreamExt};
@@ -73,16 +167,14 @@ mod tests {
         while let Ok((socket, _)) = listener.accept().await {
             let mut framed = Framed::new(socket, BincodeCodec::<TestStruct>::new());
 
-            while let Some(result) = framed.next().await {
+            if let Some(result) = framed.next().await {
                 match result {
                     Ok(mut data) => {
                         data.field1 += 1;
                         framed.send(data).await.unwrap();
-                        break;
                     },
                     Err(e) => {
                         eprintln!("Error processing data: {}", e);
-                        break;
                     }
                 }
             }
@@ -97,10 +189,150 @@ mod tests {
             field2: "hello".to_string(),
         };
         let encoded = bincode::serialize(&test_struct).unwrap();
-        let mut buf = BytesMut::with_capacity(encoded.len());
-        buf.put_slice(&encoded);
+        let mut buf = BytesMut::new();
+        codec.encode(test_struct, &mut buf).unwrap();
+        assert_eq!(&buf[..LENGTH_BYTES], (encoded.len() as u32).to_le_bytes());
+        assert_eq!(&buf[LENGTH_BYTES..], &encoded[..]);
+
         let decoded = codec.decode(&mut buf).unwrap().unwrap();
-        assert_eq!(test_struct, decoded);
+        assert_eq!(decoded, TestStruct { field1: 42, field2: "hello".to_string() });
+    }
+
+    #[test]
+    fn test_decode_incrementally() {
+        let mut codec = BincodeCodec::<TestStruct>::new();
+        let mut encoded = BytesMut::new();
+        codec.encode(TestStruct { field1: 42, field2: "hello".to_string() }, &mut encoded).unwrap();
+        let mut buf = BytesMut::new();
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+        for byte in &encoded[..encoded.len() - 1] {
+            buf.put_u8(*byte);
+            assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        }
+        buf.put_u8(encoded[encoded.len() - 1]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(TestStruct { field1: 42, field2: "hello".to_string() }));
+        // the decoded contract is removed from the buffer
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_decode_multiple_frames() {
+        let mut codec = BincodeCodec::<TestStruct>::new();
+        let mut buf = BytesMut::new();
+        for field1 in 0..3 {
+            codec.encode(TestStruct { field1, field2: "hello".to_string() }, &mut buf).unwrap();
+        }
+        // the start of a fourth frame that has not fully arrived
+        buf.put_u32_le(100);
+        buf.put_u8(1);
+
+        for field1 in 0..3 {
+            assert_eq!(codec.decode(&mut buf).unwrap(), Some(TestStruct { field1, field2: "hello".to_string() }));
+        }
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(buf.len(), LENGTH_BYTES + 1);
+    }
+
+    #[test]
+    fn test_decode_corrupt_bytes() {
+        let mut codec = BincodeCodec::<bool>::new();
+        let mut buf = BytesMut::new();
+        buf.put_u32_le(1);
+        buf.put_u8(7);
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    mod contracts {
+        use crate::create_contract_handler;
+        use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+        use serde::{Serialize, Deserialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct Address {
+            pub street: String,
+            pub number: u32,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct Person {
+            pub name: String,
+            pub address: Address,
+        }
+
+        create_contract_handler!(
+            ContractHandler,
+            Person
+        );
+
+        /// The contracts of a peer that is still on the layout without the house number.
+        pub mod old {
+            use crate::create_contract_handler;
+            use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+            use serde::{Serialize, Deserialize};
+
+            #[derive(Debug, PartialEq, Serialize, Deserialize)]
+            pub struct Address {
+                pub street: String,
+            }
+
+            #[derive(Debug, PartialEq, Serialize, Deserialize)]
+            pub struct Person {
+                pub name: String,
+                pub address: Address,
+            }
+
+            create_contract_handler!(
+                ContractHandler,
+                Person
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_error_names_nested_field() {
+        let mut old_codec = BincodeCodec::<contracts::old::ContractHandler>::new();
+        let mut buf = BytesMut::new();
+        let old_person = contracts::old::Person {
+            name: "John".to_string(),
+            address: contracts::old::Address { street: "Main".to_string() },
+        };
+        old_codec.encode(contracts::old::ContractHandler::Person(old_person), &mut buf).unwrap();
+
+        let mut codec = BincodeCodec::<contracts::ContractHandler>::new();
+        let error = NanoServiceError::from(codec.decode(&mut buf).unwrap_err());
+        assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+        assert!(
+            error.message.starts_with("Failed to deserialize contract at `Person.address.number`"),
+            "{}",
+            error.message
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_oversized_frame() {
+        let mut codec = BincodeCodec::<TestStruct>::new().with_max_len(16);
+        let mut buf = BytesMut::new();
+        // only the header of a huge frame has arrived, nothing should be reserved for the rest of it
+        buf.put_u32_le(u32::MAX);
+        let capacity = buf.capacity();
+
+        let error = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = NanoServiceError::from_io_error(error, NanoServiceErrorStatus::BadRequest);
+        assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+        assert_eq!(error.message, format!("Payload of {} bytes exceeds the limit of 16 bytes", u32::MAX));
+        assert_eq!(buf.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_encode_rejects_oversized_contract() {
+        let mut codec = BincodeCodec::<TestStruct>::new().with_max_len(8);
+        let mut buf = BytesMut::new();
+        let error = codec.encode(TestStruct { field1: 42, field2: "hello".to_string() }, &mut buf).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(NanoServiceError::from(error).status, NanoServiceErrorStatus::PayloadTooLarge);
+        assert!(buf.is_empty());
     }
 
     #[test]
@@ -0,0 +1,365 @@
+//! Defines deserialization that records the path of the field that failed so errors from mismatched contracts
+//! name the field that broke instead of only giving a generic bincode message.
+//!
+//! Bincode does not encode field names so structs are decoded as sequences and enums by the index of the variant,
+//! so the path tracked by `serde_path_to_error` is positional (e.g. `1[1][1]`). The path is resolved to names (e.g.
+//! `Person.address.number`) by walking the contract type along the path, which asks each struct and enum on the
+//! way for its field and variant names without needing any data.
+//!
+//! Bitcode does not go through serde and reports no position, so its errors use the same message with the path of
+//! the contract type that failed instead.
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor
+};
+use serde::forward_to_deserialize_any;
+use serde_path_to_error::{Path, Segment};
+use bincode::Options;
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+
+
+/// Deserializes bincode bytes with the same options as `bincode::deserialize`, tracking the path of the field
+/// being deserialized.
+///
+/// # Arguments
+/// * `bytes` - The bincode bytes.
+///
+/// # Returns
+/// * `Result<T, NanoServiceError>` - The deserialized value or a `BadRequest` error naming the field that failed.
+pub fn deserialize_bincode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, NanoServiceError> {
+    let options = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes();
+    let mut deserializer = bincode::Deserializer::from_slice(bytes, options);
+    serde_path_to_error::deserialize(&mut deserializer).map_err(field_error::<T>)
+}
+
+
+/// Decodes bitcode bytes, naming the contract type that failed in the error.
+///
+/// # Arguments
+/// * `bytes` - The bitcode bytes.
+///
+/// # Returns
+/// * `Result<T, NanoServiceError>` - The decoded value or a `BadRequest` error naming the contract type that failed.
+pub fn decode_bitcode<T: bitcode::DecodeOwned>(bytes: &[u8]) -> Result<T, NanoServiceError> {
+    bitcode::decode::<T>(bytes).map_err(|e| {
+        NanoServiceError::new(
+            format!("Failed to deserialize contract at `{}`: {}", std::any::type_name::<T>(), e),
+            NanoServiceErrorStatus::BadRequest
+        )
+    })
+}
+
+
+/// Converts a deserialization error into a `BadRequest` error naming the field that failed.
+///
+/// # Arguments
+/// * `error` - The error with the path of the field that failed.
+///
+/// # Returns
+/// * `NanoServiceError` - The error to be returned.
+fn field_error<T: DeserializeOwned>(error: serde_path_to_error::Error<bincode::Error>) -> NanoServiceError {
+    NanoServiceError::new(
+        format!(
+            "Failed to deserialize contract at `{}`: {}",
+            describe_path::<T>(error.path()),
+            error.inner()
+        ),
+        NanoServiceErrorStatus::BadRequest
+    )
+}
+
+
+/// Renders a path, replacing the positions of struct fields and enum variants with their names.
+///
+/// # Arguments
+/// * `path` - The path tracked while deserializing.
+///
+/// # Returns
+/// * `String` - The path with every field and variant that could be resolved named, e.g. `Person.address.number`.
+fn describe_path<T: DeserializeOwned>(path: &Path) -> String {
+    let segments: Vec<&Segment> = path.iter().collect();
+    let mut names = Vec::new();
+    let _ = T::deserialize(PathProbe { path: &segments, names: &mut names });
+
+    let mut described = String::new();
+    for (position, segment) in segments.iter().enumerate() {
+        match (names.get(position).copied().flatten(), segment) {
+            (None, Segment::Seq { index }) => described.push_str(&format!("[{}]", index)),
+            (name, segment) => {
+                if !described.is_empty() {
+                    described.push('.');
+                }
+                match name {
+                    Some(name) => described.push_str(name),
+                    None => described.push_str(&segment.to_string())
+                }
+            }
+        }
+    }
+    match described.is_empty() {
+        true => path.to_string(),
+        false => described
+    }
+}
+
+
+/// The error that ends a walk of a contract type, every walk ends in an error once the path runs out.
+///
+/// # Returns
+/// * `de::value::Error` - The error.
+fn end_of_path() -> de::value::Error {
+    de::Error::custom("end of the path")
+}
+
+
+/// A deserializer that walks a contract type along a path and records the name of the field or variant at each
+/// segment. Structs are entered through `visit_map` so the field on the path can be given without the fields
+/// before it, and sequences through their first element as every element has the same type.
+///
+/// # Fields
+/// * `path` - The segments of the path that are left to walk.
+/// * `names` - The name resolved for each segment walked so far, `None` for positions that have no name.
+struct PathProbe<'a, 'p> {
+    path: &'a [&'p Segment],
+    names: &'a mut Vec<Option<&'static str>>,
+}
+
+impl<'de> Deserializer<'de> for PathProbe<'_, '_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(end_of_path())
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.path.split_first() {
+            Some((Segment::Seq { .. }, path)) => {
+                self.names.push(None);
+                visitor.visit_seq(ProbeSeq { element: Some(PathProbe { path, names: self.names }) })
+            },
+            _ => Err(end_of_path())
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, Self::Error> {
+        match self.path.split_first() {
+            Some((Segment::Seq { index }, path)) if *index < fields.len() => {
+                self.names.push(Some(fields[*index]));
+                visitor.visit_map(ProbeStruct {
+                    field: Some(fields[*index]),
+                    value: Some(PathProbe { path, names: self.names })
+                })
+            },
+            _ => Err(end_of_path())
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, Self::Error> {
+        let (variant, path) = match self.path.split_first() {
+            Some((Segment::Enum { variant }, path)) => (variant, path),
+            _ => return Err(end_of_path())
+        };
+        // bincode records the index of the variant, the self-describing formats record its name
+        let variant = variant.parse::<usize>().ok()
+            .and_then(|index| variants.get(index))
+            .or_else(|| variants.iter().find(|name| *name == variant))
+            .copied()
+            .ok_or_else(end_of_path)?;
+        self.names.push(Some(variant));
+        visitor.visit_enum(ProbeEnum { variant, content: PathProbe { path, names: self.names } })
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf unit unit_struct
+        tuple tuple_struct map identifier ignored_any
+    }
+}
+
+
+/// Enters a struct on the path by giving its visitor the one field on the path.
+///
+/// # Fields
+/// * `field` - The name of the field on the path, taken once it has been given.
+/// * `value` - The walk of the rest of the path, taken once the value of the field is deserialized.
+struct ProbeStruct<'a, 'p> {
+    field: Option<&'static str>,
+    value: Option<PathProbe<'a, 'p>>,
+}
+
+impl<'de> MapAccess<'de> for ProbeStruct<'_, '_> {
+    type Error = de::value::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        match self.field.take() {
+            Some(field) => seed.deserialize(field.into_deserializer()).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
+        seed.deserialize(self.value.take().ok_or_else(end_of_path)?)
+    }
+}
+
+
+/// Enters a sequence on the path through its first element.
+///
+/// # Fields
+/// * `element` - The walk of the rest of the path, taken once the element is deserialized.
+struct ProbeSeq<'a, 'p> {
+    element: Option<PathProbe<'a, 'p>>,
+}
+
+impl<'de> SeqAccess<'de> for ProbeSeq<'_, '_> {
+    type Error = de::value::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error> {
+        match self.element.take() {
+            Some(element) => seed.deserialize(element).map(Some),
+            None => Ok(None)
+        }
+    }
+}
+
+
+/// Enters an enum on the path through the variant on the path.
+///
+/// # Fields
+/// * `variant` - The name of the variant on the path.
+/// * `content` - The walk of the rest of the path through the content of the variant.
+struct ProbeEnum<'a, 'p> {
+    variant: &'static str,
+    content: PathProbe<'a, 'p>,
+}
+
+impl<'de, 'a, 'p> EnumAccess<'de> for ProbeEnum<'a, 'p> {
+    type Error = de::value::Error;
+    type Variant = PathProbe<'a, 'p>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, self.content))
+    }
+}
+
+impl<'de> VariantAccess<'de> for PathProbe<'_, '_> {
+    type Error = de::value::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Err(end_of_path())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(end_of_path())
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_struct("", fields, visitor)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde::{Serialize, Deserialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Person {
+        pub name: String,
+        pub address: Address,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Address {
+        pub street: String,
+        pub number: u32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct OldPerson {
+        pub name: String,
+        pub address: OldAddress,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct OldAddress {
+        pub street: String,
+    }
+
+    #[test]
+    fn test_deserialize_bincode() {
+        let person = Person {
+            name: "John".to_string(),
+            address: Address { street: "Main".to_string(), number: 3 },
+        };
+        let bytes = bincode::serialize(&person).unwrap();
+        assert_eq!(deserialize_bincode::<Person>(&bytes).unwrap(), person);
+        // the output matches the default bincode options
+        assert_eq!(deserialize_bincode::<Person>(&bytes).unwrap(), bincode::deserialize::<Person>(&bytes).unwrap());
+    }
+
+    #[test]
+    fn test_missing_field_is_named() {
+        let old_person = OldPerson {
+            name: "John".to_string(),
+            address: OldAddress { street: "Main".to_string() },
+        };
+        let bytes = bincode::serialize(&old_person).unwrap();
+        let error = deserialize_bincode::<Person>(&bytes).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+        assert!(error.message.starts_with("Failed to deserialize contract at `address.number`"), "{}", error.message);
+    }
+
+    #[test]
+    fn test_field_in_sequence_is_named() {
+        let old_people = vec![
+            OldPerson { name: "John".to_string(), address: OldAddress { street: "Main".to_string() } },
+        ];
+        let bytes = bincode::serialize(&old_people).unwrap();
+        let error = deserialize_bincode::<Vec<Person>>(&bytes).unwrap_err();
+        assert!(error.message.starts_with("Failed to deserialize contract at `[0].address.number`"), "{}", error.message);
+    }
+
+    #[test]
+    fn test_bitcode_error_names_type() {
+        let bytes = bitcode::encode(&true);
+        let error = decode_bitcode::<String>(&bytes).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+        assert!(error.message.starts_with("Failed to deserialize contract at `alloc::string::String`"), "{}", error.message);
+    }
+
+}
@@ -1,5 +1,11 @@
 //! This module handles wrappers and codecs for serialization and deserialization of messages.
 pub mod bit_codec;
 pub mod codec;
+pub mod field_path;
 pub mod version_codec;
+pub mod zero_copy_codec;
 pub mod wrappers;
+
+
+/// The default maximum length of a single message in bytes that the codecs and wrappers will send or receive.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
@@ -4,16 +4,41 @@ use bytes::{BufMut, BytesMut};
 use std::{io, marker::PhantomData};
 use serde::Serialize;
 use revision::Revisioned;
+use bincode::Options;
+use crate::networking::serialization::codec::check_message_size;
+use crate::networking::serialization::DEFAULT_MAX_MESSAGE_SIZE;
 
 
 /// A codec that serializes and deserializes data using the bincode format for framing.
+///
+/// # Fields
+/// * `max_len` - The maximum length of a serialized contract in bytes, `DEFAULT_MAX_MESSAGE_SIZE` by default.
 pub struct VersionedBincodeCodec<T> {
+    max_len: usize,
     phantom: PhantomData<T>,
 }
 
 impl<T> VersionedBincodeCodec<T> {
     pub fn new() -> Self {
-        VersionedBincodeCodec { phantom: PhantomData }
+        VersionedBincodeCodec { max_len: DEFAULT_MAX_MESSAGE_SIZE, phantom: PhantomData }
+    }
+
+    /// Sets the maximum length of a serialized contract that the codec will encode or decode.
+    ///
+    /// # Arguments
+    /// * `max_len` - The maximum length in bytes.
+    ///
+    /// # Returns
+    /// * `VersionedBincodeCodec<T>` - The codec with the new maximum length.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+}
+
+impl<T> Default for VersionedBincodeCodec<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -25,9 +50,16 @@ where
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        bincode::deserialize(&src[..]).map(Some).map_err(|e| {
+        check_message_size(src.len(), self.max_len, io::ErrorKind::InvalidData)?;
+        // the limit also bounds the lengths of the collections inside the contract so they cannot request a
+        // larger allocation than the maximum message size
+        let options = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(self.max_len as u64);
+        options.deserialize(&src[..]).map(Some).map_err(|e| {
             eprintln!("Decode failed: {:?}", e);
-            io::Error::new(io::ErrorKind::Other, "deserialize failed")
+            io::Error::other("deserialize failed")
         })
     }
 }
@@ -41,8 +73,9 @@ where
     fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Self::Error> {
         let encoded = bincode::serialize(&item).map_err(|e| {
             eprintln!("Encode failed: {:?}", e);
-            io::Error::new(io::ErrorKind::Other, "serialize failed")
+            io::Error::other("serialize failed")
         })?;
+        check_message_size(encoded.len(), self.max_len, io::ErrorKind::InvalidInput)?;
         dst.reserve(encoded.len());
         dst.put_slice(&encoded);
         Ok(())
@@ -50,6 +83,44 @@ where
 }
 
 
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use revision::revisioned;
+
+    #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+    #[revisioned(revision = 1)]
+    struct TestStruct {
+        field1: Vec<u8>,
+    }
+
+    #[test]
+    fn test_max_len() {
+        let mut codec = VersionedBincodeCodec::<TestStruct>::new().with_max_len(16);
+        let mut buf = BytesMut::new();
+        codec.encode(TestStruct { field1: vec![1, 2] }, &mut buf).unwrap();
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(TestStruct { field1: vec![1, 2] }));
+
+        let error = codec.encode(TestStruct { field1: vec![0; 16] }, &mut BytesMut::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let mut buf = BytesMut::from(&[0; 17][..]);
+        let error = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_decode_rejects_oversized_inner_length() {
+        let mut codec = VersionedBincodeCodec::<TestStruct>::new().with_max_len(16);
+        // a vector that claims to hold far more elements than the message could contain
+        let mut buf = BytesMut::new();
+        buf.put_u64_le(u64::MAX);
+        assert!(codec.decode(&mut buf).is_err());
+    }
+}
+
+
 
 
 // #[cfg(test)]
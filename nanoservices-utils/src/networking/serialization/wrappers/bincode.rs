@@ -1,159 +1,28 @@
 //! The wrapper for wrapping messages that are serialized using the `bincode` crate for sending over a network.
 use serde::{Serialize, de::DeserializeOwned};
 use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
-use std::io::{Read, Write};
-use tokio::io::{AsyncWriteExt, AsyncReadExt};
+use super::format::{ContractFormat, ContractWrapper};
+use crate::networking::serialization::field_path::deserialize_bincode;
 
 
-/// The wrapper for wrapping messages that are serialized using the `bincode` crate for sending over a network.
-/// 
-/// # Fields
-/// * `header_bytes` - The bytes of the header that contains the length of the contract.
-/// * `contract_bytes` - The bytes of the contract.
-/// * `header` - The length of the contract (in byte form).
-/// * `contract` - The contract.
-pub struct BincodeContractWrapper<T: Serialize + DeserializeOwned> {
-    header_bytes: Option<[u8; 4]>,
-    contract_bytes: Option<Vec<u8>>,
-    pub header: Option<u32>,
-    pub contract: Option<T>,
-}
+/// The `bincode` serialization format. Failed deserialization reports the path of the field that failed.
+pub struct Bincode;
 
-impl <T: Serialize + DeserializeOwned> BincodeContractWrapper<T> {
-
-    /// Constructs a new `BincodeContractWrapper` for when you are sending a contract.
-    /// Refer to the `empty` function if you want to create a wrapper for receiving a contract.
-    /// 
-    /// # Arguments
-    /// * `contract` - The contract to send.
-    /// 
-    /// # Returns
-    /// * `Result<BincodeContractWrapper<T>, NanoServiceError>` - The new `BincodeContractWrapper`.
-    pub fn new(contract: T) -> Result<Self, NanoServiceError> {
-        let contract_bytes: Vec<u8> = bincode::serialize(&contract).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?;
-        let length = contract_bytes.len() as u32;
-        let header_bytes_buffer: Vec<u8> = bincode::serialize(&length).map_err(|e| {
+impl ContractFormat for Bincode {
+    fn serialize<T: Serialize>(contract: &T) -> Result<Vec<u8>, NanoServiceError> {
+        bincode::serialize(contract).map_err(|e| {
             NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?;
-        
-        if header_bytes_buffer.len() != 4 {
-            return Err(NanoServiceError::new("Header bytes length is not 4.".to_string(), NanoServiceErrorStatus::BadRequest));
-        }
-        let header_bytes: [u8; 4] = [
-            header_bytes_buffer[0],
-            header_bytes_buffer[1],
-            header_bytes_buffer[2],
-            header_bytes_buffer[3],
-        ];
-        Ok(BincodeContractWrapper {
-            header_bytes: Some(header_bytes),
-            contract_bytes: Some(contract_bytes),
-            header: None,
-            contract: None,
         })
     }
 
-    /// Constructs an empty `BincodeContractWrapper` for when you are receiving a contract. This
-    /// means that everything is empty so bytes from the TCP connection can be read into the wrapper.
-    /// For sending a contract, use the `new` function.
-    /// 
-    /// # Returns
-    /// * `BincodeContractWrapper<T>` - The empty `BincodeContractWrapper`.
-    pub fn empty() -> Self {
-        BincodeContractWrapper {
-            header_bytes: None,
-            contract_bytes: None,
-            header: None,
-            contract: None,
-        }
-    }
-
-    /// Sends the contract over a blocking stream.
-    /// 
-    /// # Arguments
-    /// * `stream` - The stream to send the contract over.
-    pub fn blocking_send<X: Write>(&self, stream: &mut X) -> Result<(), NanoServiceError> {
-        let header_bytes = self.header_bytes.unwrap();
-        let contract_bytes = self.contract_bytes.as_ref().unwrap();
-        stream.write_all(&header_bytes).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?;
-        stream.write_all(&contract_bytes).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?;
-        Ok(())
-    }
-
-    /// Receives the contract over a blocking stream.
-    /// 
-    /// # Notes
-    /// `self.header`, and `self.contract` will be populated with the values from the stream.
-    /// 
-    /// # Arguments
-    /// * `stream` - The stream to receive the contract from.
-    pub fn blocking_receive<X: Read>(&mut self, stream: &mut X) -> Result<(), NanoServiceError> {
-        let mut header_buffer = [0; 4];
-        stream.read_exact(&mut header_buffer).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?;
-        let header = bincode::deserialize::<u32>(&header_buffer).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?;
-        let mut contract_buffer = vec![0; header as usize];
-        stream.read_exact(&mut contract_buffer).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?;
-        self.header = Some(header);
-        self.contract = Some(bincode::deserialize::<T>(&contract_buffer).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?);
-        Ok(())
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, NanoServiceError> {
+        deserialize_bincode::<T>(bytes)
     }
+}
 
-    /// Sends the contract over an async stream.
-    /// 
-    /// # Arguments
-    /// * `stream` - The stream to send the contract over.
-    pub async fn async_send<X: AsyncWriteExt + std::marker::Unpin>(&self, stream: &mut X) -> Result<(), NanoServiceError> {
-        let header_bytes = self.header_bytes.unwrap();
-        let contract_bytes = self.contract_bytes.as_ref().unwrap();
-        stream.write_all(&header_bytes).await.map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?;
-        stream.write_all(&contract_bytes).await.map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?;
-        Ok(())
-    }
 
-    /// Receives the contract over an async stream.
-    /// 
-    /// # Notes
-    /// `self.header`, and `self.contract` will be populated with the values from the stream.
-    /// 
-    /// # Arguments
-    /// * `stream` - The stream to receive the contract from.
-    pub async fn async_receive<X: AsyncReadExt + std::marker::Unpin>(&mut self, stream: &mut X) -> Result<(), NanoServiceError> {
-        let mut header_buffer = [0; 4];
-        stream.read_exact(&mut header_buffer).await.map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?;
-        let header = bincode::deserialize::<u32>(&header_buffer).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?;
-        let mut contract_buffer = vec![0; header as usize];
-        stream.read_exact(&mut contract_buffer).await.map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?;
-        self.header = Some(header);
-        self.contract = Some(bincode::deserialize::<T>(&contract_buffer).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?);
-        Ok(())
-    }
-}
+/// The wrapper for wrapping messages that are serialized using the `bincode` crate for sending over a network.
+pub type BincodeContractWrapper<T> = ContractWrapper<Bincode, T>;
 
 
 #[cfg(test)]
@@ -186,10 +55,12 @@ mod tests {
         );
     }
 
+    // the round trips over TCP route the contracts with `register_contract_routes`, which needs the TCP feature
+    #[cfg(feature = "tcp-messaging")]
     mod server {
-        use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+        use crate::errors::NanoServiceError;
         use super::kernel::ContractHandler;
-        use super::kernel::ContractOne;
+        use super::ContractOne;
         use super::kernel::ContractTwo;
         use super::super::BincodeContractWrapper;
         use crate::register_contract_routes;
@@ -216,27 +87,31 @@ mod tests {
         pub async fn tcp_server(addr: &str) {
             let listener = TcpListener::bind(addr).await.unwrap();
 
-            while let Ok((mut socket, _)) = listener.accept().await {
+            if let Ok((mut socket, _)) = listener.accept().await {
+                // one wrapper receives every contract on the connection until the client hangs up
                 let mut recieving_wrapper = BincodeContractWrapper::<ContractHandler>::empty();
-                recieving_wrapper.async_receive(&mut socket).await.unwrap();
-                let contract = recieving_wrapper.contract.unwrap();
-                let response = match handle_contract(contract).await {
-                    Ok(response) => response,
-                    Err(e) => {
-                        ContractHandler::NanoServiceError(e)
-                    }
-                
-                };
-                let sending_wrapper = BincodeContractWrapper::new(response).unwrap();
-                sending_wrapper.async_send(&mut socket).await.unwrap();
-                break;
+                while recieving_wrapper.async_receive(&mut socket).await.is_ok() {
+                    let contract = recieving_wrapper.contract.take().unwrap();
+                    let response = match handle_contract(contract).await {
+                        Ok(response) => response,
+                        Err(e) => {
+                            ContractHandler::NanoServiceError(e)
+                        }
+                    };
+                    let sending_wrapper = BincodeContractWrapper::new(response).unwrap();
+                    sending_wrapper.async_send(&mut socket).await.unwrap();
+                }
             }
         }
     }
 
-    use kernel::{ContractHandler, ContractOne};
+    use kernel::ContractOne;
+    #[cfg(feature = "tcp-messaging")]
+    use kernel::ContractHandler;
+    #[cfg(feature = "tcp-messaging")]
     use server::tcp_server;
 
+    #[cfg(feature = "tcp-messaging")]
     use tokio::runtime::Builder;
 
     #[test]
@@ -248,19 +123,55 @@ mod tests {
         let wrapper = BincodeContractWrapper::new(contract.clone()).unwrap();
 
         // test the general contents
-        assert_eq!(wrapper.header_bytes.is_some(), true);
-        assert_eq!(wrapper.contract_bytes.is_some(), true);
-        assert_eq!(wrapper.header.is_none(), true);
-        assert_eq!(wrapper.contract.is_none(), true);
+        assert!(wrapper.header_bytes.is_some());
+        assert!(wrapper.contract_bytes.is_some());
+        assert!(wrapper.header.is_none());
+        assert!(wrapper.contract.is_none());
         assert_eq!([16, 0, 0, 0], wrapper.header_bytes.unwrap());
 
         // test the deserialization and if the header is correct
-        let deserialized_contract = bincode::deserialize::<ContractOne>(&wrapper.contract_bytes.as_ref().unwrap()).unwrap();
+        let deserialized_contract = bincode::deserialize::<ContractOne>(wrapper.contract_bytes.as_ref().unwrap()).unwrap();
         let deserialized_header = bincode::deserialize::<u32>(&wrapper.header_bytes.unwrap()).unwrap();
         assert_eq!(contract, deserialized_contract);
         assert_eq!(deserialized_header, wrapper.contract_bytes.unwrap().len() as u32);
     }
 
+    #[cfg(feature = "tcp-messaging")]
+    #[test]
+    fn test_multiple_contracts_over_one_stream() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let port = 8121;
+            let address = format!("127.0.0.1:{}", port);
+            let _server = tokio::spawn(tcp_server("127.0.0.1:8121"));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+            for age in 0..3 {
+                let contract = ContractHandler::ContractOne(ContractOne {
+                    name: "John".to_string(),
+                    age,
+                });
+                BincodeContractWrapper::new(contract).unwrap().async_send(&mut stream).await.unwrap();
+            }
+
+            let mut receiver = BincodeContractWrapper::<ContractHandler>::empty();
+            for age in 0..3 {
+                receiver.async_receive(&mut stream).await.unwrap();
+                let expected_contract = ContractHandler::ContractOne(ContractOne {
+                    name: "John".to_string(),
+                    age: age + 1,
+                });
+                assert_eq!(receiver.contract.take().unwrap(), expected_contract);
+            }
+        });
+    }
+
+    #[cfg(feature = "tcp-messaging")]
     #[test]
     fn test_async_send_over_tcp() {
         let runtime = Builder::new_multi_thread()
@@ -292,6 +203,7 @@ mod tests {
         });
     }
 
+    #[cfg(feature = "tcp-messaging")]
     #[test]
     fn test_blocking_over_tcp() {
         let runtime = Builder::new_multi_thread()
@@ -2,6 +2,11 @@
 //! WARNING: bitcode favours speed and compact size over stability, always test every contract before using it in production.
 //! bitcode can even break between Rust versions.
 use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use super::padding::{Padding, unpad};
+#[cfg(feature = "compression")]
+use super::compression::{compress, compressed_header, decompress, split_header, DEFAULT_COMPRESSION_THRESHOLD};
+use crate::networking::serialization::DEFAULT_MAX_MESSAGE_SIZE;
+use crate::networking::utils::check_payload_size;
 use std::io::{Read, Write};
 use tokio::io::{AsyncWriteExt, AsyncReadExt};
 use bitcode::{Encode, DecodeOwned};
@@ -12,7 +17,7 @@ use bitcode::{Encode, DecodeOwned};
 /// # Notes
 /// - `bitcode` favours speed and compact size over stability, always test every contract before using it in production.
 /// - `pre_header_bytes` field is needed because `bitcode` serialization of a `u32` value can vary from 2 bytes to 5 bytes
-///    depending on the size of the integer, therefore, the length of the header bytes is needed to extract the header bytes.
+///   depending on the size of the integer, therefore, the length of the header bytes is needed to extract the header bytes.
 /// 
 /// # Fields
 /// * `pre_header_bytes` - The bytes that represent the length of the header bytes.
@@ -21,6 +26,9 @@ use bitcode::{Encode, DecodeOwned};
 /// * `pre_header` - The length of the header bytes.
 /// * `header` - The length of the contract bytes (in byte form).
 /// * `contract` - The contract.
+/// * `padding` - How the contract bytes are padded, `None` if they are not padded.
+/// * `max_len` - The maximum length of the contract bytes, `DEFAULT_MAX_MESSAGE_SIZE` by default.
+/// * `compressed` - Whether the contract bytes sent or last received are compressed with zstd.
 pub struct BitcodeContractWrapper<T: Encode + DecodeOwned> {
     pre_header_bytes: Option<[u8; 1]>,
     header_bytes: Option<Vec<u8>>,
@@ -28,6 +36,10 @@ pub struct BitcodeContractWrapper<T: Encode + DecodeOwned> {
     pub pre_header: Option<u8>,
     pub header: Option<u32>,
     pub contract: Option<T>,
+    pub padding: Option<Padding>,
+    max_len: usize,
+    #[cfg(feature = "compression")]
+    pub compressed: bool,
 }
 
 impl <T: Encode + DecodeOwned> BitcodeContractWrapper<T> {
@@ -42,7 +54,20 @@ impl <T: Encode + DecodeOwned> BitcodeContractWrapper<T> {
     /// * `Result<BitcodeContractWrapper<T>, NanoServiceError>` - The new `BitcodeContractWrapper`.
     pub fn new(contract: T) -> Result<Self, NanoServiceError> {
         let contract_bytes: Vec<u8> = bitcode::encode(&contract);
-        let length = contract_bytes.len() as u32;
+        Self::with_contract_bytes(contract_bytes)
+    }
+
+    /// Builds the headers for serialized contract bytes that are ready to be sent.
+    ///
+    /// # Arguments
+    /// * `contract_bytes` - The bytes to be sent after the headers.
+    ///
+    /// # Returns
+    /// * `Result<BitcodeContractWrapper<T>, NanoServiceError>` - The wrapper ready to send the bytes.
+    fn with_contract_bytes(contract_bytes: Vec<u8>) -> Result<Self, NanoServiceError> {
+        let length = u32::try_from(contract_bytes.len()).map_err(|_| {
+            NanoServiceError::new("Contract is too large for a 4 byte header.".to_string(), NanoServiceErrorStatus::PayloadTooLarge)
+        })?;
         let header_bytes: Vec<u8> = bitcode::encode(&length);
         
         let mut pre_header_bytes = [0; 1];
@@ -55,6 +80,10 @@ impl <T: Encode + DecodeOwned> BitcodeContractWrapper<T> {
             pre_header: None,
             header: None,
             contract: None,
+            padding: None,
+            max_len: DEFAULT_MAX_MESSAGE_SIZE,
+            #[cfg(feature = "compression")]
+            compressed: false,
         })
     }
 
@@ -72,9 +101,197 @@ impl <T: Encode + DecodeOwned> BitcodeContractWrapper<T> {
             pre_header: None,
             header: None,
             contract: None,
+            padding: None,
+            max_len: DEFAULT_MAX_MESSAGE_SIZE,
+            #[cfg(feature = "compression")]
+            compressed: false,
+        }
+    }
+
+    /// Constructs a new `BitcodeContractWrapper` for sending a contract with the contract bytes padded up to a bucket
+    /// size so the exact size of the contract is not leaked. The receiver must use `empty_padded` with padding.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to send.
+    /// * `padding` - How the contract bytes are padded.
+    ///
+    /// # Returns
+    /// * `Result<BitcodeContractWrapper<T>, NanoServiceError>` - The new `BitcodeContractWrapper`.
+    pub fn new_padded(contract: T, padding: Padding) -> Result<Self, NanoServiceError> {
+        let contract_bytes: Vec<u8> = bitcode::encode(&contract);
+        let mut wrapper = Self::with_contract_bytes(padding.pad(&contract_bytes)?)?;
+        wrapper.padding = Some(padding);
+        Ok(wrapper)
+    }
+
+    /// Constructs an empty `BitcodeContractWrapper` for receiving a contract that was sent with `new_padded`.
+    ///
+    /// # Arguments
+    /// * `padding` - How the contract bytes are padded.
+    ///
+    /// # Returns
+    /// * `BitcodeContractWrapper<T>` - The empty `BitcodeContractWrapper`.
+    pub fn empty_padded(padding: Padding) -> Self {
+        let mut wrapper = Self::empty();
+        wrapper.padding = Some(padding);
+        wrapper
+    }
+
+    /// Constructs a new `BitcodeContractWrapper` for sending a contract with the contract bytes compressed with zstd if
+    /// they are at least `DEFAULT_COMPRESSION_THRESHOLD` bytes. Compression suits contracts with large payloads, and
+    /// the header flags compressed contract bytes so any receiver decompresses them without being told to.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to send.
+    ///
+    /// # Returns
+    /// * `Result<BitcodeContractWrapper<T>, NanoServiceError>` - The new `BitcodeContractWrapper`.
+    #[cfg(feature = "compression")]
+    pub fn new_compressed(contract: T) -> Result<Self, NanoServiceError> {
+        Self::new_compressed_with_threshold(contract, DEFAULT_COMPRESSION_THRESHOLD)
+    }
+
+    /// Constructs a new `BitcodeContractWrapper` for sending a contract with the contract bytes compressed with zstd if
+    /// they are at least `threshold` bytes, otherwise they are sent uncompressed.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to send.
+    /// * `threshold` - The size in bytes the serialized contract has to reach to be compressed.
+    ///
+    /// # Returns
+    /// * `Result<BitcodeContractWrapper<T>, NanoServiceError>` - The new `BitcodeContractWrapper`.
+    #[cfg(feature = "compression")]
+    pub fn new_compressed_with_threshold(contract: T, threshold: usize) -> Result<Self, NanoServiceError> {
+        let contract_bytes: Vec<u8> = bitcode::encode(&contract);
+        if contract_bytes.len() < threshold {
+            return Self::with_contract_bytes(contract_bytes)
+        }
+        let compressed_bytes = compress(&contract_bytes)?;
+        let header_bytes: Vec<u8> = bitcode::encode(&compressed_header(compressed_bytes.len())?);
+        let mut wrapper = Self::with_contract_bytes(compressed_bytes)?;
+        wrapper.pre_header_bytes = Some([header_bytes.len() as u8]);
+        wrapper.header_bytes = Some(header_bytes);
+        wrapper.compressed = true;
+        Ok(wrapper)
+    }
+
+    /// Constructs an empty `BitcodeContractWrapper` for receiving a contract that was sent with `new_compressed`. This
+    /// is the same as `empty`, which decompresses any contract that is flagged as compressed.
+    ///
+    /// # Returns
+    /// * `BitcodeContractWrapper<T>` - The empty `BitcodeContractWrapper`.
+    #[cfg(feature = "compression")]
+    pub fn empty_compressed() -> Self {
+        Self::empty()
+    }
+
+    /// Sets the maximum length of the contract bytes that the wrapper will send or receive. The header of a received
+    /// contract is checked against it before a buffer is allocated for the contract bytes.
+    ///
+    /// # Arguments
+    /// * `max_len` - The maximum length in bytes.
+    ///
+    /// # Returns
+    /// * `BitcodeContractWrapper<T>` - The wrapper with the new maximum length.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Clears the state of the last received contract so the wrapper can receive the next contract on the stream.
+    ///
+    /// # Notes
+    /// Only `pre_header`, `header`, and `contract` are cleared, the contract bytes to send and the padding,
+    /// compression, and maximum length settings are kept. The receive functions call this before reading, so a
+    /// wrapper can receive in a loop.
+    pub fn reset(&mut self) {
+        self.pre_header = None;
+        self.header = None;
+        self.contract = None;
+    }
+
+    /// Reads the length of the contract bytes from a received header and checks it against the maximum length.
+    /// With the `compression` feature the compressed flag is taken off the length and recorded in `compressed`.
+    ///
+    /// # Arguments
+    /// * `header` - The received header.
+    ///
+    /// # Returns
+    /// * `Result<u32, NanoServiceError>` - The length of the contract bytes.
+    fn accept_header(&mut self, header: u32) -> Result<u32, NanoServiceError> {
+        #[cfg(feature = "compression")]
+        let header = {
+            let (length, compressed) = split_header(header);
+            self.compressed = compressed;
+            length
+        };
+        check_payload_size(header as usize, self.max_len)?;
+        Ok(header)
+    }
+
+    /// Decodes the received contract bytes, decompressing them if the header flagged them as compressed or trimming
+    /// the padding if the wrapper is padded.
+    ///
+    /// # Arguments
+    /// * `contract_buffer` - The contract bytes read from the stream.
+    ///
+    /// # Returns
+    /// * `Result<T, NanoServiceError>` - The decoded contract.
+    fn decode_received(&self, contract_buffer: &[u8]) -> Result<T, NanoServiceError> {
+        #[cfg(feature = "compression")]
+        if self.compressed {
+            return Self::decode_body(&decompress(contract_buffer, self.max_len)?)
+        }
+        match self.padding {
+            Some(_) => Self::decode_body(unpad(contract_buffer)?),
+            None => Self::decode_body(contract_buffer)
         }
     }
 
+    /// Constructs a `BitcodeContractWrapper` from the raw contract bytes of a body whose length is already known (e.g.
+    /// from an HTTP `Content-Length`), so there is no header to read.
+    ///
+    /// # Notes
+    /// `self.header`, and `self.contract` will be populated from the bytes. The wrapper cannot be sent as it has no
+    /// header bytes.
+    ///
+    /// # Arguments
+    /// * `bytes` - The contract bytes without a header.
+    ///
+    /// # Returns
+    /// * `Result<BitcodeContractWrapper<T>, NanoServiceError>` - The wrapper holding the decoded contract.
+    pub fn from_known_length_bytes(bytes: Vec<u8>) -> Result<Self, NanoServiceError> {
+        let header = u32::try_from(bytes.len()).map_err(|_| {
+            NanoServiceError::new("Contract is too large for a 4 byte header.".to_string(), NanoServiceErrorStatus::PayloadTooLarge)
+        })?;
+        let contract = Self::decode_body(&bytes)?;
+        Ok(BitcodeContractWrapper {
+            pre_header_bytes: None,
+            header_bytes: None,
+            contract_bytes: Some(bytes),
+            pre_header: None,
+            header: Some(header),
+            contract: Some(contract),
+            padding: None,
+            max_len: DEFAULT_MAX_MESSAGE_SIZE,
+            #[cfg(feature = "compression")]
+            compressed: false,
+        })
+    }
+
+    /// Decodes the raw contract bytes of a body whose length is already known without any header framing.
+    ///
+    /// # Arguments
+    /// * `bytes` - The contract bytes without a header.
+    ///
+    /// # Returns
+    /// * `Result<T, NanoServiceError>` - The decoded contract.
+    pub fn decode_body(bytes: &[u8]) -> Result<T, NanoServiceError> {
+        bitcode::decode::<T>(bytes).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })
+    }
+
     /// Sends the contract over a blocking stream.
     /// 
     /// # Arguments
@@ -84,16 +301,17 @@ impl <T: Encode + DecodeOwned> BitcodeContractWrapper<T> {
         let pre_header_bytes = self.pre_header_bytes.unwrap();
         let header_bytes = self.header_bytes.as_ref().unwrap();
         let contract_bytes = self.contract_bytes.as_ref().unwrap();
+        check_payload_size(contract_bytes.len(), self.max_len)?;
 
         // send the bytes to the stream
         stream.write_all(&pre_header_bytes).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
         })?;
-        stream.write_all(&header_bytes).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        stream.write_all(header_bytes).map_err(|e| {
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
         })?;
-        stream.write_all(&contract_bytes).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        stream.write_all(contract_bytes).map_err(|e| {
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
         })?;
         Ok(())
     }
@@ -106,10 +324,11 @@ impl <T: Encode + DecodeOwned> BitcodeContractWrapper<T> {
     /// # Arguments
     /// * `stream` - The stream to receive the contract from.
     pub fn blocking_receive<X: Read>(&mut self, stream: &mut X) -> Result<(), NanoServiceError> {
+        self.reset();
         // extract the preheader
         let mut pre_header_buffer = [0; 1];
         stream.read_exact(&mut pre_header_buffer).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
         })?;
         let pre_header = bitcode::decode::<u8>(&pre_header_buffer).map_err(|e| {
             NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
@@ -119,21 +338,20 @@ impl <T: Encode + DecodeOwned> BitcodeContractWrapper<T> {
         // extract the header to get the length of the contract
         let mut header_buffer = vec![0; pre_header as usize];
         stream.read_exact(&mut header_buffer).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
         })?;
         let header = bitcode::decode::<u32>(&header_buffer).map_err(|e| {
             NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
         })?;
 
         // extract the contract
+        let header = self.accept_header(header)?;
         let mut contract_buffer = vec![0; header as usize];
         stream.read_exact(&mut contract_buffer).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
         })?;
         self.header = Some(header);
-        self.contract = Some(bitcode::decode::<T>(&contract_buffer).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?);
+        self.contract = Some(self.decode_received(&contract_buffer)?);
         Ok(())
     }
 
@@ -146,15 +364,16 @@ impl <T: Encode + DecodeOwned> BitcodeContractWrapper<T> {
         let pre_header_bytes = self.pre_header_bytes.unwrap();
         let header_bytes = self.header_bytes.as_ref().unwrap();
         let contract_bytes = self.contract_bytes.as_ref().unwrap();
+        check_payload_size(contract_bytes.len(), self.max_len)?;
 
         stream.write_all(&pre_header_bytes).await.map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
         })?;
-        stream.write_all(&header_bytes).await.map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        stream.write_all(header_bytes).await.map_err(|e| {
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
         })?;
-        stream.write_all(&contract_bytes).await.map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        stream.write_all(contract_bytes).await.map_err(|e| {
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
         })?;
         Ok(())
     }
@@ -167,10 +386,11 @@ impl <T: Encode + DecodeOwned> BitcodeContractWrapper<T> {
     /// # Arguments
     /// * `stream` - The stream to receive the contract from.
     pub async fn async_receive<X: AsyncReadExt + std::marker::Unpin>(&mut self, stream: &mut X) -> Result<(), NanoServiceError> {
+        self.reset();
         // extract the preheader
         let mut pre_header_buffer = [0; 1];
         stream.read_exact(&mut pre_header_buffer).await.map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
         })?;
         let pre_header = bitcode::decode::<u8>(&pre_header_buffer).map_err(|e| {
             NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
@@ -180,21 +400,20 @@ impl <T: Encode + DecodeOwned> BitcodeContractWrapper<T> {
         // extract the header to get the length of the contract
         let mut header_buffer = vec![0; pre_header as usize];
         stream.read_exact(&mut header_buffer).await.map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
         })?;
         let header = bitcode::decode::<u32>(&header_buffer).map_err(|e| {
             NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
         })?;
 
         // extract the contract
+        let header = self.accept_header(header)?;
         let mut contract_buffer = vec![0; header as usize];
         stream.read_exact(&mut contract_buffer).await.map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
         })?;
         self.header = Some(header);
-        self.contract = Some(bitcode::decode::<T>(&contract_buffer).map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-        })?);
+        self.contract = Some(self.decode_received(&contract_buffer)?);
         Ok(())
     }
 }
@@ -231,10 +450,12 @@ mod tests {
         );
     }
 
+    // the round trips over TCP route the contracts with `register_contract_routes`, which needs the TCP feature
+    #[cfg(feature = "tcp-messaging")]
     mod server {
-        use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+        use crate::errors::NanoServiceError;
         use super::kernel::ContractHandler;
-        use super::kernel::ContractOne;
+        use super::ContractOne;
         use super::kernel::ContractTwo;
         use super::super::BitcodeContractWrapper;
         use crate::register_contract_routes;
@@ -261,27 +482,29 @@ mod tests {
         pub async fn tcp_server(addr: &str) {
             let listener = TcpListener::bind(addr).await.unwrap();
 
-            while let Ok((mut socket, _)) = listener.accept().await {
+            if let Ok((mut socket, _)) = listener.accept().await {
+                // one wrapper receives every contract on the connection until the client hangs up
                 let mut recieving_wrapper = BitcodeContractWrapper::<ContractHandler>::empty();
-                recieving_wrapper.async_receive(&mut socket).await.unwrap();
-                let contract = recieving_wrapper.contract.unwrap();
-                let response = match handle_contract(contract).await {
-                    Ok(response) => response,
-                    Err(e) => {
-                        ContractHandler::NanoServiceError(e)
-                    }
-                
-                };
-                let sending_wrapper = BitcodeContractWrapper::new(response).unwrap();
-                sending_wrapper.async_send(&mut socket).await.unwrap();
-                break;
+                while recieving_wrapper.async_receive(&mut socket).await.is_ok() {
+                    let contract = recieving_wrapper.contract.take().unwrap();
+                    let response = match handle_contract(contract).await {
+                        Ok(response) => response,
+                        Err(e) => {
+                            ContractHandler::NanoServiceError(e)
+                        }
+                    };
+                    let sending_wrapper = BitcodeContractWrapper::new(response).unwrap();
+                    sending_wrapper.async_send(&mut socket).await.unwrap();
+                }
             }
         }
     }
 
     use kernel::{ContractHandler, ContractOne};
+    #[cfg(feature = "tcp-messaging")]
     use server::tcp_server;
 
+    #[cfg(feature = "tcp-messaging")]
     use tokio::runtime::Builder;
 
     #[test]
@@ -293,19 +516,198 @@ mod tests {
         let wrapper = BitcodeContractWrapper::new(contract.clone()).unwrap();
 
         // test the general contents
-        assert_eq!(wrapper.header_bytes.is_some(), true);
-        assert_eq!(wrapper.contract_bytes.is_some(), true);
-        assert_eq!(wrapper.header.is_none(), true);
-        assert_eq!(wrapper.contract.is_none(), true);
+        assert!(wrapper.header_bytes.is_some());
+        assert!(wrapper.contract_bytes.is_some());
+        assert!(wrapper.header.is_none());
+        assert!(wrapper.contract.is_none());
         // assert_eq!([16, 0, 0, 0], wrapper.header_bytes.unwrap());
 
         // test the deserialization and if the header is correct
-        let deserialized_contract = bitcode::decode::<ContractOne>(&wrapper.contract_bytes.as_ref().unwrap()).unwrap();
+        let deserialized_contract = bitcode::decode::<ContractOne>(wrapper.contract_bytes.as_ref().unwrap()).unwrap();
         let deserialized_header = bitcode::decode::<u32>(&wrapper.header_bytes.unwrap()).unwrap();
         assert_eq!(contract, deserialized_contract);
         assert_eq!(deserialized_header, wrapper.contract_bytes.unwrap().len() as u32);
     }
 
+    #[test]
+    fn test_error_contract_round_trip() {
+        let error = NanoServiceError::new(
+            "Test error".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        );
+        let wrapper = BitcodeContractWrapper::new(ContractHandler::NanoServiceError(error.clone())).unwrap();
+        let mut buffer: Vec<u8> = Vec::new();
+        wrapper.blocking_send(&mut buffer).unwrap();
+
+        let mut receiving_wrapper = BitcodeContractWrapper::<ContractHandler>::empty();
+        receiving_wrapper.blocking_receive(&mut buffer.as_slice()).unwrap();
+        assert_eq!(receiving_wrapper.contract.unwrap().NanoServiceError().unwrap(), error);
+    }
+
+    #[test]
+    fn test_decode_body_without_header() {
+        let contract = ContractOne {
+            name: "John".to_string(),
+            age: 32,
+        };
+        let body = bitcode::encode(&contract);
+
+        let decoded = BitcodeContractWrapper::<ContractOne>::decode_body(&body).unwrap();
+        assert_eq!(decoded, contract);
+
+        let wrapper = BitcodeContractWrapper::<ContractOne>::from_known_length_bytes(body.clone()).unwrap();
+        assert_eq!(wrapper.header, Some(body.len() as u32));
+        assert_eq!(wrapper.contract, Some(contract));
+
+        let error = BitcodeContractWrapper::<ContractOne>::decode_body(&body[..1]).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+    }
+
+    #[test]
+    fn test_padded_contracts_share_wire_length() {
+        let short_contract = ContractOne {
+            name: "Jo".to_string(),
+            age: 32,
+        };
+        let long_contract = ContractOne {
+            name: "Johnathan Smith".to_string(),
+            age: 32,
+        };
+        let padding = Padding::Bucket(64);
+
+        let mut short_wire = Vec::new();
+        BitcodeContractWrapper::new_padded(short_contract.clone(), padding).unwrap().blocking_send(&mut short_wire).unwrap();
+        let mut long_wire = Vec::new();
+        BitcodeContractWrapper::new_padded(long_contract.clone(), padding).unwrap().blocking_send(&mut long_wire).unwrap();
+        assert_eq!(short_wire.len(), long_wire.len());
+
+        let mut receiver = BitcodeContractWrapper::<ContractOne>::empty_padded(padding);
+        receiver.blocking_receive(&mut short_wire.as_slice()).unwrap();
+        assert_eq!(receiver.contract, Some(short_contract));
+
+        let mut receiver = BitcodeContractWrapper::<ContractOne>::empty_padded(padding);
+        receiver.blocking_receive(&mut long_wire.as_slice()).unwrap();
+        assert_eq!(receiver.contract, Some(long_contract));
+    }
+
+    #[test]
+    fn test_max_len() {
+        let contract = ContractOne {
+            name: "John".to_string(),
+            age: 32,
+        };
+        let mut wire = Vec::new();
+        let error = BitcodeContractWrapper::new(contract.clone()).unwrap().with_max_len(4).blocking_send(&mut wire).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+        assert!(wire.is_empty());
+
+        // the header advertises far more bytes than will ever be sent, it is rejected before they are allocated
+        let header = bitcode::encode(&u32::MAX);
+        let mut wire = vec![header.len() as u8];
+        wire.extend_from_slice(&header);
+        let mut receiver = BitcodeContractWrapper::<ContractOne>::empty();
+        let error = receiver.blocking_receive(&mut wire.as_slice()).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+        assert_eq!(receiver.contract, None);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compressed_round_trip() {
+        let contract = ContractOne {
+            name: "John ".repeat(200),
+            age: 32,
+        };
+        let mut plain_wire = Vec::new();
+        BitcodeContractWrapper::new(contract.clone()).unwrap().blocking_send(&mut plain_wire).unwrap();
+        let mut compressed_wire = Vec::new();
+        BitcodeContractWrapper::new_compressed(contract.clone()).unwrap().blocking_send(&mut compressed_wire).unwrap();
+        assert!(compressed_wire.len() < plain_wire.len());
+
+        let mut receiver = BitcodeContractWrapper::<ContractOne>::empty();
+        receiver.blocking_receive(&mut compressed_wire.as_slice()).unwrap();
+        assert_eq!(receiver.contract, Some(contract));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compression_threshold() {
+        let contract = ContractOne {
+            name: "John".to_string(),
+            age: 32,
+        };
+        let mut plain_wire = Vec::new();
+        BitcodeContractWrapper::new(contract.clone()).unwrap().blocking_send(&mut plain_wire).unwrap();
+        let mut small_wire = Vec::new();
+        BitcodeContractWrapper::new_compressed(contract.clone()).unwrap().blocking_send(&mut small_wire).unwrap();
+        assert_eq!(small_wire, plain_wire);
+
+        let mut compressed_wire = Vec::new();
+        BitcodeContractWrapper::new_compressed_with_threshold(contract.clone(), 0).unwrap()
+            .blocking_send(&mut compressed_wire).unwrap();
+        assert_ne!(compressed_wire, plain_wire);
+
+        let mut receiver = BitcodeContractWrapper::<ContractOne>::empty();
+        receiver.blocking_receive(&mut compressed_wire.as_slice()).unwrap();
+        assert!(receiver.compressed);
+        assert_eq!(receiver.contract, Some(contract));
+    }
+
+    #[test]
+    fn test_reset() {
+        let contract = ContractOne {
+            name: "John".to_string(),
+            age: 32,
+        };
+        let mut wire = Vec::new();
+        BitcodeContractWrapper::new(contract.clone()).unwrap().blocking_send(&mut wire).unwrap();
+
+        let mut receiver = BitcodeContractWrapper::<ContractOne>::empty();
+        receiver.blocking_receive(&mut wire.as_slice()).unwrap();
+        assert_eq!(receiver.contract, Some(contract));
+
+        // a failed receive does not leave the previous contract behind
+        assert!(receiver.blocking_receive(&mut &wire[..2]).is_err());
+        assert_eq!(receiver.header, None);
+        assert_eq!(receiver.contract, None);
+    }
+
+    #[cfg(feature = "tcp-messaging")]
+    #[test]
+    fn test_multiple_contracts_over_one_stream() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let port = 8122;
+            let address = format!("127.0.0.1:{}", port);
+            let _server = tokio::spawn(tcp_server("127.0.0.1:8122"));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+            for age in 0..3 {
+                let contract = ContractHandler::ContractOne(ContractOne {
+                    name: "John".to_string(),
+                    age,
+                });
+                BitcodeContractWrapper::new(contract).unwrap().async_send(&mut stream).await.unwrap();
+            }
+
+            let mut receiver = BitcodeContractWrapper::<ContractHandler>::empty();
+            for age in 0..3 {
+                receiver.async_receive(&mut stream).await.unwrap();
+                let expected_contract = ContractHandler::ContractOne(ContractOne {
+                    name: "John".to_string(),
+                    age: age + 1,
+                });
+                assert_eq!(receiver.contract.take().unwrap(), expected_contract);
+            }
+        });
+    }
+
+    #[cfg(feature = "tcp-messaging")]
     #[test]
     fn test_async_send_over_tcp() {
         let runtime = Builder::new_multi_thread()
@@ -337,6 +739,7 @@ mod tests {
         });
     }
 
+    #[cfg(feature = "tcp-messaging")]
     #[test]
     fn test_blocking_over_tcp() {
         let runtime = Builder::new_multi_thread()
@@ -0,0 +1,146 @@
+//! Defines the compression of contract bytes with zstd for contracts with large payloads when bandwidth is the
+//! bottleneck. Compressed contract bytes are laid out as the uncompressed length of the contract (4 bytes, little
+//! endian) followed by the zstd frame. The header of the wrapper holds the compressed length so the framing of the
+//! wrappers does not change, and the uncompressed length lets the receiver check the contract against its maximum
+//! message size and allocate the buffer for it once. Compressed contract bytes are flagged by the top bit of the
+//! length header, so a receiver decompresses a contract only if the flag is set and does not need to know in advance
+//! whether the sender compressed it. Contracts under a size threshold are sent uncompressed with the flag clear, as
+//! compressing small payloads costs more time than it saves bytes.
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use crate::networking::utils::check_payload_size;
+
+
+/// The number of bytes used to record the uncompressed length of the contract.
+const UNCOMPRESSED_LENGTH_BYTES: usize = 4;
+
+/// The size in bytes a serialized contract has to reach before it is compressed, when a threshold is not given.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 512;
+
+/// The bit of the length header that flags the contract bytes as compressed.
+pub(crate) const COMPRESSED_FLAG: u32 = 1 << 31;
+
+
+/// Builds the length header of compressed contract bytes.
+///
+/// # Arguments
+/// * `length` - The length of the compressed contract bytes.
+///
+/// # Returns
+/// * `Result<u32, NanoServiceError>` - The header with the compressed flag set, or a `PayloadTooLarge` error if the
+///   length needs the bit used by the flag.
+pub(crate) fn compressed_header(length: usize) -> Result<u32, NanoServiceError> {
+    match u32::try_from(length) {
+        Ok(length) if length & COMPRESSED_FLAG == 0 => Ok(length | COMPRESSED_FLAG),
+        _ => Err(NanoServiceError::new(
+            "Compressed contract is too large for a 4 byte header.".to_string(),
+            NanoServiceErrorStatus::PayloadTooLarge
+        ))
+    }
+}
+
+
+/// Splits a received length header into the length of the contract bytes and the compressed flag.
+///
+/// # Arguments
+/// * `header` - The received header.
+///
+/// # Returns
+/// * `(u32, bool)` - The length of the contract bytes and whether they are compressed.
+pub(crate) fn split_header(header: u32) -> (u32, bool) {
+    (header & !COMPRESSED_FLAG, header & COMPRESSED_FLAG != 0)
+}
+
+
+/// Compresses the contract bytes.
+///
+/// # Arguments
+/// * `contract_bytes` - The serialized contract.
+///
+/// # Returns
+/// * `Result<Vec<u8>, NanoServiceError>` - The compressed bytes with the uncompressed length prefix.
+pub fn compress(contract_bytes: &[u8]) -> Result<Vec<u8>, NanoServiceError> {
+    check_payload_size(contract_bytes.len(), u32::MAX as usize)?;
+    let frame = zstd::bulk::compress(contract_bytes, zstd::DEFAULT_COMPRESSION_LEVEL).map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+    })?;
+    let mut compressed = Vec::with_capacity(UNCOMPRESSED_LENGTH_BYTES + frame.len());
+    compressed.extend_from_slice(&(contract_bytes.len() as u32).to_le_bytes());
+    compressed.extend_from_slice(&frame);
+    Ok(compressed)
+}
+
+
+/// Decompresses compressed contract bytes.
+///
+/// # Arguments
+/// * `compressed` - The compressed bytes with the uncompressed length prefix.
+/// * `max_len` - The maximum uncompressed length of the contract in bytes.
+///
+/// # Returns
+/// * `Result<Vec<u8>, NanoServiceError>` - The contract bytes, or a `PayloadTooLarge` error if the contract is
+///   longer than `max_len`.
+pub fn decompress(compressed: &[u8], max_len: usize) -> Result<Vec<u8>, NanoServiceError> {
+    if compressed.len() < UNCOMPRESSED_LENGTH_BYTES {
+        return Err(NanoServiceError::new(
+            "Compressed contract is missing the uncompressed length".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ))
+    }
+    let (length_bytes, frame) = compressed.split_at(UNCOMPRESSED_LENGTH_BYTES);
+    let uncompressed_length = u32::from_le_bytes([length_bytes[0], length_bytes[1], length_bytes[2], length_bytes[3]]) as usize;
+    check_payload_size(uncompressed_length, max_len)?;
+
+    // the frame cannot decompress to more than the advertised length, so a small frame cannot expand without bound
+    let contract_bytes = zstd::bulk::decompress(frame, uncompressed_length).map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+    })?;
+    if contract_bytes.len() != uncompressed_length {
+        return Err(NanoServiceError::new(
+            "Compressed contract is shorter than its uncompressed length".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ))
+    }
+    Ok(contract_bytes)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_compress_and_decompress() {
+        let contract_bytes = "a large string payload ".repeat(100).into_bytes();
+        let compressed = compress(&contract_bytes).unwrap();
+        assert!(compressed.len() < contract_bytes.len());
+        assert_eq!(&compressed[..UNCOMPRESSED_LENGTH_BYTES], (contract_bytes.len() as u32).to_le_bytes());
+        assert_eq!(decompress(&compressed, contract_bytes.len()).unwrap(), contract_bytes);
+
+        let error = decompress(&compressed, contract_bytes.len() - 1).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+    }
+
+    #[test]
+    fn test_decompress_rejects_understated_length() {
+        let contract_bytes = vec![0; 1024];
+        let mut compressed = compress(&contract_bytes).unwrap();
+        // the frame expands past the length it claims, which is refused rather than allocated
+        compressed[..UNCOMPRESSED_LENGTH_BYTES].copy_from_slice(&16u32.to_le_bytes());
+        assert_eq!(decompress(&compressed, 2048).unwrap_err().status, NanoServiceErrorStatus::BadRequest);
+
+        compressed[..UNCOMPRESSED_LENGTH_BYTES].copy_from_slice(&2000u32.to_le_bytes());
+        assert_eq!(decompress(&compressed, 2048).unwrap_err().status, NanoServiceErrorStatus::BadRequest);
+
+        assert_eq!(decompress(&[1, 0], 2048).unwrap_err().status, NanoServiceErrorStatus::BadRequest);
+    }
+
+    #[test]
+    fn test_compressed_header() {
+        let header = compressed_header(100).unwrap();
+        assert_eq!(split_header(header), (100, true));
+        assert_eq!(split_header(100), (100, false));
+        let error = compressed_header(COMPRESSED_FLAG as usize).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+    }
+}
@@ -0,0 +1,557 @@
+//! Defines the wrapper shared by the serialization formats that frame a contract as its length (4 bytes, little
+//! endian) followed by the serialized contract. The framing, padding, compression, and maximum length are the same
+//! for every such format, so a format only says how a contract is serialized and deserialized by implementing
+//! `ContractFormat`, and each format module exposes an alias of `ContractWrapper` for its format.
+use serde::{Serialize, de::DeserializeOwned};
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use super::padding::{Padding, unpad};
+#[cfg(feature = "compression")]
+use super::compression::{compress, compressed_header, decompress, split_header, DEFAULT_COMPRESSION_THRESHOLD};
+use crate::networking::serialization::DEFAULT_MAX_MESSAGE_SIZE;
+use crate::networking::utils::check_payload_size;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use tokio::io::{AsyncWriteExt, AsyncReadExt};
+
+
+/// A serialization format that contracts can be wrapped with by `ContractWrapper`.
+pub trait ContractFormat {
+
+    /// Serializes a contract.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to serialize.
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>, NanoServiceError>` - The contract bytes.
+    fn serialize<T: Serialize>(contract: &T) -> Result<Vec<u8>, NanoServiceError>;
+
+    /// Deserializes a contract.
+    ///
+    /// # Arguments
+    /// * `bytes` - The contract bytes.
+    ///
+    /// # Returns
+    /// * `Result<T, NanoServiceError>` - The contract.
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, NanoServiceError>;
+}
+
+
+/// The wrapper for wrapping messages that are serialized with a `ContractFormat` for sending over a network. Use the
+/// alias of a format (e.g. `BincodeContractWrapper`) rather than naming the format.
+/// 
+/// # Fields
+/// * `header_bytes` - The bytes of the header that contains the length of the contract.
+/// * `contract_bytes` - The bytes of the contract.
+/// * `header` - The length of the contract (in byte form).
+/// * `contract` - The contract.
+/// * `padding` - How the contract bytes are padded, `None` if they are not padded.
+/// * `max_len` - The maximum length of the contract bytes, `DEFAULT_MAX_MESSAGE_SIZE` by default.
+/// * `compressed` - Whether the contract bytes sent or last received are compressed with zstd.
+/// * `format` - The format the contract is serialized with.
+pub struct ContractWrapper<F: ContractFormat, T: Serialize + DeserializeOwned> {
+    pub(super) header_bytes: Option<[u8; 4]>,
+    pub(super) contract_bytes: Option<Vec<u8>>,
+    pub header: Option<u32>,
+    pub contract: Option<T>,
+    pub padding: Option<Padding>,
+    max_len: usize,
+    #[cfg(feature = "compression")]
+    pub compressed: bool,
+    format: PhantomData<F>,
+}
+
+impl <F: ContractFormat, T: Serialize + DeserializeOwned> ContractWrapper<F, T> {
+
+    /// Constructs a new `ContractWrapper` for when you are sending a contract.
+    /// Refer to the `empty` function if you want to create a wrapper for receiving a contract.
+    /// 
+    /// # Arguments
+    /// * `contract` - The contract to send.
+    /// 
+    /// # Returns
+    /// * `Result<ContractWrapper<F, T>, NanoServiceError>` - The new `ContractWrapper`.
+    pub fn new(contract: T) -> Result<Self, NanoServiceError> {
+        let contract_bytes = F::serialize(&contract)?;
+        Self::with_contract_bytes(contract_bytes)
+    }
+
+    /// Builds the header for serialized contract bytes that are ready to be sent.
+    ///
+    /// # Arguments
+    /// * `contract_bytes` - The bytes to be sent after the header.
+    ///
+    /// # Returns
+    /// * `Result<ContractWrapper<F, T>, NanoServiceError>` - The wrapper ready to send the bytes.
+    fn with_contract_bytes(contract_bytes: Vec<u8>) -> Result<Self, NanoServiceError> {
+        let length = u32::try_from(contract_bytes.len()).map_err(|_| {
+            NanoServiceError::new("Contract is too large for a 4 byte header.".to_string(), NanoServiceErrorStatus::PayloadTooLarge)
+        })?;
+        let header_bytes = length.to_le_bytes();
+        Ok(ContractWrapper {
+            header_bytes: Some(header_bytes),
+            contract_bytes: Some(contract_bytes),
+            header: None,
+            contract: None,
+            padding: None,
+            max_len: DEFAULT_MAX_MESSAGE_SIZE,
+            #[cfg(feature = "compression")]
+            compressed: false,
+            format: PhantomData,
+        })
+    }
+
+    /// Constructs an empty `ContractWrapper` for when you are receiving a contract. This
+    /// means that everything is empty so bytes from the TCP connection can be read into the wrapper.
+    /// For sending a contract, use the `new` function.
+    /// 
+    /// # Returns
+    /// * `ContractWrapper<F, T>` - The empty `ContractWrapper`.
+    pub fn empty() -> Self {
+        ContractWrapper {
+            header_bytes: None,
+            contract_bytes: None,
+            header: None,
+            contract: None,
+            padding: None,
+            max_len: DEFAULT_MAX_MESSAGE_SIZE,
+            #[cfg(feature = "compression")]
+            compressed: false,
+            format: PhantomData,
+        }
+    }
+
+    /// Constructs a new `ContractWrapper` for sending a contract with the contract bytes padded up to a bucket
+    /// size so the exact size of the contract is not leaked. The receiver must use `empty_padded` with padding.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to send.
+    /// * `padding` - How the contract bytes are padded.
+    ///
+    /// # Returns
+    /// * `Result<ContractWrapper<F, T>, NanoServiceError>` - The new `ContractWrapper`.
+    pub fn new_padded(contract: T, padding: Padding) -> Result<Self, NanoServiceError> {
+        let contract_bytes = F::serialize(&contract)?;
+        let mut wrapper = Self::with_contract_bytes(padding.pad(&contract_bytes)?)?;
+        wrapper.padding = Some(padding);
+        Ok(wrapper)
+    }
+
+    /// Constructs an empty `ContractWrapper` for receiving a contract that was sent with `new_padded`.
+    ///
+    /// # Arguments
+    /// * `padding` - How the contract bytes are padded.
+    ///
+    /// # Returns
+    /// * `ContractWrapper<F, T>` - The empty `ContractWrapper`.
+    pub fn empty_padded(padding: Padding) -> Self {
+        let mut wrapper = Self::empty();
+        wrapper.padding = Some(padding);
+        wrapper
+    }
+
+    /// Constructs a new `ContractWrapper` for sending a contract with the contract bytes compressed with zstd if they
+    /// are at least `DEFAULT_COMPRESSION_THRESHOLD` bytes. Compression suits contracts with large payloads, and the
+    /// header flags compressed contract bytes so any receiver decompresses them without being told to.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to send.
+    ///
+    /// # Returns
+    /// * `Result<ContractWrapper<F, T>, NanoServiceError>` - The new `ContractWrapper`.
+    #[cfg(feature = "compression")]
+    pub fn new_compressed(contract: T) -> Result<Self, NanoServiceError> {
+        Self::new_compressed_with_threshold(contract, DEFAULT_COMPRESSION_THRESHOLD)
+    }
+
+    /// Constructs a new `ContractWrapper` for sending a contract with the contract bytes compressed with zstd if they
+    /// are at least `threshold` bytes, otherwise they are sent uncompressed.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to send.
+    /// * `threshold` - The size in bytes the serialized contract has to reach to be compressed.
+    ///
+    /// # Returns
+    /// * `Result<ContractWrapper<F, T>, NanoServiceError>` - The new `ContractWrapper`.
+    #[cfg(feature = "compression")]
+    pub fn new_compressed_with_threshold(contract: T, threshold: usize) -> Result<Self, NanoServiceError> {
+        let contract_bytes = F::serialize(&contract)?;
+        if contract_bytes.len() < threshold {
+            return Self::with_contract_bytes(contract_bytes)
+        }
+        let compressed_bytes = compress(&contract_bytes)?;
+        let header = compressed_header(compressed_bytes.len())?;
+        let mut wrapper = Self::with_contract_bytes(compressed_bytes)?;
+        wrapper.header_bytes = Some(header.to_le_bytes());
+        wrapper.compressed = true;
+        Ok(wrapper)
+    }
+
+    /// Constructs an empty `ContractWrapper` for receiving a contract that was sent with `new_compressed`. This is the
+    /// same as `empty`, which decompresses any contract that is flagged as compressed.
+    ///
+    /// # Returns
+    /// * `ContractWrapper<F, T>` - The empty `ContractWrapper`.
+    #[cfg(feature = "compression")]
+    pub fn empty_compressed() -> Self {
+        Self::empty()
+    }
+
+    /// Sets the maximum length of the contract bytes that the wrapper will send or receive. The header of a received
+    /// contract is checked against it before a buffer is allocated for the contract bytes.
+    ///
+    /// # Arguments
+    /// * `max_len` - The maximum length in bytes.
+    ///
+    /// # Returns
+    /// * `ContractWrapper<F, T>` - The wrapper with the new maximum length.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Clears the state of the last received contract so the wrapper can receive the next contract on the stream.
+    ///
+    /// # Notes
+    /// Only `header` and `contract` are cleared, the contract bytes to send and the padding, compression, and maximum
+    /// length settings are kept. The receive functions call this before reading, so a wrapper can receive in a loop.
+    pub fn reset(&mut self) {
+        self.header = None;
+        self.contract = None;
+    }
+
+    /// Reads the length of the contract bytes from a received header and checks it against the maximum length.
+    /// With the `compression` feature the compressed flag is taken off the length and recorded in `compressed`.
+    ///
+    /// # Arguments
+    /// * `header` - The received header.
+    ///
+    /// # Returns
+    /// * `Result<u32, NanoServiceError>` - The length of the contract bytes.
+    fn accept_header(&mut self, header: u32) -> Result<u32, NanoServiceError> {
+        #[cfg(feature = "compression")]
+        let header = {
+            let (length, compressed) = split_header(header);
+            self.compressed = compressed;
+            length
+        };
+        check_payload_size(header as usize, self.max_len)?;
+        Ok(header)
+    }
+
+    /// Decodes the received contract bytes, decompressing them if the header flagged them as compressed or trimming
+    /// the padding if the wrapper is padded.
+    ///
+    /// # Arguments
+    /// * `contract_buffer` - The contract bytes read from the stream.
+    ///
+    /// # Returns
+    /// * `Result<T, NanoServiceError>` - The decoded contract.
+    fn decode_received(&self, contract_buffer: &[u8]) -> Result<T, NanoServiceError> {
+        #[cfg(feature = "compression")]
+        if self.compressed {
+            return Self::decode_body(&decompress(contract_buffer, self.max_len)?)
+        }
+        match self.padding {
+            Some(_) => Self::decode_body(unpad(contract_buffer)?),
+            None => Self::decode_body(contract_buffer)
+        }
+    }
+
+    /// Constructs a `ContractWrapper` from the raw contract bytes of a body whose length is already known (e.g.
+    /// from an HTTP `Content-Length`), so there is no header to read.
+    ///
+    /// # Notes
+    /// `self.header`, and `self.contract` will be populated from the bytes. The wrapper cannot be sent as it has no
+    /// header bytes.
+    ///
+    /// # Arguments
+    /// * `bytes` - The contract bytes without a header.
+    ///
+    /// # Returns
+    /// * `Result<ContractWrapper<F, T>, NanoServiceError>` - The wrapper holding the decoded contract.
+    pub fn from_known_length_bytes(bytes: Vec<u8>) -> Result<Self, NanoServiceError> {
+        let header = u32::try_from(bytes.len()).map_err(|_| {
+            NanoServiceError::new("Contract is too large for a 4 byte header.".to_string(), NanoServiceErrorStatus::PayloadTooLarge)
+        })?;
+        let contract = Self::decode_body(&bytes)?;
+        Ok(ContractWrapper {
+            header_bytes: None,
+            contract_bytes: Some(bytes),
+            header: Some(header),
+            contract: Some(contract),
+            padding: None,
+            max_len: DEFAULT_MAX_MESSAGE_SIZE,
+            #[cfg(feature = "compression")]
+            compressed: false,
+            format: PhantomData,
+        })
+    }
+
+    /// Decodes the raw contract bytes of a body whose length is already known without any header framing.
+    ///
+    /// # Arguments
+    /// * `bytes` - The contract bytes without a header.
+    ///
+    /// # Returns
+    /// * `Result<T, NanoServiceError>` - The decoded contract.
+    pub fn decode_body(bytes: &[u8]) -> Result<T, NanoServiceError> {
+        F::deserialize::<T>(bytes)
+    }
+
+    /// Sends the contract over a blocking stream.
+    /// 
+    /// # Arguments
+    /// * `stream` - The stream to send the contract over.
+    pub fn blocking_send<X: Write>(&self, stream: &mut X) -> Result<(), NanoServiceError> {
+        let header_bytes = self.header_bytes.unwrap();
+        let contract_bytes = self.contract_bytes.as_ref().unwrap();
+        check_payload_size(contract_bytes.len(), self.max_len)?;
+        stream.write_all(&header_bytes).map_err(|e| {
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
+        })?;
+        stream.write_all(contract_bytes).map_err(|e| {
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
+        })?;
+        Ok(())
+    }
+
+    /// Receives the contract over a blocking stream.
+    /// 
+    /// # Notes
+    /// `self.header`, and `self.contract` will be populated with the values from the stream.
+    /// 
+    /// # Arguments
+    /// * `stream` - The stream to receive the contract from.
+    pub fn blocking_receive<X: Read>(&mut self, stream: &mut X) -> Result<(), NanoServiceError> {
+        self.reset();
+        let mut header_buffer = [0; 4];
+        stream.read_exact(&mut header_buffer).map_err(|e| {
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
+        })?;
+        let header = self.accept_header(u32::from_le_bytes(header_buffer))?;
+        let mut contract_buffer = vec![0; header as usize];
+        stream.read_exact(&mut contract_buffer).map_err(|e| {
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
+        })?;
+        self.header = Some(header);
+        self.contract = Some(self.decode_received(&contract_buffer)?);
+        Ok(())
+    }
+
+    /// Sends the contract over an async stream.
+    /// 
+    /// # Arguments
+    /// * `stream` - The stream to send the contract over.
+    pub async fn async_send<X: AsyncWriteExt + std::marker::Unpin>(&self, stream: &mut X) -> Result<(), NanoServiceError> {
+        let header_bytes = self.header_bytes.unwrap();
+        let contract_bytes = self.contract_bytes.as_ref().unwrap();
+        check_payload_size(contract_bytes.len(), self.max_len)?;
+        stream.write_all(&header_bytes).await.map_err(|e| {
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
+        })?;
+        stream.write_all(contract_bytes).await.map_err(|e| {
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
+        })?;
+        Ok(())
+    }
+
+    /// Receives the contract over an async stream.
+    /// 
+    /// # Notes
+    /// `self.header`, and `self.contract` will be populated with the values from the stream.
+    /// 
+    /// # Arguments
+    /// * `stream` - The stream to receive the contract from.
+    pub async fn async_receive<X: AsyncReadExt + std::marker::Unpin>(&mut self, stream: &mut X) -> Result<(), NanoServiceError> {
+        self.reset();
+        let mut header_buffer = [0; 4];
+        stream.read_exact(&mut header_buffer).await.map_err(|e| {
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
+        })?;
+        let header = self.accept_header(u32::from_le_bytes(header_buffer))?;
+        let mut contract_buffer = vec![0; header as usize];
+        stream.read_exact(&mut contract_buffer).await.map_err(|e| {
+            NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
+        })?;
+        self.header = Some(header);
+        self.contract = Some(self.decode_received(&contract_buffer)?);
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::super::bincode::BincodeContractWrapper;
+
+    mod kernel {
+        use crate::create_contract_handler;
+        use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+        use serde::{Serialize, Deserialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+        pub struct ContractOne {
+            pub name: String,
+            pub age: i32,
+        }
+
+        create_contract_handler!(
+            ContractHandler,
+            ContractOne
+        );
+    }
+
+    use kernel::{ContractHandler, ContractOne};
+
+    #[test]
+    fn test_decode_body_without_header() {
+        let contract = ContractOne {
+            name: "John".to_string(),
+            age: 32,
+        };
+        let body = bincode::serialize(&contract).unwrap();
+
+        let decoded = BincodeContractWrapper::<ContractOne>::decode_body(&body).unwrap();
+        assert_eq!(decoded, contract);
+
+        let wrapper = BincodeContractWrapper::<ContractOne>::from_known_length_bytes(body.clone()).unwrap();
+        assert_eq!(wrapper.header, Some(body.len() as u32));
+        assert_eq!(wrapper.contract, Some(contract));
+
+        let error = BincodeContractWrapper::<ContractOne>::decode_body(&body[..1]).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+    }
+
+    #[test]
+    fn test_max_len() {
+        let contract = ContractOne {
+            name: "John".to_string(),
+            age: 32,
+        };
+        let mut wire = Vec::new();
+        let error = BincodeContractWrapper::new(contract.clone()).unwrap().with_max_len(8).blocking_send(&mut wire).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+        assert!(wire.is_empty());
+
+        // the header advertises far more bytes than will ever be sent, it is rejected before they are allocated
+        let wire = u32::MAX.to_le_bytes();
+        let mut receiver = BincodeContractWrapper::<ContractOne>::empty();
+        let error = receiver.blocking_receive(&mut wire.as_slice()).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+        assert_eq!(receiver.contract, None);
+
+        let mut wire = Vec::new();
+        BincodeContractWrapper::new(contract.clone()).unwrap().blocking_send(&mut wire).unwrap();
+        let mut receiver = BincodeContractWrapper::<ContractOne>::empty().with_max_len(8);
+        let error = receiver.blocking_receive(&mut wire.as_slice()).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+    }
+
+    #[test]
+    fn test_reset() {
+        let contract = ContractOne {
+            name: "John".to_string(),
+            age: 32,
+        };
+        let mut wire = Vec::new();
+        BincodeContractWrapper::new(contract.clone()).unwrap().blocking_send(&mut wire).unwrap();
+
+        let mut receiver = BincodeContractWrapper::<ContractOne>::empty();
+        receiver.blocking_receive(&mut wire.as_slice()).unwrap();
+        assert_eq!(receiver.contract, Some(contract));
+
+        // a failed receive does not leave the previous contract behind
+        assert!(receiver.blocking_receive(&mut &wire[..2]).is_err());
+        assert_eq!(receiver.header, None);
+        assert_eq!(receiver.contract, None);
+    }
+
+    #[test]
+    fn test_error_contract_round_trip() {
+        let error = NanoServiceError::new(
+            "Test error".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        );
+        let wrapper = BincodeContractWrapper::new(ContractHandler::NanoServiceError(error.clone())).unwrap();
+        let mut buffer: Vec<u8> = Vec::new();
+        wrapper.blocking_send(&mut buffer).unwrap();
+
+        let mut receiving_wrapper = BincodeContractWrapper::<ContractHandler>::empty();
+        receiving_wrapper.blocking_receive(&mut buffer.as_slice()).unwrap();
+        assert_eq!(receiving_wrapper.contract.unwrap().NanoServiceError().unwrap(), error);
+    }
+
+    #[test]
+    fn test_padded_contracts_share_wire_length() {
+        let short_contract = ContractOne {
+            name: "Jo".to_string(),
+            age: 32,
+        };
+        let long_contract = ContractOne {
+            name: "Johnathan Smith".to_string(),
+            age: 32,
+        };
+        let padding = Padding::Bucket(64);
+
+        let mut short_wire = Vec::new();
+        BincodeContractWrapper::new_padded(short_contract.clone(), padding).unwrap().blocking_send(&mut short_wire).unwrap();
+        let mut long_wire = Vec::new();
+        BincodeContractWrapper::new_padded(long_contract.clone(), padding).unwrap().blocking_send(&mut long_wire).unwrap();
+        assert_eq!(short_wire.len(), long_wire.len());
+
+        let mut receiver = BincodeContractWrapper::<ContractOne>::empty_padded(padding);
+        receiver.blocking_receive(&mut short_wire.as_slice()).unwrap();
+        assert_eq!(receiver.contract, Some(short_contract));
+
+        let mut receiver = BincodeContractWrapper::<ContractOne>::empty_padded(padding);
+        receiver.blocking_receive(&mut long_wire.as_slice()).unwrap();
+        assert_eq!(receiver.contract, Some(long_contract));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compressed_round_trip() {
+        let contract = ContractOne {
+            name: "John ".repeat(200),
+            age: 32,
+        };
+        let mut plain_wire = Vec::new();
+        BincodeContractWrapper::new(contract.clone()).unwrap().blocking_send(&mut plain_wire).unwrap();
+        let mut compressed_wire = Vec::new();
+        BincodeContractWrapper::new_compressed(contract.clone()).unwrap().blocking_send(&mut compressed_wire).unwrap();
+        assert!(compressed_wire.len() < plain_wire.len());
+
+        let mut receiver = BincodeContractWrapper::<ContractOne>::empty();
+        receiver.blocking_receive(&mut compressed_wire.as_slice()).unwrap();
+        assert!(receiver.compressed);
+        assert_eq!(receiver.contract, Some(contract));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compression_threshold() {
+        let contract = ContractOne {
+            name: "John".to_string(),
+            age: 32,
+        };
+        let mut plain_wire = Vec::new();
+        BincodeContractWrapper::new(contract.clone()).unwrap().blocking_send(&mut plain_wire).unwrap();
+        let mut small_wire = Vec::new();
+        BincodeContractWrapper::new_compressed(contract.clone()).unwrap().blocking_send(&mut small_wire).unwrap();
+        assert_eq!(small_wire, plain_wire);
+
+        let mut compressed_wire = Vec::new();
+        BincodeContractWrapper::new_compressed_with_threshold(contract.clone(), 0).unwrap()
+            .blocking_send(&mut compressed_wire).unwrap();
+        assert_ne!(compressed_wire, plain_wire);
+
+        let mut receiver = BincodeContractWrapper::<ContractOne>::empty();
+        receiver.blocking_receive(&mut compressed_wire.as_slice()).unwrap();
+        assert!(receiver.compressed);
+        assert_eq!(receiver.contract, Some(contract));
+    }
+
+}
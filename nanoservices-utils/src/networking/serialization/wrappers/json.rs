@@ -0,0 +1,188 @@
+//! The wrapper for wrapping messages that are serialized as JSON (through the `serde_json` crate) for sending over a
+//! network. JSON is slower and larger than the binary formats, but the contract bytes on the wire can be read as they
+//! are, so this wrapper is for inspecting traffic (e.g. with `nc`) and for tooling that already speaks JSON. The
+//! framing is the same as the `BincodeContractWrapper`, a 4 byte little endian length followed by the contract.
+use serde::{Serialize, de::DeserializeOwned};
+use crate::errors::NanoServiceError;
+use super::format::{ContractFormat, ContractWrapper};
+
+
+/// The JSON serialization format.
+pub struct Json;
+
+impl ContractFormat for Json {
+    fn serialize<T: Serialize>(contract: &T) -> Result<Vec<u8>, NanoServiceError> {
+        Ok(serde_json::to_vec(contract)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, NanoServiceError> {
+        Ok(serde_json::from_slice::<T>(bytes)?)
+    }
+}
+
+
+/// The wrapper for wrapping messages that are serialized as JSON for sending over a network.
+pub type JsonContractWrapper<T> = ContractWrapper<Json, T>;
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    mod kernel {
+        use crate::create_contract_handler;
+        use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+        use serde::{Serialize, Deserialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+        pub struct ContractOne{
+            pub name: String,
+            pub age: i32,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct ContractTwo;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct ContractThree;
+
+        create_contract_handler!(
+            ContractHandler, 
+            ContractOne, 
+            ContractTwo, 
+            ContractThree
+        );
+    }
+
+    // the round trips over TCP route the contracts with `register_contract_routes`, which needs the TCP feature
+    #[cfg(feature = "tcp-messaging")]
+    mod server {
+        use crate::errors::NanoServiceError;
+        use super::kernel::ContractHandler;
+        use super::ContractOne;
+        use super::kernel::ContractTwo;
+        use super::super::JsonContractWrapper;
+        use crate::register_contract_routes;
+
+        use tokio::net::TcpListener;
+
+
+        async fn handle_test_contract_one(mut contract: ContractOne) -> Result<ContractOne, NanoServiceError> {
+            contract.age += 1;
+            Ok(contract)
+        }
+
+        async fn handle_test_contract_two(contract: ContractTwo) -> Result<ContractTwo, NanoServiceError> {
+            Ok(contract)
+        }
+
+        register_contract_routes!(
+            ContractHandler, 
+            handle_contract, 
+            ContractOne => handle_test_contract_one, 
+            ContractTwo => handle_test_contract_two
+        );
+
+        pub async fn tcp_server(addr: &str) {
+            let listener = TcpListener::bind(addr).await.unwrap();
+
+            if let Ok((mut socket, _)) = listener.accept().await {
+                let mut recieving_wrapper = JsonContractWrapper::<ContractHandler>::empty();
+                recieving_wrapper.async_receive(&mut socket).await.unwrap();
+                let contract = recieving_wrapper.contract.unwrap();
+                let response = match handle_contract(contract).await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        ContractHandler::NanoServiceError(e)
+                    }
+                };
+                let sending_wrapper = JsonContractWrapper::new(response).unwrap();
+                sending_wrapper.async_send(&mut socket).await.unwrap();
+            }
+        }
+    }
+
+    use kernel::ContractOne;
+    #[cfg(feature = "tcp-messaging")]
+    use kernel::ContractHandler;
+    #[cfg(feature = "tcp-messaging")]
+    use server::tcp_server;
+
+    #[cfg(feature = "tcp-messaging")]
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn test_contract_bytes_are_readable() {
+        let contract = ContractOne {
+            name: "John".to_string(),
+            age: 32,
+        };
+        let wrapper = JsonContractWrapper::new(contract).unwrap();
+        assert_eq!(wrapper.contract_bytes.unwrap(), br#"{"name":"John","age":32}"#);
+    }
+
+    #[cfg(feature = "tcp-messaging")]
+    #[test]
+    fn test_async_send_over_tcp() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let port = 8119;
+            let address = format!("127.0.0.1:{}", port);
+            let _server = tokio::spawn(tcp_server("127.0.0.1:8119"));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let contract = ContractHandler::ContractOne(ContractOne {
+                name: "John".to_string(),
+                age: 32,
+            });
+
+            let mut wrapper = JsonContractWrapper::new(contract).unwrap();
+            let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+            wrapper.async_send(&mut stream).await.unwrap();
+            wrapper.async_receive(&mut stream).await.unwrap();
+
+            let expected_contract = ContractHandler::ContractOne(ContractOne {
+                name: "John".to_string(),
+                age: 33,
+            });
+            assert_eq!(wrapper.contract.unwrap(), expected_contract);
+        });
+    }
+
+    #[cfg(feature = "tcp-messaging")]
+    #[test]
+    fn test_blocking_over_tcp() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let port = 8120;
+            let address = format!("127.0.0.1:{}", port);
+            let _server = tokio::spawn(tcp_server("127.0.0.1:8120"));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let contract = ContractHandler::ContractOne(ContractOne {
+                name: "John".to_string(),
+                age: 32,
+            });
+
+            let mut wrapper = JsonContractWrapper::new(contract).unwrap();
+            let mut stream = std::net::TcpStream::connect(address).unwrap();
+            wrapper.blocking_send(&mut stream).unwrap();
+            wrapper.blocking_receive(&mut stream).unwrap();
+
+            let expected_contract = ContractHandler::ContractOne(ContractOne {
+                name: "John".to_string(),
+                age: 33,
+            });
+            assert_eq!(wrapper.contract.unwrap(), expected_contract);
+        });
+    }
+}
@@ -0,0 +1,197 @@
+//! The wrapper for wrapping messages that are serialized using MessagePack (through the `rmp-serde` crate) for
+//! sending over a network. MessagePack has libraries for most languages, so this wrapper is for sending contracts
+//! to and from services that are not written in Rust. The framing is the same as the `BincodeContractWrapper`, a
+//! 4 byte little endian length followed by the contract. Structs are serialized as maps keyed by field name so
+//! consumers in other languages do not need to know the order of the fields.
+use serde::{Serialize, de::DeserializeOwned};
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use super::format::{ContractFormat, ContractWrapper};
+
+
+/// The MessagePack serialization format. Structs are serialized as maps keyed by field name.
+pub struct MessagePack;
+
+impl ContractFormat for MessagePack {
+    fn serialize<T: Serialize>(contract: &T) -> Result<Vec<u8>, NanoServiceError> {
+        rmp_serde::to_vec_named(contract).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })
+    }
+
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, NanoServiceError> {
+        rmp_serde::from_slice::<T>(bytes).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })
+    }
+}
+
+
+/// The wrapper for wrapping messages that are serialized using MessagePack for sending over a network.
+pub type MessagePackContractWrapper<T> = ContractWrapper<MessagePack, T>;
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    mod kernel {
+        use crate::create_contract_handler;
+        use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+        use serde::{Serialize, Deserialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+        pub struct ContractOne{
+            pub name: String,
+            pub age: i32,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct ContractTwo;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct ContractThree;
+
+        create_contract_handler!(
+            ContractHandler, 
+            ContractOne, 
+            ContractTwo, 
+            ContractThree
+        );
+    }
+
+    // the round trips over TCP route the contracts with `register_contract_routes`, which needs the TCP feature
+    #[cfg(feature = "tcp-messaging")]
+    mod server {
+        use crate::errors::NanoServiceError;
+        use super::kernel::ContractHandler;
+        use super::ContractOne;
+        use super::kernel::ContractTwo;
+        use super::super::MessagePackContractWrapper;
+        use crate::register_contract_routes;
+
+        use tokio::net::TcpListener;
+
+
+        async fn handle_test_contract_one(mut contract: ContractOne) -> Result<ContractOne, NanoServiceError> {
+            contract.age += 1;
+            Ok(contract)
+        }
+
+        async fn handle_test_contract_two(contract: ContractTwo) -> Result<ContractTwo, NanoServiceError> {
+            Ok(contract)
+        }
+
+        register_contract_routes!(
+            ContractHandler, 
+            handle_contract, 
+            ContractOne => handle_test_contract_one, 
+            ContractTwo => handle_test_contract_two
+        );
+
+        pub async fn tcp_server(addr: &str) {
+            let listener = TcpListener::bind(addr).await.unwrap();
+
+            if let Ok((mut socket, _)) = listener.accept().await {
+                let mut recieving_wrapper = MessagePackContractWrapper::<ContractHandler>::empty();
+                recieving_wrapper.async_receive(&mut socket).await.unwrap();
+                let contract = recieving_wrapper.contract.unwrap();
+                let response = match handle_contract(contract).await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        ContractHandler::NanoServiceError(e)
+                    }
+                };
+                let sending_wrapper = MessagePackContractWrapper::new(response).unwrap();
+                sending_wrapper.async_send(&mut socket).await.unwrap();
+            }
+        }
+    }
+
+    use kernel::ContractOne;
+    #[cfg(feature = "tcp-messaging")]
+    use kernel::ContractHandler;
+    #[cfg(feature = "tcp-messaging")]
+    use server::tcp_server;
+
+    #[cfg(feature = "tcp-messaging")]
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn test_contract_fields_are_named() {
+        let contract = ContractOne {
+            name: "John".to_string(),
+            age: 32,
+        };
+        let wrapper = MessagePackContractWrapper::new(contract).unwrap();
+
+        // a map of two entries (0x82) keyed by the field names
+        let contract_bytes = wrapper.contract_bytes.unwrap();
+        assert_eq!(contract_bytes[0], 0x82);
+        assert_eq!(&contract_bytes[1..6], b"\xa4name");
+    }
+
+    #[cfg(feature = "tcp-messaging")]
+    #[test]
+    fn test_async_send_over_tcp() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let port = 8114;
+            let address = format!("127.0.0.1:{}", port);
+            let _server = tokio::spawn(tcp_server("127.0.0.1:8114"));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let contract = ContractHandler::ContractOne(ContractOne {
+                name: "John".to_string(),
+                age: 32,
+            });
+
+            let mut wrapper = MessagePackContractWrapper::new(contract).unwrap();
+            let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+            wrapper.async_send(&mut stream).await.unwrap();
+            wrapper.async_receive(&mut stream).await.unwrap();
+
+            let expected_contract = ContractHandler::ContractOne(ContractOne {
+                name: "John".to_string(),
+                age: 33,
+            });
+            assert_eq!(wrapper.contract.unwrap(), expected_contract);
+        });
+    }
+
+    #[cfg(feature = "tcp-messaging")]
+    #[test]
+    fn test_blocking_over_tcp() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let port = 8115;
+            let address = format!("127.0.0.1:{}", port);
+            let _server = tokio::spawn(tcp_server("127.0.0.1:8115"));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let contract = ContractHandler::ContractOne(ContractOne {
+                name: "John".to_string(),
+                age: 32,
+            });
+
+            let mut wrapper = MessagePackContractWrapper::new(contract).unwrap();
+            let mut stream = std::net::TcpStream::connect(address).unwrap();
+            wrapper.blocking_send(&mut stream).unwrap();
+            wrapper.blocking_receive(&mut stream).unwrap();
+
+            let expected_contract = ContractHandler::ContractOne(ContractOne {
+                name: "John".to_string(),
+                age: 33,
+            });
+            assert_eq!(wrapper.contract.unwrap(), expected_contract);
+        });
+    }
+}
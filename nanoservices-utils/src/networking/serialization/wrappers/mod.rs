@@ -4,3 +4,11 @@
 //! TCP calls.
 pub mod bincode;
 pub mod bitcode;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod format;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "messagepack")]
+pub mod messagepack;
+pub mod padding;
@@ -0,0 +1,105 @@
+//! Defines the padding of contract bytes to fixed size buckets so the exact size of a contract is not leaked on
+//! the wire. Padded contract bytes are laid out as the true length of the contract (4 bytes, little endian)
+//! followed by the contract bytes and then zeros up to the bucket size. The header of the wrapper holds the padded
+//! length so the framing of the wrappers does not change.
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use crate::networking::utils::check_payload_size;
+
+
+/// The number of bytes used to record the true length of the contract.
+const TRUE_LENGTH_BYTES: usize = 4;
+
+
+/// How the contract bytes are padded.
+///
+/// # Variants
+/// * `PowerOfTwo` - Pads up to the next power of two.
+/// * `Bucket` - Pads up to the next multiple of the bucket size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Padding {
+    PowerOfTwo,
+    Bucket(usize),
+}
+
+impl Padding {
+
+    /// Calculates the padded length for an unpadded length.
+    ///
+    /// # Arguments
+    /// * `len` - The length of the true length prefix and the contract bytes.
+    ///
+    /// # Returns
+    /// * `usize` - The padded length.
+    pub fn padded_len(&self, len: usize) -> usize {
+        match self {
+            Padding::PowerOfTwo => len.next_power_of_two(),
+            Padding::Bucket(size) => {
+                let size = (*size).max(1);
+                len.div_ceil(size) * size
+            }
+        }
+    }
+
+    /// Pads the contract bytes.
+    ///
+    /// # Arguments
+    /// * `contract_bytes` - The serialized contract.
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>, NanoServiceError>` - The padded bytes with the true length prefix.
+    pub fn pad(&self, contract_bytes: &[u8]) -> Result<Vec<u8>, NanoServiceError> {
+        check_payload_size(contract_bytes.len(), u32::MAX as usize)?;
+        let true_length = contract_bytes.len() as u32;
+        let padded_len = self.padded_len(TRUE_LENGTH_BYTES + contract_bytes.len());
+        let mut padded = Vec::with_capacity(padded_len);
+        padded.extend_from_slice(&true_length.to_le_bytes());
+        padded.extend_from_slice(contract_bytes);
+        padded.resize(padded_len, 0);
+        Ok(padded)
+    }
+}
+
+
+/// Trims the padding from padded contract bytes.
+///
+/// # Arguments
+/// * `padded` - The padded bytes with the true length prefix.
+///
+/// # Returns
+/// * `Result<&[u8], NanoServiceError>` - The contract bytes without the padding.
+pub fn unpad(padded: &[u8]) -> Result<&[u8], NanoServiceError> {
+    if padded.len() < TRUE_LENGTH_BYTES {
+        return Err(NanoServiceError::new(
+            "Padded contract is missing the true length".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ))
+    }
+    let (length_bytes, rest) = padded.split_at(TRUE_LENGTH_BYTES);
+    let true_length = u32::from_le_bytes([length_bytes[0], length_bytes[1], length_bytes[2], length_bytes[3]]) as usize;
+    rest.get(..true_length).ok_or_else(|| {
+        NanoServiceError::new(
+            "Padded contract true length exceeds the padded length".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        )
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_pad_and_unpad() {
+        assert_eq!(Padding::PowerOfTwo.padded_len(5), 8);
+        assert_eq!(Padding::Bucket(64).padded_len(65), 128);
+
+        let padded = Padding::Bucket(16).pad(&[1, 2, 3]).unwrap();
+        assert_eq!(padded.len(), 16);
+        assert_eq!(unpad(&padded).unwrap(), &[1, 2, 3]);
+
+        assert_eq!(unpad(&[9, 0, 0, 0, 1]).unwrap_err().status, NanoServiceErrorStatus::BadRequest);
+    }
+
+}
@@ -0,0 +1,162 @@
+//! Defines a length prefixed TCP framing for the bincode serialization format that avoids intermediate buffers.
+//! Contracts are serialized straight into the outgoing buffer rather than into a `Vec` that is then copied, and
+//! received frames are split off the incoming buffer as `Bytes` without copying. A frame is the length of the
+//! serialized contract (4 bytes, little endian) followed by the serialized contract.
+//!
+//! The framing is the same as `BincodeCodec`, which this codec wraps for its maximum length and decoding, so only
+//! encoding differs between the two.
+use tokio_util::codec::{Decoder, Encoder};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::io;
+use serde::Serialize;
+use crate::networking::serialization::codec::{check_message_size, next_length_prefixed_frame, BincodeCodec, LENGTH_BYTES};
+
+
+/// A codec that serializes and deserializes data using the bincode format with length prefixed framing.
+///
+/// # Fields
+/// * `inner` - The `BincodeCodec` that holds the maximum length and decodes received frames.
+pub struct ZeroCopyBincodeCodec<T> {
+    inner: BincodeCodec<T>,
+}
+
+impl<T> ZeroCopyBincodeCodec<T> {
+    pub fn new() -> Self {
+        ZeroCopyBincodeCodec { inner: BincodeCodec::new() }
+    }
+
+    /// Sets the maximum length of a serialized contract that the codec will encode or decode.
+    ///
+    /// # Arguments
+    /// * `max_len` - The maximum length in bytes.
+    ///
+    /// # Returns
+    /// * `ZeroCopyBincodeCodec<T>` - The codec with the new maximum length.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.inner = self.inner.with_max_len(max_len);
+        self
+    }
+
+    /// Splits the next complete frame off the buffer without copying it.
+    ///
+    /// # Arguments
+    /// * `src` - The buffer of received bytes.
+    ///
+    /// # Returns
+    /// * `io::Result<Option<Bytes>>` - The serialized contract of the next frame, `None` if the frame has not fully
+    ///   arrived, or an error if the frame is longer than the maximum length of the codec.
+    pub fn next_frame(&mut self, src: &mut BytesMut) -> io::Result<Option<Bytes>> {
+        next_length_prefixed_frame(src, self.inner.max_len())
+    }
+}
+
+impl<T> Default for ZeroCopyBincodeCodec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Decoder for ZeroCopyBincodeCodec<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    type Item = T;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.inner.decode(src)
+    }
+}
+
+impl<T> Encoder<T> for ZeroCopyBincodeCodec<T>
+where
+    T: Serialize,
+{
+    type Error = io::Error;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let size = bincode::serialized_size(&item).map_err(|e| {
+            eprintln!("Encode failed: {:?}", e);
+            io::Error::new(io::ErrorKind::InvalidInput, "serialize failed")
+        })?;
+        let frame_len = u32::try_from(size).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+        })?;
+        check_message_size(frame_len as usize, self.inner.max_len(), io::ErrorKind::InvalidInput)?;
+        dst.reserve(LENGTH_BYTES + frame_len as usize);
+        dst.put_u32_le(frame_len);
+        bincode::serialize_into(dst.writer(), &item).map_err(|e| {
+            eprintln!("Encode failed: {:?}", e);
+            io::Error::new(io::ErrorKind::InvalidInput, "serialize failed")
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use tokio_util::codec::Framed;
+    use futures::{sink::SinkExt, StreamExt};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, serde::Deserialize)]
+    struct TestStruct {
+        field1: u32,
+        field2: String,
+    }
+
+    #[test]
+    fn test_zero_copy_codec() {
+        let mut codec = ZeroCopyBincodeCodec::<TestStruct>::new();
+        let test_struct = TestStruct {
+            field1: 42,
+            field2: "hello".to_string(),
+        };
+        let mut buf = BytesMut::new();
+        codec.encode(test_struct.clone(), &mut buf).unwrap();
+        codec.encode(test_struct.clone(), &mut buf).unwrap();
+
+        // a partial frame is left in the buffer until the rest arrives
+        let mut partial = buf.split_to(3);
+        assert_eq!(codec.decode(&mut partial).unwrap(), None);
+        partial.unsplit(buf);
+        let mut buf = partial;
+
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(test_struct.clone()));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(test_struct));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_tcp_framing() {
+        let tokio_runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        tokio_runtime.block_on(async {
+            let addr = "127.0.0.1:8106";
+            let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+            let _server = tokio::spawn(async move {
+                let (socket, _) = listener.accept().await.unwrap();
+                let mut framed = Framed::new(socket, ZeroCopyBincodeCodec::<TestStruct>::new());
+                while let Some(Ok(mut data)) = framed.next().await {
+                    data.field1 += 1;
+                    framed.send(data).await.unwrap();
+                }
+            });
+
+            let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let mut framed = Framed::new(stream, ZeroCopyBincodeCodec::<TestStruct>::new());
+            for field1 in [1, 2] {
+                framed.send(TestStruct { field1, field2: "hello".to_string() }).await.unwrap();
+                let response = framed.next().await.unwrap().unwrap();
+                assert_eq!(response.field1, field1 + 1);
+            }
+        });
+    }
+
+}
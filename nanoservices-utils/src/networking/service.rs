@@ -0,0 +1,156 @@
+//! Defines an adapter that exposes a contract handler as a `tower::Service` so tower middleware such as timeouts,
+//! rate limits and concurrency limits can be layered around the handling of contracts.
+//!
+//! # Example
+//!
+//! ```rust
+//! use nanoservices_utils::errors::{NanoServiceError, NanoServiceErrorStatus};
+//! use nanoservices_utils::create_contract_handler;
+//! use nanoservices_utils::networking::service::ContractService;
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! pub struct ContractOne;
+//!
+//! create_contract_handler!(
+//!    ContractHandler,
+//!    ContractOne
+//! );
+//!
+//! let service = tower::ServiceBuilder::new()
+//!     .concurrency_limit(8)
+//!     .service(ContractService::new(|contract: ContractHandler| async move {
+//!         Ok(contract)
+//!     }));
+//! ```
+use std::marker::PhantomData;
+use std::task::{Context, Poll};
+use crate::errors::NanoServiceError;
+use crate::networking::router::ContractRoute;
+
+
+/// Exposes a contract handler as a `tower::Service` taking and returning the contract handler enum.
+///
+/// # Fields
+/// * `route` - The function that handles the contracts (e.g. one made by `register_contract_routes!`).
+pub struct ContractService<H, R> {
+    route: R,
+    phantom: PhantomData<fn(H) -> H>,
+}
+
+impl<H, R: ContractRoute<H>> ContractService<H, R> {
+
+    /// Constructs a new `ContractService`.
+    ///
+    /// # Arguments
+    /// * `route` - The function that handles the contracts.
+    ///
+    /// # Returns
+    /// * `ContractService<H, R>` - The service handling contracts with the route.
+    pub fn new(route: R) -> Self {
+        ContractService {
+            route,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<H, R: Clone> Clone for ContractService<H, R> {
+    fn clone(&self) -> Self {
+        ContractService {
+            route: self.route.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<H, R: ContractRoute<H>> tower::Service<H> for ContractService<H, R> {
+    type Response = H;
+    type Error = NanoServiceError;
+    type Future = R::Fut;
+
+    /// The route can always take another contract, so backpressure is left to the layers around the service.
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, contract: H) -> Self::Future {
+        self.route.handle(contract)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::create_contract_handler;
+    use crate::errors::NanoServiceErrorStatus;
+    use serde::{Deserialize, Serialize};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::runtime::Builder;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Work {
+        pub id: u32,
+    }
+
+    create_contract_handler!(
+        ContractHandler,
+        Work
+    );
+
+    #[test]
+    fn test_concurrency_limited_service() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let in_flight = Arc::new(AtomicUsize::new(0));
+            let max_seen = Arc::new(AtomicUsize::new(0));
+
+            let route_in_flight = in_flight.clone();
+            let route_max_seen = max_seen.clone();
+            let service = ServiceBuilder::new()
+                .concurrency_limit(2)
+                .service(ContractService::new(move |contract: ContractHandler| {
+                    let in_flight = route_in_flight.clone();
+                    let max_seen = route_max_seen.clone();
+                    async move {
+                        let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_seen.fetch_max(current, Ordering::SeqCst);
+                        tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        match contract.Work()? {
+                            Work { id: 0 } => Err(NanoServiceError::new(
+                                "Work has no id".to_string(),
+                                NanoServiceErrorStatus::BadRequest
+                            )),
+                            Work { id } => Ok(ContractHandler::Work(Work { id: id * 10 })),
+                        }
+                    }
+                }));
+
+            let mut calls = Vec::new();
+            for id in 1..=6 {
+                let service = service.clone();
+                calls.push(tokio::spawn(async move {
+                    service.oneshot(ContractHandler::Work(Work { id })).await
+                }));
+            }
+            for (id, call) in (1..=6).zip(calls) {
+                assert_eq!(call.await.unwrap().unwrap(), ContractHandler::Work(Work { id: id * 10 }));
+            }
+            assert_eq!(max_seen.load(Ordering::SeqCst), 2);
+
+            let error = service.oneshot(ContractHandler::Work(Work { id: 0 })).await.unwrap_err();
+            assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+        });
+    }
+
+}
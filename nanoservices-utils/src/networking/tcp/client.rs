@@ -2,9 +2,64 @@
 use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
 use serde::{de::DeserializeOwned, Serialize};
 use tokio::net::TcpStream;
-use tokio_util::codec::Framed;
+use tokio::io::AsyncWriteExt;
+use tokio_util::codec::{Encoder, Framed};
+use bytes::BytesMut;
 use crate::networking::serialization::codec::BincodeCodec;
-use futures::{sink::SinkExt, StreamExt};
+use futures::{sink::SinkExt, FutureExt, StreamExt};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::time::Duration;
+
+
+/// How `send_data_contract_over_tcp_with_retry` retries a contract after a transport failure.
+///
+/// # Fields
+/// * `max_attempts` - The most times the contract is sent, including the first attempt.
+/// * `base_delay` - The delay before the first retry, doubled before every retry after it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+
+    /// Constructs a new `RetryPolicy`.
+    ///
+    /// # Arguments
+    /// * `max_attempts` - The most times the contract is sent, including the first attempt.
+    /// * `base_delay` - The delay before the first retry.
+    ///
+    /// # Returns
+    /// * `RetryPolicy` - The new policy.
+    pub fn new(max_attempts: u32, base_delay: Duration) -> Self {
+        RetryPolicy { max_attempts, base_delay }
+    }
+
+    /// Works out how long to wait before a retry. The delay doubles with every retry and a random jitter of up to
+    /// half of it is taken off, so clients that failed at the same time do not all retry at the same time.
+    ///
+    /// # Arguments
+    /// * `retry` - The number of the retry, starting at 1.
+    ///
+    /// # Returns
+    /// * `Duration` - The delay before the retry.
+    fn delay(&self, retry: u32) -> Duration {
+        let delay = self.base_delay.saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)));
+        let half = delay / 2;
+        // a fresh `RandomState` is randomly seeded, which is enough for jitter without a random number dependency
+        let jitter = RandomState::new().build_hasher().finish() % (half.as_millis() as u64 + 1);
+        delay - Duration::from_millis(jitter)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(3, Duration::from_millis(100))
+    }
+}
 
 
 /// Sends a data contract over TCP to the specified address.
@@ -20,22 +75,257 @@ where
     T: Serialize + DeserializeOwned,
 {
     let stream = TcpStream::connect(address).await.map_err(|e| {
-        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        NanoServiceError::from_source(e, NanoServiceErrorStatus::BadRequest)
     })?;
     let mut framed = Framed::new(stream, BincodeCodec::<T>::new());
     framed.send(contract).await.map_err(|e| {
-        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        NanoServiceError::from_io_error(e, NanoServiceErrorStatus::BadRequest)
     })?;
     let response = match framed.next().await {
         Some(response) => response,
         None => return Err(NanoServiceError::new("No response from server.".to_string(), NanoServiceErrorStatus::BadRequest))
     };
-    Ok(response.map_err(|e| {
-        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
-    })?)
+    response.map_err(|e| {
+        NanoServiceError::from_io_error(e, NanoServiceErrorStatus::BadRequest)
+    })
+}
+
+
+/// Sends a data contract over TCP to the specified address, retrying with an exponential backoff if the contract
+/// could not be sent or the response could not be received.
+///
+/// # Notes
+/// Only transport failures that `NanoServiceError::is_retryable` reports as transient (e.g. the connection being
+/// refused or dropped) are retried, any other error is returned straight away. An error the server handled
+/// the contract with comes back as the error variant of the contract handler, which is a successful response and is
+/// returned without retrying. The contract is encoded once and the same bytes are sent on every attempt, so it does
+/// not need to be `Clone`, but a server may receive it more than once if a connection drops after it was sent.
+///
+/// # Arguments
+/// * `contract` - The contract to send.
+/// * `address` - The address to send the contract to.
+/// * `policy` - How many times to try and how long to wait between attempts.
+///
+/// # Returns
+/// * `Result<T, NanoServiceError>` - The response from the server, or the error of the last attempt.
+pub async fn send_data_contract_over_tcp_with_retry<T>(
+    contract: T,
+    address: &str,
+    policy: RetryPolicy
+) -> Result<T, NanoServiceError>
+where
+    T: Serialize + DeserializeOwned,
+{
+    // a contract that cannot be encoded will not encode on a retry either, so this is not retried
+    let mut frame = BytesMut::new();
+    BincodeCodec::<T>::new().encode(contract, &mut frame)?;
+    let mut attempt = 1;
+    loop {
+        match send_frame_over_tcp::<T>(&frame, address).await {
+            Ok(response) => return Ok(response),
+            Err(e) if !e.is_retryable() || attempt >= policy.max_attempts => return Err(e),
+            Err(_) => {}
+        }
+        tokio::time::sleep(policy.delay(attempt)).await;
+        attempt += 1;
+    }
+}
+
+
+/// Sends an encoded contract frame over TCP and waits for the response.
+///
+/// # Arguments
+/// * `frame` - The contract encoded by the `BincodeCodec`.
+/// * `address` - The address to send the frame to.
+///
+/// # Returns
+/// * `Result<T, NanoServiceError>` - The response from the server.
+async fn send_frame_over_tcp<T: DeserializeOwned>(frame: &[u8], address: &str) -> Result<T, NanoServiceError> {
+    let mut stream = TcpStream::connect(address).await?;
+    stream.write_all(frame).await?;
+    let mut framed = Framed::new(stream, BincodeCodec::<T>::new());
+    // a connection dropped before the response arrived is a transport failure, so it is retryable
+    receive(&mut framed).await
+}
+
+
+/// Sends a data contract over TCP to the specified address, giving up if connecting, sending the contract, and
+/// receiving the response do not finish within the timeout.
+///
+/// # Arguments
+/// * `contract` - The contract to send.
+/// * `address` - The address to send the contract to.
+/// * `timeout` - How long to wait for the whole exchange.
+///
+/// # Returns
+/// * `Result<T, NanoServiceError>` - The response from the server, or a `ServiceUnavailable` error if the timeout
+///   elapsed (the same status a gRPC deadline being exceeded is mapped to).
+pub async fn send_data_contract_over_tcp_with_timeout<T>(
+    contract: T,
+    address: &str,
+    timeout: Duration
+) -> Result<T, NanoServiceError>
+where
+    T: Serialize + DeserializeOwned,
+{
+    match tokio::time::timeout(timeout, send_data_contract_over_tcp(contract, address)).await {
+        Ok(response) => response,
+        Err(_) => Err(NanoServiceError::new(
+            format!("No response from {} within {:?}.", address, timeout),
+            NanoServiceErrorStatus::ServiceUnavailable
+        ))
+    }
+}
+
+
+
+/// A client that keeps its TCP connection open so several contracts can be sent without connecting for each one.
+///
+/// # Notes
+/// The connection is closed when anything goes wrong with a request so the next request starts on a fresh
+/// connection. A contract is only sent again on a fresh connection when it never reached the server, that is when
+/// the server had already closed the reused connection (e.g. it timed out while idle) or writing the contract to it
+/// failed. If the connection drops after the contract was written the server may have handled it, so the error is
+/// returned rather than risking the contract running twice. Retry those errors yourself only for contracts that
+/// are safe to handle more than once.
+///
+/// # Fields
+/// * `address` - The address of the server.
+/// * `framed` - The open connection, `None` if it has been closed or a request is using it.
+pub struct ContractClient<T> {
+    address: String,
+    framed: Option<Framed<TcpStream, BincodeCodec<T>>>,
+}
+
+impl<T: Serialize + DeserializeOwned> ContractClient<T> {
+
+    /// Connects a new `ContractClient` to a server.
+    ///
+    /// # Arguments
+    /// * `address` - The address of the server.
+    ///
+    /// # Returns
+    /// * `Result<ContractClient<T>, NanoServiceError>` - The connected client.
+    pub async fn connect(address: &str) -> Result<Self, NanoServiceError> {
+        let stream = TcpStream::connect(address).await?;
+        Ok(ContractClient {
+            address: address.to_string(),
+            framed: Some(Framed::new(stream, BincodeCodec::<T>::new())),
+        })
+    }
+
+    /// Sends a data contract over the open connection and waits for the response, reconnecting first if the
+    /// connection has been closed.
+    ///
+    /// # Notes
+    /// The connection is only kept once the response has been read, so if the future is dropped part way through
+    /// (e.g. by a timeout) the connection is closed rather than leaving the response for the next request to read.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to send.
+    ///
+    /// # Returns
+    /// * `Result<T, NanoServiceError>` - The response from the server which is either the contract or an Error.
+    pub async fn request(&mut self, contract: T) -> Result<T, NanoServiceError> {
+        // the contract is encoded up front so the same bytes can be sent again after a reconnect
+        let mut frame = BytesMut::new();
+        BincodeCodec::<T>::new().encode(contract, &mut frame)?;
+        self.close_if_closed_by_server();
+        let framed = self.framed.take();
+        let reused = framed.is_some();
+        let mut framed = match self.send_frame(framed, &frame).await {
+            Ok(framed) => framed,
+            // a frame that was not fully written cannot be decoded by the server, so sending it again is safe
+            Err(e) if reused && is_dropped_connection(&e) => self.send_frame(None, &frame).await?,
+            Err(e) => return Err(e)
+        };
+        let response = receive(&mut framed).await?;
+        self.framed = Some(framed);
+        Ok(response)
+    }
+
+    /// Checks if the client has an open connection.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if the next request will reuse an open connection.
+    pub fn is_connected(&self) -> bool {
+        self.framed.is_some()
+    }
+
+    /// Closes the open connection if the server has already closed its end, without waiting on the socket.
+    ///
+    /// # Notes
+    /// This is only a best effort check. A close that has not reached the client yet is not seen, in which case
+    /// the failed write is sent again on a fresh connection by `request`, or the error is returned if the write
+    /// went through before the close arrived.
+    fn close_if_closed_by_server(&mut self) {
+        let closed = match self.framed.as_ref() {
+            // nothing is sent before a request so the only thing left to read on an idle connection is its end
+            Some(framed) => matches!(framed.get_ref().peek(&mut [0; 1]).now_or_never(), Some(Ok(0)) | Some(Err(_))),
+            None => false
+        };
+        if closed {
+            self.framed = None;
+        }
+    }
+
+    /// Writes an encoded contract frame, connecting first if there is no open connection.
+    ///
+    /// # Arguments
+    /// * `framed` - The open connection, `None` to connect a new one.
+    /// * `frame` - The contract encoded by the `BincodeCodec`.
+    ///
+    /// # Returns
+    /// * `Result<Framed<TcpStream, BincodeCodec<T>>, NanoServiceError>` - The connection the frame was written to,
+    ///   or an error if the frame could not be written, in which case the connection is closed.
+    async fn send_frame(
+        &self,
+        framed: Option<Framed<TcpStream, BincodeCodec<T>>>,
+        frame: &[u8]
+    ) -> Result<Framed<TcpStream, BincodeCodec<T>>, NanoServiceError> {
+        let mut framed = match framed {
+            Some(framed) => framed,
+            None => Framed::new(TcpStream::connect(&self.address).await?, BincodeCodec::<T>::new())
+        };
+        framed.get_mut().write_all(frame).await?;
+        Ok(framed)
+    }
 }
 
 
+/// Waits for the response to a contract sent over a connection.
+///
+/// # Arguments
+/// * `framed` - The connection the contract was sent over.
+///
+/// # Returns
+/// * `Result<T, NanoServiceError>` - The response from the server. A response that cannot be decoded is a
+///   `BadRequest` error as it would not decode if sent again, a dropped connection is an `Unknown` error.
+async fn receive<T: DeserializeOwned>(framed: &mut Framed<TcpStream, BincodeCodec<T>>) -> Result<T, NanoServiceError> {
+    match framed.next().await {
+        Some(response) => response.map_err(|e| NanoServiceError::from_io_error(e, NanoServiceErrorStatus::BadRequest)),
+        None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "No response from server.").into())
+    }
+}
+
+
+/// Checks if an error writing a contract was caused by the server closing the connection.
+///
+/// # Arguments
+/// * `error` - The error of the write.
+///
+/// # Returns
+/// * `bool` - `true` if the connection was closed by the server.
+fn is_dropped_connection(error: &NanoServiceError) -> bool {
+    let source = std::error::Error::source(error).and_then(|source| source.downcast_ref::<io::Error>());
+    matches!(
+        source.map(io::Error::kind),
+        Some(io::ErrorKind::BrokenPipe)
+            | Some(io::ErrorKind::ConnectionReset)
+            | Some(io::ErrorKind::ConnectionAborted)
+    )
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -62,7 +352,7 @@ mod tests {
     }
 
     mod server {
-        use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+        use crate::errors::NanoServiceError;
         use super::kernel::ContractHandler;
         use super::kernel::ContractOne;
         use super::kernel::ContractTwo;
@@ -95,7 +385,7 @@ mod tests {
             while let Ok((socket, _)) = listener.accept().await {
                 let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
 
-                while let Some(result) = framed.next().await {
+                if let Some(result) = framed.next().await {
                     match result {
                         Ok(data) => {
                             let response = match handle_contract(data).await {
@@ -106,11 +396,9 @@ mod tests {
                             
                             };
                             framed.send(response).await.unwrap();
-                            break;
                         },
                         Err(e) => {
                             eprintln!("Error processing data: {}", e);
-                            break;
                         }
                     }
                 }
@@ -121,7 +409,14 @@ mod tests {
     use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
     use kernel::{ContractHandler, ContractOne, ContractThree, ContractTwo};
     use server::tcp_server;
-    use crate::networking::tcp::client::send_data_contract_over_tcp;
+    use crate::networking::tcp::client::{
+        send_data_contract_over_tcp,
+        send_data_contract_over_tcp_with_retry,
+        send_data_contract_over_tcp_with_timeout,
+        ContractClient,
+        RetryPolicy
+    };
+    use std::time::Duration;
 
     use tokio::runtime::Builder;
 
@@ -148,9 +443,354 @@ mod tests {
             let contract_three: ContractHandler = ContractHandler::ContractThree(ContractThree);
             let response_three = send_data_contract_over_tcp(contract_three, address).await.unwrap();
             assert_eq!(response_three.NanoServiceError().unwrap(), NanoServiceError::new(
-                "Received unknown contract type.".to_string(),
+                "Contract not supported: contractthree_contract. Supported contracts: contractone_contract, contracttwo_contract".to_string(),
                 NanoServiceErrorStatus::ContractNotSupported
             ));
         });
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_connection_failure_is_bad_request() {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            // nothing listens on the address so the connection is refused
+            let error = send_data_contract_over_tcp(ContractHandler::ContractOne(ContractOne), "127.0.0.1:8140")
+                .await
+                .unwrap_err();
+            assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+            assert!(std::error::Error::source(&error).is_some());
+        });
+    }
+
+    #[test]
+    fn test_send_over_tcp_with_timeout() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let address = "127.0.0.1:8123";
+            let _server = tokio::spawn(tcp_server(address));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let contract = ContractHandler::ContractOne(ContractOne);
+            let response = send_data_contract_over_tcp_with_timeout(contract, address, Duration::from_secs(5)).await.unwrap();
+            assert_eq!(response.ContractOne().unwrap(), ContractOne);
+
+            // a server that accepts the connection but never replies
+            let silent_address = "127.0.0.1:8124";
+            let listener = tokio::net::TcpListener::bind(silent_address).await.unwrap();
+            let _silent_server = tokio::spawn(async move {
+                let (_socket, _) = listener.accept().await.unwrap();
+                std::future::pending::<()>().await;
+            });
+
+            let contract = ContractHandler::ContractOne(ContractOne);
+            let error = send_data_contract_over_tcp_with_timeout(
+                contract,
+                silent_address,
+                Duration::from_millis(200)
+            ).await.unwrap_err();
+            assert_eq!(error.status, NanoServiceErrorStatus::ServiceUnavailable);
+            assert_eq!(error.message, "No response from 127.0.0.1:8124 within 200ms.");
+        });
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy::new(5, Duration::from_millis(100));
+        for (retry, full_delay) in [(1, 100), (2, 200), (3, 400)] {
+            let delay = policy.delay(retry);
+            assert!(delay <= Duration::from_millis(full_delay));
+            assert!(delay >= Duration::from_millis(full_delay / 2));
+        }
+        // the delay saturates rather than overflowing after many retries
+        assert!(policy.delay(100) >= Duration::from_millis(100) * (u32::MAX / 2));
+    }
+
+    #[test]
+    fn test_send_over_tcp_with_retry() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let policy = RetryPolicy::new(5, Duration::from_millis(50));
+
+            // the server only starts listening after the first attempt has been refused
+            let address = "127.0.0.1:8125";
+            tokio::spawn(async move {
+                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                tcp_server(address).await;
+            });
+            let response = send_data_contract_over_tcp_with_retry(
+                ContractHandler::ContractOne(ContractOne),
+                address,
+                policy
+            ).await.unwrap();
+            assert_eq!(response.ContractOne().unwrap(), ContractOne);
+
+            // nothing ever listens so every attempt fails and the last error is returned
+            let error = send_data_contract_over_tcp_with_retry(
+                ContractHandler::ContractOne(ContractOne),
+                "127.0.0.1:8126",
+                RetryPolicy::new(2, Duration::from_millis(10))
+            ).await.unwrap_err();
+            assert!(error.is_retryable());
+        });
+    }
+
+    #[test]
+    fn test_error_contract_is_not_retried() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            use std::sync::Arc;
+            use tokio_util::codec::Framed;
+            use crate::networking::serialization::codec::BincodeCodec;
+            use futures::{sink::SinkExt, StreamExt};
+
+            let address = "127.0.0.1:8127";
+            let connections = Arc::new(AtomicUsize::new(0));
+            let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+            let server_connections = connections.clone();
+            tokio::spawn(async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    server_connections.fetch_add(1, Ordering::SeqCst);
+                    let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
+                    framed.next().await.unwrap().unwrap();
+                    let error = NanoServiceError::new("Service is overloaded.".to_string(), NanoServiceErrorStatus::ServiceUnavailable);
+                    framed.send(ContractHandler::NanoServiceError(error)).await.unwrap();
+                }
+            });
+
+            let response = send_data_contract_over_tcp_with_retry(
+                ContractHandler::ContractOne(ContractOne),
+                address,
+                RetryPolicy::new(3, Duration::from_millis(10))
+            ).await.unwrap();
+            assert_eq!(response.NanoServiceError().unwrap().status, NanoServiceErrorStatus::ServiceUnavailable);
+            assert_eq!(connections.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    #[test]
+    fn test_non_retryable_error_is_not_retried() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            use std::sync::Arc;
+            use tokio::io::AsyncWriteExt;
+            use tokio_util::codec::Framed;
+            use crate::networking::serialization::codec::BincodeCodec;
+            use futures::StreamExt;
+
+            // a server that answers with a frame that cannot be decoded
+            let address = "127.0.0.1:8139";
+            let connections = Arc::new(AtomicUsize::new(0));
+            let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+            let server_connections = connections.clone();
+            tokio::spawn(async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    server_connections.fetch_add(1, Ordering::SeqCst);
+                    let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
+                    framed.next().await.unwrap().unwrap();
+                    framed.get_mut().write_all(&[4, 0, 0, 0, 255, 255, 255, 255]).await.unwrap();
+                }
+            });
+
+            let error = send_data_contract_over_tcp_with_retry(
+                ContractHandler::ContractOne(ContractOne),
+                address,
+                RetryPolicy::new(3, Duration::from_millis(10))
+            ).await.unwrap_err();
+            assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+            assert_eq!(connections.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    #[test]
+    fn test_oversized_response_is_payload_too_large() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            use tokio::io::AsyncWriteExt;
+            use tokio_util::codec::Framed;
+            use crate::networking::serialization::codec::BincodeCodec;
+            use crate::networking::serialization::DEFAULT_MAX_MESSAGE_SIZE;
+            use futures::StreamExt;
+
+            // a server that answers with the header of a frame over the maximum message size
+            let address = "127.0.0.1:8143";
+            let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+            tokio::spawn(async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
+                    framed.next().await.unwrap().unwrap();
+                    let header = (DEFAULT_MAX_MESSAGE_SIZE as u32 + 1).to_le_bytes();
+                    framed.get_mut().write_all(&header).await.unwrap();
+                }
+            });
+
+            let error = send_data_contract_over_tcp(ContractHandler::ContractOne(ContractOne), address)
+                .await
+                .unwrap_err();
+            assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+
+            let mut client = ContractClient::<ContractHandler>::connect(address).await.unwrap();
+            let error = client.request(ContractHandler::ContractOne(ContractOne)).await.unwrap_err();
+            assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+        });
+    }
+
+    #[test]
+    fn test_contract_client_reuses_connection() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            use std::sync::Arc;
+            use tokio_util::codec::Framed;
+            use crate::networking::serialization::codec::BincodeCodec;
+            use futures::{sink::SinkExt, StreamExt};
+
+            // a server that answers every contract sent over a connection
+            let address = "127.0.0.1:8128";
+            let connections = Arc::new(AtomicUsize::new(0));
+            let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+            let server_connections = connections.clone();
+            tokio::spawn(async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    server_connections.fetch_add(1, Ordering::SeqCst);
+                    let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
+                    while let Some(Ok(contract)) = framed.next().await {
+                        framed.send(contract).await.unwrap();
+                    }
+                }
+            });
+
+            let mut client = ContractClient::<ContractHandler>::connect(address).await.unwrap();
+            for _ in 0..3 {
+                let response = client.request(ContractHandler::ContractOne(ContractOne)).await.unwrap();
+                assert_eq!(response.ContractOne().unwrap(), ContractOne);
+            }
+            assert_eq!(connections.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    #[test]
+    fn test_contract_client_reconnects() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            // the server closes every connection after answering one contract
+            let address = "127.0.0.1:8129";
+            let _server = tokio::spawn(tcp_server(address));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let mut client = ContractClient::<ContractHandler>::connect(address).await.unwrap();
+            for _ in 0..3 {
+                let response = client.request(ContractHandler::ContractOne(ContractOne)).await.unwrap();
+                assert_eq!(response.ContractOne().unwrap(), ContractOne);
+                tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+            }
+
+            let response = client.request(ContractHandler::ContractThree(ContractThree)).await.unwrap();
+            assert_eq!(response.NanoServiceError().unwrap().status, NanoServiceErrorStatus::ContractNotSupported);
+        });
+    }
+
+    #[test]
+    fn test_contract_client_does_not_resend_handled_contract() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            use std::sync::Arc;
+            use tokio_util::codec::Framed;
+            use crate::networking::serialization::codec::BincodeCodec;
+            use futures::StreamExt;
+
+            // a server that reads every contract and closes the connection without answering
+            let address = "127.0.0.1:8135";
+            let received = Arc::new(AtomicUsize::new(0));
+            let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+            let server_received = received.clone();
+            tokio::spawn(async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
+                    if let Some(Ok(_)) = framed.next().await {
+                        server_received.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            });
+
+            let mut client = ContractClient::<ContractHandler>::connect(address).await.unwrap();
+            let result = client.request(ContractHandler::ContractOne(ContractOne)).await;
+            assert!(result.is_err());
+            assert!(!client.is_connected());
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(received.load(Ordering::SeqCst), 1);
+        });
+    }
+    #[test]
+    fn test_contract_client_drops_cancelled_request() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            use tokio_util::codec::Framed;
+            use crate::networking::serialization::codec::BincodeCodec;
+            use futures::{sink::SinkExt, StreamExt};
+
+            // a server that takes a while to answer every contract sent over a connection
+            let address = "127.0.0.1:8138";
+            let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+            tokio::spawn(async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    tokio::spawn(async move {
+                        let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
+                        while let Some(Ok(contract)) = framed.next().await {
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            framed.send(contract).await.unwrap();
+                        }
+                    });
+                }
+            });
+
+            let mut client = ContractClient::<ContractHandler>::connect(address).await.unwrap();
+            let request = client.request(ContractHandler::ContractOne(ContractOne));
+            assert!(tokio::time::timeout(Duration::from_millis(20), request).await.is_err());
+            assert!(!client.is_connected());
+
+            // the answer to the cancelled request is not read as the answer to this one
+            let response = client.request(ContractHandler::ContractTwo(ContractTwo)).await.unwrap();
+            assert_eq!(response.ContractTwo().unwrap(), ContractTwo);
+        });
+    }
+
+}
@@ -0,0 +1,106 @@
+//! Defines a live stream of the contracts a server handles so an admin endpoint or a debugging dashboard can tail
+//! what a running service is doing. An event is sent over a `tokio::sync::broadcast` channel for every contract
+//! handled by `serve_connection_with_events`, and nothing is worked out for an event if nobody is subscribed.
+use std::time::Duration;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use crate::networking::wire_ref::is_error_ref;
+
+
+/// Whether a handled contract was answered with a contract or an error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContractOutcome {
+    Success,
+    Error,
+}
+
+
+/// An event for a contract that has been handled.
+///
+/// # Fields
+/// * `variant` - The wire ref of the contract received (e.g. `contractone_contract`).
+/// * `size` - The size of the contract received in bytes when serialized with bincode.
+/// * `outcome` - Whether the contract was answered with a contract or an error.
+/// * `latency` - How long the handler took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractEvent {
+    pub variant: String,
+    pub size: u64,
+    pub outcome: ContractOutcome,
+    pub latency: Duration,
+}
+
+
+/// Sends an event for every contract handled to the subscribers of the stream.
+///
+/// # Fields
+/// * `sender` - The sending half of the broadcast channel.
+/// * `string_ref` - The function that extracts the wire ref from a contract (e.g. `ContractHandler::to_string_ref`).
+pub struct ContractEvents<H> {
+    sender: broadcast::Sender<ContractEvent>,
+    string_ref: fn(&H) -> String,
+}
+
+impl<H> Clone for ContractEvents<H> {
+    fn clone(&self) -> Self {
+        ContractEvents {
+            sender: self.sender.clone(),
+            string_ref: self.string_ref,
+        }
+    }
+}
+
+impl<H: Serialize> ContractEvents<H> {
+
+    /// Constructs a new `ContractEvents` stream.
+    ///
+    /// # Arguments
+    /// * `capacity` - The number of events kept for a subscriber that falls behind before the oldest are dropped.
+    /// * `string_ref` - The function that extracts the wire ref from a contract.
+    ///
+    /// # Returns
+    /// * `ContractEvents<H>` - The new event stream with no subscribers.
+    pub fn new(capacity: usize, string_ref: fn(&H) -> String) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        ContractEvents { sender, string_ref }
+    }
+
+    /// Subscribes to the events of contracts handled from now on.
+    ///
+    /// # Returns
+    /// * `broadcast::Receiver<ContractEvent>` - The receiver of the events.
+    pub fn subscribe(&self) -> broadcast::Receiver<ContractEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Describes a contract that is about to be handled if anyone is subscribed.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract received.
+    ///
+    /// # Returns
+    /// * `Option<(String, u64)>` - The wire ref and size of the contract, `None` if there are no subscribers.
+    pub(crate) fn describe(&self, contract: &H) -> Option<(String, u64)> {
+        if self.sender.receiver_count() == 0 {
+            return None
+        }
+        let size = bincode::serialized_size(contract).unwrap_or(0);
+        Some(((self.string_ref)(contract), size))
+    }
+
+    /// Sends the event of a handled contract to the subscribers.
+    ///
+    /// # Arguments
+    /// * `description` - The wire ref and size of the contract from `describe`.
+    /// * `response` - The response of the handler.
+    /// * `latency` - How long the handler took.
+    pub(crate) fn emit(&self, description: (String, u64), response: &H, latency: Duration) {
+        let outcome = match is_error_ref(&(self.string_ref)(response)) {
+            true => ContractOutcome::Error,
+            false => ContractOutcome::Success,
+        };
+        let (variant, size) = description;
+        // an error only means every subscriber has gone, which is not a problem for the server
+        let _ = self.sender.send(ContractEvent { variant, size, outcome, latency });
+    }
+}
@@ -0,0 +1,540 @@
+//! Defines the transfer of files over TCP in chunks so a file never has to be held in memory as a single contract.
+//! The sender first sends a header with the size of the file, the number of chunks, and a checksum of the whole
+//! file, which the receiver accepts or rejects before any chunks are sent. Each chunk then carries its index, the
+//! total number of chunks, and a checksum of its data. The receiver writes the chunks to disk as they arrive, checks
+//! every checksum, and replies with the number of bytes received or the error that stopped the transfer.
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use crate::networking::serialization::zero_copy_codec::ZeroCopyBincodeCodec;
+use futures::{sink::SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_util::codec::Framed;
+
+
+/// The size of the chunks a file is split into when one is not configured.
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The largest chunk a receiver accepts when one is not configured.
+pub const DEFAULT_MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The largest file a receiver accepts when one is not configured.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 1024 * 1024 * 1024;
+
+
+/// A chunk of a file.
+///
+/// # Fields
+/// * `index` - The position of the chunk in the file, starting at zero.
+/// * `total` - The number of chunks in the file.
+/// * `checksum` - The CRC-32 of the data.
+/// * `data` - The bytes of the chunk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileChunk {
+    pub index: u32,
+    pub total: u32,
+    pub checksum: u32,
+    pub data: Vec<u8>,
+}
+
+
+/// The messages of a file transfer.
+///
+/// # Variants
+/// * `Header` - Sent first with the size of the file, the number of chunks, and the CRC-32 of the whole file.
+/// * `Accepted` - Sent back by the receiver if the header is within its limits.
+/// * `Chunk` - A chunk of the file.
+/// * `Received` - Sent back by the receiver once the whole file has been written.
+/// * `Rejected` - Sent back by the receiver if the transfer failed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FileStream {
+    Header { total_len: u64, total_chunks: u32, checksum: u32 },
+    Accepted,
+    Chunk(FileChunk),
+    Received { total_len: u64 },
+    Rejected(NanoServiceError),
+}
+
+
+/// The limits a receiver puts on a file transfer.
+///
+/// # Fields
+/// * `max_chunk_size` - The largest chunk accepted in bytes.
+/// * `max_file_size` - The largest file accepted in bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileStreamLimits {
+    pub max_chunk_size: usize,
+    pub max_file_size: u64,
+}
+
+impl Default for FileStreamLimits {
+    fn default() -> Self {
+        FileStreamLimits {
+            max_chunk_size: DEFAULT_MAX_CHUNK_SIZE,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+        }
+    }
+}
+
+
+/// Updates a CRC-32 (IEEE) checksum with more bytes. Start with a checksum of `0`.
+///
+/// # Arguments
+/// * `checksum` - The checksum of the bytes so far.
+/// * `bytes` - The next bytes.
+///
+/// # Returns
+/// * `u32` - The checksum including the next bytes.
+pub fn crc32(checksum: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !checksum;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+
+/// Reads a file in chunks without holding the whole file in memory.
+///
+/// # Arguments
+/// * `file` - The file to read from the start.
+/// * `chunk_size` - The size of the chunks.
+/// * `on_chunk` - Called with each chunk read.
+///
+/// # Returns
+/// * `Result<(), NanoServiceError>` - An error if the file could not be read or `on_chunk` failed.
+async fn for_each_chunk<F>(file: &mut File, chunk_size: usize, mut on_chunk: F) -> Result<(), NanoServiceError>
+where
+    F: AsyncFnMut(&[u8]) -> Result<(), NanoServiceError>,
+{
+    let mut buffer = vec![0; chunk_size];
+    loop {
+        let mut filled = 0;
+        while filled < chunk_size {
+            let read = file.read(&mut buffer[filled..]).await.map_err(|e| {
+                NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+            })?;
+            if read == 0 {
+                break
+            }
+            filled += read;
+        }
+        if filled == 0 {
+            return Ok(())
+        }
+        on_chunk(&buffer[..filled]).await?;
+        if filled < chunk_size {
+            return Ok(())
+        }
+    }
+}
+
+
+/// Sends a file to a receiver in chunks.
+///
+/// # Arguments
+/// * `address` - The address of the receiver.
+/// * `source` - The path of the file to send.
+/// * `chunk_size` - The size of the chunks the file is split into.
+///
+/// # Returns
+/// * `Result<u64, NanoServiceError>` - The number of bytes the receiver wrote, or the error that stopped the transfer.
+pub async fn send_file<P: AsRef<Path>>(address: &str, source: P, chunk_size: usize) -> Result<u64, NanoServiceError> {
+    if chunk_size == 0 {
+        return Err(NanoServiceError::new(
+            "Chunk size has to be at least one byte".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ))
+    }
+    let mut file = File::open(source).await.map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::NotFound)
+    })?;
+    let total_len = file.metadata().await.map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    })?.len();
+    let total_chunks = u32::try_from(total_len.div_ceil(chunk_size as u64)).map_err(|_| {
+        NanoServiceError::new(
+            format!("File of {} bytes needs more than {} chunks", total_len, u32::MAX),
+            NanoServiceErrorStatus::PayloadTooLarge
+        )
+    })?;
+
+    // the checksum of the whole file is worked out first so it can go in the header
+    let mut checksum = 0;
+    for_each_chunk(&mut file, chunk_size, async |chunk: &[u8]| {
+        checksum = crc32(checksum, chunk);
+        Ok(())
+    }).await?;
+    file.rewind().await.map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    })?;
+
+    let stream = TcpStream::connect(address).await.map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+    })?;
+    let mut framed = Framed::new(stream, ZeroCopyBincodeCodec::<FileStream>::new());
+    framed.send(FileStream::Header { total_len, total_chunks, checksum }).await.map_err(|e| {
+        NanoServiceError::from_io_error(e, NanoServiceErrorStatus::BadRequest)
+    })?;
+    match read_reply(&mut framed).await? {
+        FileStream::Accepted => {},
+        _ => return Err(unexpected_reply())
+    }
+
+    let mut index = 0;
+    for_each_chunk(&mut file, chunk_size, async |data: &[u8]| {
+        let chunk = FileChunk {
+            index,
+            total: total_chunks,
+            checksum: crc32(0, data),
+            data: data.to_vec(),
+        };
+        index += 1;
+        framed.send(FileStream::Chunk(chunk)).await.map_err(|e| {
+            NanoServiceError::from_io_error(e, NanoServiceErrorStatus::BadRequest)
+        })
+    }).await?;
+
+    match read_reply(&mut framed).await? {
+        FileStream::Received { total_len } => Ok(total_len),
+        _ => Err(unexpected_reply())
+    }
+}
+
+
+/// Reads a reply from the receiver, turning a rejection into its error.
+///
+/// # Arguments
+/// * `framed` - The framed connection to the receiver.
+///
+/// # Returns
+/// * `Result<FileStream, NanoServiceError>` - The reply, or the error the receiver rejected the transfer with.
+async fn read_reply(framed: &mut Framed<TcpStream, ZeroCopyBincodeCodec<FileStream>>) -> Result<FileStream, NanoServiceError> {
+    match framed.next().await {
+        Some(Ok(FileStream::Rejected(error))) => Err(error),
+        Some(Ok(reply)) => Ok(reply),
+        Some(Err(e)) => Err(NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)),
+        None => Err(NanoServiceError::new(
+            "No response from server.".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ))
+    }
+}
+
+
+/// Builds the error for a reply that does not fit the point the transfer is at.
+fn unexpected_reply() -> NanoServiceError {
+    NanoServiceError::new(
+        "Receiver replied with an unexpected message".to_string(),
+        NanoServiceErrorStatus::BadRequest
+    )
+}
+
+
+/// Receives a file sent with `send_file` and writes it to disk. The chunks are written to a temporary file next to
+/// `destination` which only replaces `destination` once the whole file has been received and checked, so a failed
+/// transfer leaves any file already at `destination` untouched. The sender is told the outcome of the transfer.
+///
+/// # Arguments
+/// * `stream` - The connection to the sender.
+/// * `destination` - The path the file is written to.
+/// * `limits` - The limits on the size of the chunks and the file.
+///
+/// # Returns
+/// * `Result<u64, NanoServiceError>` - The number of bytes written, or the error that stopped the transfer.
+pub async fn receive_file<S, P>(stream: S, destination: P, limits: FileStreamLimits) -> Result<u64, NanoServiceError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    P: AsRef<Path>,
+{
+    // oversized chunks are rejected at the length prefix of the frame before any buffer is reserved for them
+    let codec = ZeroCopyBincodeCodec::<FileStream>::new()
+        .with_max_len(limits.max_chunk_size.saturating_add(chunk_overhead()));
+    let mut framed = Framed::new(stream, codec);
+    let destination = destination.as_ref();
+    let partial = partial_path(destination);
+    let result = match write_chunks(&mut framed, &partial, limits).await {
+        Ok(total_len) => tokio::fs::rename(&partial, destination).await.map(|_| total_len).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+        }),
+        Err(error) => Err(error)
+    };
+    let reply = match &result {
+        Ok(total_len) => FileStream::Received { total_len: *total_len },
+        Err(error) => {
+            // only the temporary file is removed, if the header was rejected it was never created
+            let _ = tokio::fs::remove_file(&partial).await;
+            FileStream::Rejected(error.clone())
+        }
+    };
+    // the limit is for what the sender sends, a rejection carrying a long message can be larger than a small chunk
+    *framed.codec_mut() = ZeroCopyBincodeCodec::new();
+    framed.send(reply).await.map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    })?;
+    result
+}
+
+
+/// Works out the bytes a chunk message takes up on the wire on top of the data of the chunk.
+///
+/// # Returns
+/// * `usize` - The serialized size of a chunk message with no data.
+fn chunk_overhead() -> usize {
+    let empty = FileStream::Chunk(FileChunk { index: 0, total: 0, checksum: 0, data: Vec::new() });
+    bincode::serialized_size(&empty).unwrap_or(0) as usize
+}
+
+
+/// Builds the path of the temporary file a transfer is written to before it replaces the destination.
+///
+/// # Arguments
+/// * `destination` - The path the file is written to.
+///
+/// # Returns
+/// * `PathBuf` - A hidden file next to the destination with a `.partial` extension.
+fn partial_path(destination: &Path) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(destination.file_name().unwrap_or_default());
+    file_name.push(".partial");
+    destination.with_file_name(file_name)
+}
+
+
+/// Reads the header and chunks of a transfer and writes the chunks to disk, checking them as they arrive.
+///
+/// # Arguments
+/// * `framed` - The framed connection to the sender.
+/// * `destination` - The path of the temporary file the chunks are written to.
+/// * `limits` - The limits on the size of the file.
+///
+/// # Returns
+/// * `Result<u64, NanoServiceError>` - The number of bytes written.
+async fn write_chunks<S>(
+    framed: &mut Framed<S, ZeroCopyBincodeCodec<FileStream>>,
+    destination: &Path,
+    limits: FileStreamLimits
+) -> Result<u64, NanoServiceError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (total_len, total_chunks, checksum) = match next_message(framed).await? {
+        FileStream::Header { total_len, total_chunks, checksum } => (total_len, total_chunks, checksum),
+        _ => return Err(NanoServiceError::new(
+            "File transfer did not start with a header".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ))
+    };
+    if total_len > limits.max_file_size {
+        return Err(NanoServiceError::new(
+            format!("File of {} bytes exceeds the limit of {} bytes", total_len, limits.max_file_size),
+            NanoServiceErrorStatus::PayloadTooLarge
+        ))
+    }
+    framed.send(FileStream::Accepted).await.map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    })?;
+
+    let mut file = File::create(destination).await.map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    })?;
+    let mut written: u64 = 0;
+    let mut file_checksum = 0;
+    for expected_index in 0..total_chunks {
+        let chunk = match next_message(framed).await? {
+            FileStream::Chunk(chunk) => chunk,
+            _ => return Err(NanoServiceError::new(
+                "Expected a chunk of the file".to_string(),
+                NanoServiceErrorStatus::BadRequest
+            ))
+        };
+        if chunk.index != expected_index || chunk.total != total_chunks {
+            return Err(NanoServiceError::new(
+                format!("Received chunk {} of {} but expected chunk {} of {}", chunk.index, chunk.total, expected_index, total_chunks),
+                NanoServiceErrorStatus::BadRequest
+            ))
+        }
+        if written + chunk.data.len() as u64 > total_len {
+            return Err(NanoServiceError::new(
+                format!("Received more than the {} bytes in the header", total_len),
+                NanoServiceErrorStatus::BadRequest
+            ))
+        }
+        if crc32(0, &chunk.data) != chunk.checksum {
+            return Err(NanoServiceError::new(
+                format!("Checksum of chunk {} does not match its data", chunk.index),
+                NanoServiceErrorStatus::BadRequest
+            ))
+        }
+        file.write_all(&chunk.data).await.map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+        })?;
+        written += chunk.data.len() as u64;
+        file_checksum = crc32(file_checksum, &chunk.data);
+    }
+    file.flush().await.map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    })?;
+
+    if written != total_len || file_checksum != checksum {
+        return Err(NanoServiceError::new(
+            "Received file does not match the size and checksum in the header".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ))
+    }
+    Ok(written)
+}
+
+
+/// Reads the next message of a transfer.
+///
+/// # Arguments
+/// * `framed` - The framed connection to the sender.
+///
+/// # Returns
+/// * `Result<FileStream, NanoServiceError>` - The next message, or an error if the connection closed or broke.
+async fn next_message<S>(framed: &mut Framed<S, ZeroCopyBincodeCodec<FileStream>>) -> Result<FileStream, NanoServiceError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    match framed.next().await {
+        Some(message) => message.map_err(|e| {
+            NanoServiceError::from_io_error(e, NanoServiceErrorStatus::BadRequest)
+        }),
+        None => Err(NanoServiceError::new(
+            "Connection closed before the file was received".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_send_and_receive_file() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8111";
+            let source = std::env::temp_dir().join("nanoservices_test_file_stream_source.bin");
+            let destination = std::env::temp_dir().join("nanoservices_test_file_stream_destination.bin");
+            let rejected = std::env::temp_dir().join("nanoservices_test_file_stream_rejected.bin");
+            let source_bytes: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+            tokio::fs::write(&source, &source_bytes).await.unwrap();
+
+            let listener = TcpListener::bind(address).await.unwrap();
+            let receiver_destination = destination.clone();
+            let receiver_rejected = rejected.clone();
+            let receiver = tokio::spawn(async move {
+                let (socket, _) = listener.accept().await.unwrap();
+                let received = receive_file(socket, &receiver_destination, FileStreamLimits::default()).await;
+
+                let (socket, _) = listener.accept().await.unwrap();
+                let limits = FileStreamLimits { max_chunk_size: 1024, max_file_size: 5_000 };
+                let rejected = receive_file(socket, &receiver_rejected, limits).await;
+                (received, rejected)
+            });
+
+            // 10,000 bytes in 1,024 byte chunks is 10 chunks with a short last chunk
+            assert_eq!(send_file(address, &source, 1024).await, Ok(10_000));
+            let error = send_file(address, &source, 1024).await.unwrap_err();
+            assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+
+            let (received, rejected_result) = receiver.await.unwrap();
+            assert_eq!(received, Ok(10_000));
+            assert_eq!(rejected_result.unwrap_err().status, NanoServiceErrorStatus::PayloadTooLarge);
+            assert_eq!(tokio::fs::read(&destination).await.unwrap(), source_bytes);
+            assert!(!rejected.exists());
+
+            tokio::fs::remove_file(&source).await.unwrap();
+            tokio::fs::remove_file(&destination).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_corrupt_chunk_is_rejected() {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+
+        runtime.block_on(async {
+            let destination = std::env::temp_dir().join("nanoservices_test_file_stream_corrupt.bin");
+            let (client, server) = tokio::io::duplex(4096);
+            let receiver_destination = destination.clone();
+            let receiver = tokio::spawn(async move {
+                receive_file(server, &receiver_destination, FileStreamLimits::default()).await
+            });
+
+            let mut framed = Framed::new(client, ZeroCopyBincodeCodec::<FileStream>::new());
+            framed.send(FileStream::Header { total_len: 3, total_chunks: 1, checksum: crc32(0, &[1, 2, 3]) }).await.unwrap();
+            assert_eq!(framed.next().await.unwrap().unwrap(), FileStream::Accepted);
+            framed.send(FileStream::Chunk(FileChunk {
+                index: 0,
+                total: 1,
+                checksum: crc32(0, &[1, 2, 3]),
+                data: vec![1, 2, 4],
+            })).await.unwrap();
+
+            let reply = framed.next().await.unwrap().unwrap();
+            let error = receiver.await.unwrap().unwrap_err();
+            assert_eq!(reply, FileStream::Rejected(error.clone()));
+            assert_eq!(error.message, "Checksum of chunk 0 does not match its data");
+            assert!(!destination.exists());
+        });
+    }
+
+    #[test]
+    fn test_failed_transfer_keeps_existing_file() {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+
+        runtime.block_on(async {
+            let destination = std::env::temp_dir().join("nanoservices_test_file_stream_existing.bin");
+            tokio::fs::write(&destination, b"existing").await.unwrap();
+            let (client, server) = tokio::io::duplex(4096);
+            let receiver_destination = destination.clone();
+            let limits = FileStreamLimits { max_chunk_size: 16, max_file_size: 1024 };
+            let receiver = tokio::spawn(async move {
+                receive_file(server, &receiver_destination, limits).await
+            });
+
+            // the header is accepted but the chunk is over the limit, so its frame is refused at the length prefix
+            let mut framed = Framed::new(client, ZeroCopyBincodeCodec::<FileStream>::new());
+            let data = vec![7; 17];
+            framed.send(FileStream::Header { total_len: 17, total_chunks: 1, checksum: crc32(0, &data) }).await.unwrap();
+            assert_eq!(framed.next().await.unwrap().unwrap(), FileStream::Accepted);
+            framed.send(FileStream::Chunk(FileChunk { index: 0, total: 1, checksum: crc32(0, &data), data })).await.unwrap();
+
+            let reply = framed.next().await.unwrap().unwrap();
+            let error = receiver.await.unwrap().unwrap_err();
+            assert_eq!(reply, FileStream::Rejected(error.clone()));
+            assert!(error.message.contains("exceeds the limit"));
+            assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+            assert_eq!(tokio::fs::read(&destination).await.unwrap(), b"existing");
+            assert!(!partial_path(&destination).exists());
+
+            tokio::fs::remove_file(&destination).await.unwrap();
+        });
+    }
+
+}
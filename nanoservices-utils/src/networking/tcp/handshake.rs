@@ -0,0 +1,191 @@
+//! Defines a handshake at the start of a connection where the client and server swap the protocol version they
+//! speak, so peers on incompatible versions find out before any contracts are exchanged. Each side sends a
+//! 4 byte magic value followed by its protocol version (2 bytes, big endian).
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+
+/// The version of the protocol spoken by this crate. This is bumped when the framing of contracts changes in a
+/// way that older peers cannot read.
+///
+/// # Notes
+/// * `1` - Bincode contracts are written back to back with no length prefix.
+/// * `2` - Bincode contracts are framed with their length (4 bytes, little endian), see `BincodeCodec`.
+pub const PROTOCOL_VERSION: u16 = 2;
+
+/// Marks the start of a handshake so a peer that does not handshake is not mistaken for one that does.
+const HANDSHAKE_MAGIC: [u8; 4] = *b"NSPV";
+
+
+/// Sends the protocol version of this crate and reads the protocol version of the peer.
+///
+/// # Arguments
+/// * `stream` - The connection to the peer.
+/// * `send_first` - Whether this side sends its version before reading the peer's (the client does).
+///
+/// # Returns
+/// * `Result<u16, NanoServiceError>` - The protocol version spoken by the peer.
+async fn swap_versions<S>(stream: &mut S, send_first: bool) -> Result<u16, NanoServiceError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    if send_first {
+        write_version(stream).await?;
+    }
+    let mut magic = [0u8; 4];
+    stream.read_exact(&mut magic).await.map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+    })?;
+    if magic != HANDSHAKE_MAGIC {
+        return Err(NanoServiceError::new(
+            "Peer did not start the connection with a handshake".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ))
+    }
+    let peer_version = stream.read_u16().await.map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+    })?;
+    if !send_first {
+        write_version(stream).await?;
+    }
+    Ok(peer_version)
+}
+
+
+/// Writes the handshake with the protocol version of this crate.
+///
+/// # Arguments
+/// * `stream` - The connection to the peer.
+///
+/// # Returns
+/// * `Result<(), NanoServiceError>` - An error if the handshake could not be written.
+async fn write_version<S>(stream: &mut S) -> Result<(), NanoServiceError>
+where
+    S: AsyncWrite + Unpin,
+{
+    let mut handshake = Vec::with_capacity(6);
+    handshake.extend_from_slice(&HANDSHAKE_MAGIC);
+    handshake.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+    stream.write_all(&handshake).await.map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    })
+}
+
+
+/// Runs the client side of the handshake, sending the protocol version and then reading the server's.
+///
+/// # Arguments
+/// * `stream` - The connection to the server.
+///
+/// # Returns
+/// * `Result<u16, NanoServiceError>` - The protocol version spoken by the server.
+pub async fn client_handshake<S>(stream: &mut S) -> Result<u16, NanoServiceError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    swap_versions(stream, true).await
+}
+
+
+/// Runs the server side of the handshake, reading the client's protocol version and then sending the server's.
+///
+/// # Arguments
+/// * `stream` - The connection to the client.
+///
+/// # Returns
+/// * `Result<u16, NanoServiceError>` - The protocol version spoken by the client.
+pub async fn server_handshake<S>(stream: &mut S) -> Result<u16, NanoServiceError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    swap_versions(stream, false).await
+}
+
+
+/// Checks that a peer speaks the same protocol version as this crate.
+///
+/// # Arguments
+/// * `peer_version` - The protocol version reported by the peer.
+///
+/// # Returns
+/// * `Result<(), NanoServiceError>` - A `ContractNotSupported` error if the versions differ.
+pub fn check_protocol_version(peer_version: u16) -> Result<(), NanoServiceError> {
+    if peer_version != PROTOCOL_VERSION {
+        return Err(NanoServiceError::new(
+            format!("Peer speaks protocol version {} but {} is required", peer_version, PROTOCOL_VERSION),
+            NanoServiceErrorStatus::ContractNotSupported
+        ))
+    }
+    Ok(())
+}
+
+
+/// Connects to a server and asks which protocol version it speaks.
+///
+/// # Arguments
+/// * `address` - The address of the server.
+///
+/// # Returns
+/// * `Result<u16, NanoServiceError>` - The protocol version spoken by the server.
+pub async fn request_protocol_version(address: &str) -> Result<u16, NanoServiceError> {
+    let mut stream = TcpStream::connect(address).await.map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+    })?;
+    client_handshake(&mut stream).await
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio::runtime::Builder;
+
+    async fn tcp_server(addr: &str) {
+        let listener = TcpListener::bind(addr).await.unwrap();
+
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let client_version = server_handshake(&mut socket).await.unwrap();
+            assert_eq!(check_protocol_version(client_version), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_request_protocol_version() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8110";
+            let _server = tokio::spawn(tcp_server(address));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let server_version = request_protocol_version(address).await.unwrap();
+            assert_eq!(server_version, PROTOCOL_VERSION);
+        });
+    }
+
+    #[test]
+    fn test_mismatched_peers() {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+
+        runtime.block_on(async {
+            assert_eq!(
+                check_protocol_version(PROTOCOL_VERSION + 1).unwrap_err().status,
+                NanoServiceErrorStatus::ContractNotSupported
+            );
+
+            // a peer that sends a contract straight away is rejected rather than read as a version
+            let (mut client, mut server) = tokio::io::duplex(64);
+            client.write_all(&[0, 0, 0, 1, 0, 0]).await.unwrap();
+            let error = server_handshake(&mut server).await.unwrap_err();
+            assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+        });
+    }
+
+}
@@ -0,0 +1,256 @@
+//! Defines a load balancer that spreads contracts across several backend servers in round-robin order. A backend
+//! that fails to accept connections a number of times in a row is taken out of rotation for a while, and the
+//! contract is sent to the next backend instead.
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use crate::networking::tcp::client::ContractClient;
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+
+/// The number of failures in a row before a backend is taken out of rotation when one is not configured.
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+
+/// How long a backend is out of rotation for when one is not configured.
+pub const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(10);
+
+
+/// A backend server and its health.
+///
+/// # Fields
+/// * `address` - The address of the backend.
+/// * `failures` - The number of failures in a row.
+/// * `down_until` - When the backend goes back into rotation, `None` if it is in rotation.
+struct Backend {
+    address: String,
+    failures: AtomicU32,
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl Backend {
+
+    /// Checks if the backend is in rotation, putting it back once its time out of rotation has passed.
+    fn is_available(&self) -> bool {
+        match *self.down_until.lock().unwrap_or_else(|e| e.into_inner()) {
+            Some(down_until) => Instant::now() >= down_until,
+            None => true,
+        }
+    }
+
+    /// Resets the failures of the backend after a successful request.
+    fn record_success(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+        *self.down_until.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Records a failure, taking the backend out of rotation if it has failed too many times in a row.
+    fn record_failure(&self, failure_threshold: u32, retry_after: Duration) {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= failure_threshold {
+            eprintln!("Taking backend {} out of rotation after {} failures", self.address, failures);
+            *self.down_until.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now() + retry_after);
+        }
+    }
+}
+
+
+/// Spreads contracts across backend servers in round-robin order.
+///
+/// # Fields
+/// * `backends` - The backend servers.
+/// * `next` - The position in the rotation of the next request.
+/// * `failure_threshold` - The number of failures in a row before a backend is taken out of rotation.
+/// * `retry_after` - How long a backend is out of rotation for.
+#[derive(Clone)]
+pub struct ContractLoadBalancer {
+    backends: Arc<Vec<Backend>>,
+    next: Arc<AtomicUsize>,
+    failure_threshold: u32,
+    retry_after: Duration,
+}
+
+impl ContractLoadBalancer {
+
+    /// Constructs a new `ContractLoadBalancer` with the default failure threshold and retry delay.
+    ///
+    /// # Arguments
+    /// * `addresses` - The addresses of the backend servers.
+    ///
+    /// # Returns
+    /// * `ContractLoadBalancer` - The new load balancer.
+    pub fn new(addresses: &[&str]) -> Self {
+        ContractLoadBalancer {
+            backends: Arc::new(addresses.iter().map(|address| Backend {
+                address: address.to_string(),
+                failures: AtomicU32::new(0),
+                down_until: Mutex::new(None),
+            }).collect()),
+            next: Arc::new(AtomicUsize::new(0)),
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            retry_after: DEFAULT_RETRY_AFTER,
+        }
+    }
+
+    /// Sets the number of failures in a row before a backend is taken out of rotation.
+    ///
+    /// # Arguments
+    /// * `failure_threshold` - The number of failures, at least one.
+    ///
+    /// # Returns
+    /// * `ContractLoadBalancer` - The load balancer with the threshold set.
+    pub fn with_failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+
+    /// Sets how long a backend is out of rotation for before it is tried again.
+    ///
+    /// # Arguments
+    /// * `retry_after` - How long the backend is out of rotation for.
+    ///
+    /// # Returns
+    /// * `ContractLoadBalancer` - The load balancer with the retry delay set.
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Gets the addresses of the backends that are in rotation.
+    ///
+    /// # Returns
+    /// * `Vec<String>` - The addresses of the backends in rotation.
+    pub fn healthy_backends(&self) -> Vec<String> {
+        self.backends.iter()
+            .filter(|backend| backend.is_available())
+            .map(|backend| backend.address.clone())
+            .collect()
+    }
+
+    /// Sends a contract to the next backend in rotation with a `ContractClient` and returns the response. If a
+    /// backend cannot be connected to, the failure is recorded and the next backend is tried. Once the contract has
+    /// been sent it is not sent again, so a failure after connecting is recorded and returned.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to send.
+    ///
+    /// # Returns
+    /// * `Result<T, NanoServiceError>` - The response from the backend which is either the contract or an Error.
+    pub async fn send<T>(&self, contract: T) -> Result<T, NanoServiceError>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let backend_count = self.backends.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut last_error = NanoServiceError::new(
+            "No healthy backends to send the contract to".to_string(),
+            NanoServiceErrorStatus::Unknown
+        );
+
+        for offset in 0..backend_count {
+            let backend = &self.backends[(start + offset) % backend_count];
+            if !backend.is_available() {
+                continue
+            }
+            let mut client = match ContractClient::<T>::connect(&backend.address).await {
+                Ok(client) => client,
+                Err(e) => {
+                    backend.record_failure(self.failure_threshold, self.retry_after);
+                    last_error = e;
+                    continue
+                }
+            };
+            let response = client.request(contract).await;
+            match response {
+                Ok(_) => backend.record_success(),
+                Err(_) => backend.record_failure(self.failure_threshold, self.retry_after),
+            }
+            return response
+        }
+        Err(last_error)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::create_contract_handler;
+    use crate::networking::serialization::codec::BincodeCodec;
+    use futures::{sink::SinkExt, StreamExt};
+    use serde::Deserialize;
+    use tokio::net::TcpListener;
+    use tokio_util::codec::Framed;
+    use tokio::runtime::Builder;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct ServedBy {
+        pub address: String,
+    }
+
+    create_contract_handler!(
+        ContractHandler,
+        ServedBy
+    );
+
+    async fn tcp_server(addr: &'static str) {
+        let listener = TcpListener::bind(addr).await.unwrap();
+
+        while let Ok((socket, _)) = listener.accept().await {
+            let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
+
+            if let Some(Ok(_)) = framed.next().await {
+                let response = ContractHandler::ServedBy(ServedBy { address: addr.to_string() });
+                framed.send(response).await.unwrap();
+            }
+        }
+    }
+
+    async fn served_by(load_balancer: &ContractLoadBalancer) -> String {
+        let contract = ContractHandler::ServedBy(ServedBy { address: String::new() });
+        load_balancer.send(contract).await.unwrap().ServedBy().unwrap().address
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let _server_one = tokio::spawn(tcp_server("127.0.0.1:8107"));
+            let _server_two = tokio::spawn(tcp_server("127.0.0.1:8108"));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let load_balancer = ContractLoadBalancer::new(&["127.0.0.1:8107", "127.0.0.1:8108"]);
+            let mut responses = Vec::new();
+            for _ in 0..4 {
+                responses.push(served_by(&load_balancer).await);
+            }
+            assert_eq!(responses, vec![
+                "127.0.0.1:8107", "127.0.0.1:8108", "127.0.0.1:8107", "127.0.0.1:8108"
+            ]);
+
+            // nothing is listening on 8109 so it is skipped and then taken out of rotation
+            let load_balancer = ContractLoadBalancer::new(&["127.0.0.1:8109", "127.0.0.1:8107"])
+                .with_failure_threshold(2);
+            for _ in 0..4 {
+                assert_eq!(served_by(&load_balancer).await, "127.0.0.1:8107");
+            }
+            assert_eq!(load_balancer.healthy_backends(), vec!["127.0.0.1:8107"]);
+
+            let load_balancer = ContractLoadBalancer::new(&["127.0.0.1:8109"]).with_failure_threshold(1);
+            let contract = ContractHandler::ServedBy(ServedBy { address: String::new() });
+            assert!(load_balancer.send(contract).await.is_err());
+            let contract = ContractHandler::ServedBy(ServedBy { address: String::new() });
+            assert_eq!(
+                load_balancer.send(contract).await.unwrap_err().message,
+                "No healthy backends to send the contract to"
+            );
+        });
+    }
+
+}
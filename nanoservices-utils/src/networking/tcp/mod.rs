@@ -1,3 +1,12 @@
 pub mod client;
+pub mod events;
+pub mod file_stream;
+pub mod handshake;
+pub mod load_balancer;
+pub mod pool;
 pub mod routing;
-// pub mod wasm_proxy;
+pub mod server;
+pub mod subscription;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod wasm_proxy;
@@ -0,0 +1,360 @@
+//! Defines a pool of TCP connections to a single address. Connections are handed out as guards that return the
+//! connection to the pool when they are dropped, so requests reuse connections and the total number of
+//! connections is capped.
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use crate::networking::serialization::codec::BincodeCodec;
+use futures::{sink::SinkExt, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::net::TcpStream;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::codec::Framed;
+
+
+/// A connection kept with its framing. The codec is swapped for the contract type of each exchange.
+type IdleConnection = Framed<TcpStream, BincodeCodec<()>>;
+
+
+/// The state that is shared between the pool and the guards it hands out.
+///
+/// # Fields
+/// * `address` - The address that the connections are made to.
+/// * `idle` - The connections that are not being used, framed so bytes read ahead are kept with the connection.
+/// * `permits` - Caps the number of connections that exist at the same time.
+struct PoolInner {
+    address: String,
+    idle: Mutex<Vec<IdleConnection>>,
+    permits: Arc<Semaphore>,
+}
+
+
+/// A pool of TCP connections to a single address.
+///
+/// # Fields
+/// * `inner` - The state shared with the guards handed out by the pool.
+#[derive(Clone)]
+pub struct ConnectionPool {
+    inner: Arc<PoolInner>,
+}
+
+impl ConnectionPool {
+
+    /// Constructs a new empty `ConnectionPool`. Connections are made lazily when `get` is called.
+    ///
+    /// # Arguments
+    /// * `address` - The address that the connections are made to.
+    /// * `max_connections` - The maximum number of connections that can exist at the same time.
+    ///
+    /// # Returns
+    /// * `ConnectionPool` - The new pool.
+    pub fn new(address: &str, max_connections: usize) -> Self {
+        ConnectionPool {
+            inner: Arc::new(PoolInner {
+                address: address.to_string(),
+                idle: Mutex::new(Vec::new()),
+                permits: Arc::new(Semaphore::new(max_connections)),
+            }),
+        }
+    }
+
+    /// Gets a connection from the pool, waiting if the maximum number of connections are in use. An idle
+    /// connection is reused if there is one, otherwise a new connection is made.
+    ///
+    /// # Returns
+    /// * `Result<PooledConnection, NanoServiceError>` - The guard holding the connection.
+    pub async fn get(&self) -> Result<PooledConnection, NanoServiceError> {
+        let permit = self.inner.permits.clone().acquire_owned().await.map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+        })?;
+        let idle = self.inner.idle.lock().unwrap_or_else(PoisonError::into_inner).pop();
+        let framed = match idle {
+            Some(framed) => framed,
+            None => Framed::new(
+                TcpStream::connect(&self.inner.address).await.map_err(|e| {
+                    NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+                })?,
+                BincodeCodec::new()
+            ),
+        };
+        Ok(PooledConnection {
+            framed: Some(framed),
+            healthy: false,
+            pool: self.inner.clone(),
+            _permit: permit,
+        })
+    }
+
+    /// Gets the number of connections that are waiting in the pool to be reused.
+    ///
+    /// # Returns
+    /// * `usize` - The number of idle connections.
+    pub fn idle_count(&self) -> usize {
+        self.inner.idle.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+}
+
+
+/// A connection that has been taken from a `ConnectionPool`. When dropped, the connection is returned to the
+/// pool if it is healthy and discarded otherwise. A connection only becomes healthy once a whole exchange has
+/// finished on it, so a guard dropped part way through an exchange never hands an unread response to the next
+/// borrower. The connection is dropped with such a cancelled exchange, so after it the guard only returns errors
+/// and should be dropped.
+///
+/// # Fields
+/// * `framed` - The connection and its framing.
+/// * `healthy` - Whether the connection can be reused.
+/// * `pool` - The pool the connection is returned to.
+/// * `_permit` - Holds a slot in the pool until the guard is dropped.
+pub struct PooledConnection {
+    framed: Option<IdleConnection>,
+    healthy: bool,
+    pool: Arc<PoolInner>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledConnection {
+
+    /// Marks the connection as broken so it is discarded instead of being returned to the pool.
+    pub fn mark_unhealthy(&mut self) {
+        self.healthy = false;
+    }
+
+    /// Marks the connection as safe to reuse. Only needed when the connection is used directly rather than
+    /// through `send_contract`, once nothing is left unread on it.
+    pub fn mark_healthy(&mut self) {
+        self.healthy = true;
+    }
+
+    /// Gets the underlying stream to use the connection directly.
+    ///
+    /// # Returns
+    /// * `Result<&TcpStream, NanoServiceError>` - The stream, or an error if it was dropped with a cancelled exchange.
+    pub fn stream(&self) -> Result<&TcpStream, NanoServiceError> {
+        self.framed.as_ref().map(Framed::get_ref).ok_or_else(cancelled_exchange)
+    }
+
+    /// Gets the underlying stream mutably to use the connection directly, e.g. to write raw bytes.
+    ///
+    /// # Returns
+    /// * `Result<&mut TcpStream, NanoServiceError>` - The stream, or an error if it was dropped with a cancelled
+    ///   exchange.
+    pub fn stream_mut(&mut self) -> Result<&mut TcpStream, NanoServiceError> {
+        self.framed.as_mut().map(Framed::get_mut).ok_or_else(cancelled_exchange)
+    }
+
+    /// Sends a data contract over the connection and waits for the response. The connection is only marked as
+    /// healthy once the response has been read, so it is discarded if anything fails or the future is dropped
+    /// before the exchange finishes.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to send.
+    ///
+    /// # Returns
+    /// * `Result<T, NanoServiceError>` - The response from the server which is either the contract or an Error.
+    pub async fn send_contract<T>(&mut self, contract: T) -> Result<T, NanoServiceError>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        self.mark_unhealthy();
+        let result = self.exchange(contract).await;
+        if result.is_ok() {
+            self.mark_healthy();
+        }
+        result
+    }
+
+    async fn exchange<T>(&mut self, contract: T) -> Result<T, NanoServiceError>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        // the framing is taken for the exchange, if the future is dropped part way the connection is dropped with it
+        let framed = self.framed.take().ok_or_else(cancelled_exchange)?;
+        let mut framed = framed.map_codec(|_| BincodeCodec::<T>::new());
+        let result = exchange_on(&mut framed, contract).await;
+        self.framed = Some(framed.map_codec(|_| BincodeCodec::new()));
+        result
+    }
+}
+
+/// The error returned when a guard is used after its connection was dropped with a cancelled exchange.
+///
+/// # Returns
+/// * `NanoServiceError` - The error.
+fn cancelled_exchange() -> NanoServiceError {
+    NanoServiceError::new(
+        "Connection was dropped with a cancelled exchange".to_string(),
+        NanoServiceErrorStatus::Unknown
+    )
+}
+
+/// Sends a data contract over a framed connection and reads the response.
+///
+/// # Arguments
+/// * `framed` - The framed connection.
+/// * `contract` - The contract to send.
+///
+/// # Returns
+/// * `Result<T, NanoServiceError>` - The response from the server which is either the contract or an Error.
+async fn exchange_on<T>(framed: &mut Framed<TcpStream, BincodeCodec<T>>, contract: T) -> Result<T, NanoServiceError>
+where
+    T: Serialize + DeserializeOwned,
+{
+    framed.send(contract).await.map_err(|e| {
+        NanoServiceError::from_io_error(e, NanoServiceErrorStatus::BadRequest)
+    })?;
+    let response = match framed.next().await {
+        Some(response) => response,
+        None => return Err(NanoServiceError::new("No response from server.".to_string(), NanoServiceErrorStatus::BadRequest))
+    };
+    response.map_err(|e| {
+        NanoServiceError::from_io_error(e, NanoServiceErrorStatus::BadRequest)
+    })
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if !self.healthy {
+            return
+        }
+        if let Some(framed) = self.framed.take() {
+            // a panic while the lock was held cannot leave the list half updated, so a poisoned lock is recovered
+            // rather than panicking again in drop
+            self.pool.idle.lock().unwrap_or_else(PoisonError::into_inner).push(framed);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::create_contract_handler;
+    use serde::Deserialize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+    use tokio::runtime::Builder;
+
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct ContractOne {
+        pub value: i32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct ContractTwo;
+
+    create_contract_handler!(
+        ContractHandler,
+        ContractOne,
+        ContractTwo
+    );
+
+    static ACCEPTED: AtomicUsize = AtomicUsize::new(0);
+
+    async fn echo_server(addr: &str) {
+        let listener = TcpListener::bind(addr).await.unwrap();
+
+        while let Ok((socket, _)) = listener.accept().await {
+            ACCEPTED.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                // one frame for the whole connection so bytes read ahead of a contract are kept for the next one
+                let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
+                while let Some(Ok(data)) = framed.next().await {
+                    framed.send(data).await.unwrap();
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn test_pool_reuses_bounded_connections() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8102";
+            let _server = tokio::spawn(echo_server(address));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let pool = ConnectionPool::new(address, 2);
+            for value in 0..10 {
+                let mut connection = pool.get().await.unwrap();
+                let response = connection.send_contract(ContractHandler::ContractOne(ContractOne { value })).await.unwrap();
+                assert_eq!(response.ContractOne().unwrap(), ContractOne { value });
+            }
+            assert_eq!(ACCEPTED.load(Ordering::SeqCst), 1);
+            assert_eq!(pool.idle_count(), 1);
+
+            let mut handles = Vec::new();
+            for value in 0..6 {
+                let pool = pool.clone();
+                handles.push(tokio::spawn(async move {
+                    let mut connection = pool.get().await.unwrap();
+                    connection.send_contract(ContractHandler::ContractOne(ContractOne { value })).await.unwrap()
+                }));
+            }
+            for handle in handles {
+                handle.await.unwrap();
+            }
+            assert!(ACCEPTED.load(Ordering::SeqCst) <= 2);
+
+            // an unhealthy connection is discarded rather than returned
+            let idle_before = pool.idle_count();
+            let mut connection = pool.get().await.unwrap();
+            connection.mark_unhealthy();
+            drop(connection);
+            assert_eq!(pool.idle_count(), idle_before - 1);
+
+            // a poisoned lock is recovered so getting and returning connections does not panic
+            let inner = pool.inner.clone();
+            let _ = std::thread::spawn(move || {
+                let _idle = inner.idle.lock().unwrap();
+                panic!("poison the idle connections");
+            }).join();
+            let mut connection = pool.get().await.unwrap();
+            connection.send_contract(ContractHandler::ContractOne(ContractOne { value: 1 })).await.unwrap();
+            drop(connection);
+            assert_eq!(pool.idle_count(), idle_before - 1);
+        });
+    }
+
+    #[test]
+    fn test_cancelled_exchange_is_not_returned() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8137";
+            let listener = TcpListener::bind(address).await.unwrap();
+            let _server = tokio::spawn(async move {
+                let (socket, _) = listener.accept().await.unwrap();
+                let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
+                while let Some(Ok(data)) = framed.next().await {
+                    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+                    framed.send(data).await.unwrap();
+                }
+            });
+
+            let pool = ConnectionPool::new(address, 1);
+            let mut connection = pool.get().await.unwrap();
+            let send = connection.send_contract(ContractHandler::ContractOne(ContractOne { value: 1 }));
+            assert!(tokio::time::timeout(tokio::time::Duration::from_millis(50), send).await.is_err());
+            // the guard reports the dropped connection instead of panicking
+            assert!(connection.stream().is_err());
+            assert!(connection.stream_mut().is_err());
+            let error = connection.send_contract(ContractHandler::ContractOne(ContractOne { value: 2 })).await.unwrap_err();
+            assert_eq!(error.message, "Connection was dropped with a cancelled exchange");
+            drop(connection);
+            // the response to the cancelled exchange is still on its way, so the connection is not reused
+            assert_eq!(pool.idle_count(), 0);
+        });
+    }
+
+}
@@ -1,20 +1,42 @@
+//! Defines the routing of contracts to handler functions for TCP servers.
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use futures::FutureExt;
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use crate::networking::envelope::{Envelope, EnvelopeContext};
+
+
+tokio::task_local! {
+    static ENVELOPE_CONTEXT: EnvelopeContext;
+}
+
 #[macro_export]
 macro_rules! register_contract_routes {
-    ($handler_enum:ident, $fn_name:ident, $( $contract:ident => $handler_fn:path ),*) => {
+    ($handler_enum:ident, $fn_name:ident, $( $( $contract:ident )|+ => $handler_fn:path ),*) => {
         pub async fn $fn_name(received_msg: $handler_enum) -> Result<$handler_enum, NanoServiceError> {
             match received_msg {
                 msg => match msg {
                     $(
-                        $handler_enum::$contract(inner) => {
-                            // need to add error handling
-                            let executed_contract = $handler_fn(inner).await?;
-                            return Ok($handler_enum::$contract(executed_contract));
-                        }
+                        $(
+                            $handler_enum::$contract(inner) => {
+                                // need to add error handling
+                                let executed_contract = $handler_fn(inner).await?;
+                                return Ok($handler_enum::$contract(executed_contract));
+                            }
+                        )+
                     )*
-                    _ => Err(NanoServiceError::new(
-                            "Received unknown contract type.".to_string(),
-                            NanoServiceErrorStatus::ContractNotSupported
-                        )),
+                    // an error contract is passed back as the error rather than reported as an unknown contract
+                    $handler_enum::NanoServiceError(error) => Err(error),
+                    #[allow(unreachable_patterns)]
+                    unsupported => {
+                        let format = $crate::networking::wire_ref::WireRefFormat::CURRENT;
+                        let supported_refs = vec![ $( $( format.contract_ref(stringify!($contract)), )+ )* ];
+                        Err($crate::networking::wire_ref::contract_not_supported(
+                            &unsupported.to_string_ref(),
+                            &supported_refs
+                        ))
+                    },
                 },
             }
         }
@@ -22,6 +44,87 @@ macro_rules! register_contract_routes {
 }
 
 
+/// Runs a handler future and converts a panic inside the handler into a `NanoServiceError` so the server
+/// loop can send an error contract back to the client instead of dropping the connection. This is opt-in,
+/// wrap the call to the routing function made by `register_contract_routes!` to use it.
+///
+/// # Arguments
+/// * `handler_future` - The future returned by the handler (e.g. `handle_contract(contract)`).
+///
+/// # Returns
+/// * `Result<H, NanoServiceError>` - The result of the handler or an `Unknown` error if the handler panicked.
+pub async fn catch_handler_panic<H, Fut>(handler_future: Fut) -> Result<H, NanoServiceError>
+where
+    Fut: Future<Output = Result<H, NanoServiceError>>,
+{
+    match AssertUnwindSafe(handler_future).catch_unwind().await {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = panic_message(payload.as_ref());
+            eprintln!("Handler panicked: {}", message);
+            Err(NanoServiceError::new(
+                format!("Handler panicked: {}", message),
+                NanoServiceErrorStatus::Unknown
+            ))
+        }
+    }
+}
+
+/// Unwraps the contract from an envelope and passes it to the handler, wrapping the response in an envelope with
+/// the same ID, token, and trace as the request. The envelope fields are available to the handler (and anything
+/// it calls) through `current_envelope_context` so handlers made by `register_contract_routes!` do not change.
+///
+/// # Arguments
+/// * `envelope` - The envelope received from the client.
+/// * `handler` - The handler for the inner contract (e.g. the function made by `register_contract_routes!`).
+/// * `into_error` - Converts an error from the handler into a contract (e.g. `ContractHandler::NanoServiceError`).
+///
+/// # Returns
+/// * `Envelope<H>` - The envelope wrapping the response of the handler.
+pub async fn dispatch_envelope<H, F, Fut>(
+    envelope: Envelope<H>,
+    handler: F,
+    into_error: fn(NanoServiceError) -> H
+) -> Envelope<H>
+where
+    F: FnOnce(H) -> Fut,
+    Fut: Future<Output = Result<H, NanoServiceError>>,
+{
+    let (context, contract) = envelope.into_parts();
+    let response = ENVELOPE_CONTEXT.scope(context.clone(), handler(contract)).await;
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => into_error(e)
+    };
+    Envelope::from_parts(context, response)
+}
+
+/// Gets the fields of the envelope that is being handled by `dispatch_envelope`.
+///
+/// # Returns
+/// * `Option<EnvelopeContext>` - The fields of the envelope or `None` if not called inside `dispatch_envelope`.
+pub fn current_envelope_context() -> Option<EnvelopeContext> {
+    ENVELOPE_CONTEXT.try_with(|context| context.clone()).ok()
+}
+
+/// Extracts the message from a panic payload.
+///
+/// # Arguments
+/// * `payload` - The payload caught from the panic.
+///
+/// # Returns
+/// * `String` - The message of the panic.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string()
+    }
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone()
+    }
+    "unknown panic payload".to_string()
+}
+
+
 #[cfg(test)]
 mod tests {
 
@@ -29,6 +132,13 @@ mod tests {
     use serde::{Serialize, Deserialize};
     use crate::create_contract_handler;
     use tokio::runtime::Builder;
+    use tokio::net::TcpListener;
+    use tokio_util::codec::Framed;
+    use futures::{sink::SinkExt, StreamExt};
+    use crate::networking::serialization::codec::BincodeCodec;
+    use crate::networking::tcp::client::send_data_contract_over_tcp;
+    use super::{catch_handler_panic, dispatch_envelope, current_envelope_context};
+    use crate::networking::envelope::Envelope;
 
 
     #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -62,6 +172,87 @@ mod tests {
         ContractTwo => handle_test_contract_two
     );
 
+    async fn handle_any_contract<T>(contract: T) -> Result<T, NanoServiceError> {
+        Ok(contract)
+    }
+
+    register_contract_routes!(
+        ContractHandler,
+        handle_contract_with_shared_handler,
+        ContractOne | ContractTwo => handle_any_contract,
+        ContractThree => handle_any_contract
+    );
+
+    async fn handle_panicking_contract_two(_contract: ContractTwo) -> Result<ContractTwo, NanoServiceError> {
+        panic!("contract two handler failed");
+    }
+
+    register_contract_routes!(
+        ContractHandler,
+        handle_contract_with_panic,
+        ContractOne => handle_test_contract_one,
+        ContractTwo => handle_panicking_contract_two
+    );
+
+    async fn tcp_server_catching_panics(addr: &str) {
+        let listener = TcpListener::bind(addr).await.unwrap();
+
+        while let Ok((socket, _)) = listener.accept().await {
+            let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
+
+            if let Some(Ok(data)) = framed.next().await {
+                let response = ContractHandler::dispatch_result(
+                    catch_handler_panic(handle_contract_with_panic(data)).await
+                );
+                framed.send(response).await.unwrap();
+            }
+        }
+    }
+
+    async fn tcp_routed_server(addr: &str) {
+        let listener = TcpListener::bind(addr).await.unwrap();
+
+        while let Ok((socket, _)) = listener.accept().await {
+            let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
+
+            if let Some(Ok(data)) = framed.next().await {
+                let response = ContractHandler::dispatch_result(handle_contract(data).await);
+                framed.send(response).await.unwrap();
+            }
+        }
+    }
+
+    async fn handle_contract_one_with_context(contract: ContractOne) -> Result<ContractOne, NanoServiceError> {
+        let context = current_envelope_context().unwrap();
+        if context.token != Some("token".to_string()) {
+            return Err(NanoServiceError::new("Missing token".to_string(), NanoServiceErrorStatus::Unauthorized))
+        }
+        Ok(contract)
+    }
+
+    register_contract_routes!(
+        ContractHandler,
+        handle_contract_with_context,
+        ContractOne => handle_contract_one_with_context
+    );
+
+    async fn tcp_envelope_server(addr: &str) {
+        let listener = TcpListener::bind(addr).await.unwrap();
+
+        while let Ok((socket, _)) = listener.accept().await {
+            let mut framed = Framed::new(socket, BincodeCodec::<Envelope<ContractHandler>>::new());
+
+            if let Some(Ok(envelope)) = framed.next().await {
+                let response = dispatch_envelope(
+                    envelope,
+                    handle_contract_with_context,
+                    ContractHandler::NanoServiceError
+                ).await;
+                framed.send(response).await.unwrap();
+            }
+        }
+    }
+
     #[test]
     fn test_register_contract_routes() {
         let runtime = Builder::new_multi_thread()
@@ -82,10 +273,113 @@ mod tests {
             assert_eq!(handled_contract_one, ContractHandler::ContractOne(ContractOne));
             assert_eq!(handled_contract_two, ContractHandler::ContractTwo(ContractTwo));
             assert_eq!(handled_contract_three, Err(NanoServiceError::new(
-                "Received unknown contract type.".to_string(),
+                "Contract not supported: contractthree_contract. Supported contracts: contractone_contract, contracttwo_contract".to_string(),
                 NanoServiceErrorStatus::ContractNotSupported
             )));
+
+            let error = NanoServiceError::new("Client failed".to_string(), NanoServiceErrorStatus::BadRequest);
+            let handled_error = handle_contract(ContractHandler::NanoServiceError(error.clone())).await;
+            assert_eq!(handled_error, Err(error));
         });
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_shared_handler() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let handled_contract_one = handle_contract_with_shared_handler(ContractHandler::ContractOne(ContractOne)).await;
+            let handled_contract_two = handle_contract_with_shared_handler(ContractHandler::ContractTwo(ContractTwo)).await;
+            let handled_contract_three = handle_contract_with_shared_handler(ContractHandler::ContractThree(ContractThree)).await;
+
+            assert_eq!(handled_contract_one, Ok(ContractHandler::ContractOne(ContractOne)));
+            assert_eq!(handled_contract_two, Ok(ContractHandler::ContractTwo(ContractTwo)));
+            assert_eq!(handled_contract_three, Ok(ContractHandler::ContractThree(ContractThree)));
+        });
+    }
+
+    #[test]
+    fn test_catch_handler_panic() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8100";
+            let _server = tokio::spawn(tcp_server_catching_panics(address));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let contract_two = ContractHandler::ContractTwo(ContractTwo);
+            let response_two = send_data_contract_over_tcp(contract_two, address).await.unwrap();
+            assert_eq!(response_two.NanoServiceError().unwrap(), NanoServiceError::new(
+                "Handler panicked: contract two handler failed".to_string(),
+                NanoServiceErrorStatus::Unknown
+            ));
+
+            // the server is still up after the panic
+            let contract_one = ContractHandler::ContractOne(ContractOne);
+            let response_one = send_data_contract_over_tcp(contract_one, address).await.unwrap();
+            assert_eq!(response_one.ContractOne().unwrap(), ContractOne);
+        });
+    }
+
+    #[test]
+    fn test_dispatch_envelope() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8101";
+            let _server = tokio::spawn(tcp_envelope_server(address));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let envelope = Envelope::new(1, ContractHandler::ContractOne(ContractOne))
+                .with_token("token".to_string())
+                .with_trace("trace".to_string());
+            let response = send_data_contract_over_tcp(envelope, address).await.unwrap();
+            assert_eq!(response.id, 1);
+            assert_eq!(response.trace, Some("trace".to_string()));
+            assert_eq!(response.contract.ContractOne().unwrap(), ContractOne);
+
+            let envelope = Envelope::new(2, ContractHandler::ContractOne(ContractOne));
+            let response = send_data_contract_over_tcp(envelope, address).await.unwrap();
+            assert_eq!(response.id, 2);
+            assert_eq!(response.contract.NanoServiceError().unwrap().status, NanoServiceErrorStatus::Unauthorized);
+        });
+
+        assert_eq!(current_envelope_context(), None);
+    }
+
+    #[test]
+    fn test_unknown_contract_lists_supported_refs() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8133";
+            let _server = tokio::spawn(tcp_routed_server(address));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            // the server has no route for contract three
+            let contract_three = ContractHandler::ContractThree(ContractThree);
+            let error = send_data_contract_over_tcp(contract_three, address).await.unwrap().NanoServiceError().unwrap();
+            assert_eq!(error.status, NanoServiceErrorStatus::ContractNotSupported);
+            assert_eq!(
+                error.message,
+                "Contract not supported: contractthree_contract. Supported contracts: contractone_contract, contracttwo_contract"
+            );
+        });
+    }
+}
@@ -0,0 +1,793 @@
+//! Defines helpers for setting up TCP servers that serve contracts.
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use crate::networking::serialization::codec::BincodeCodec;
+use crate::networking::tcp::events::ContractEvents;
+use futures::{sink::SinkExt, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tokio::sync::oneshot;
+use tokio_util::codec::Framed;
+use tokio_util::sync::CancellationToken;
+#[cfg(target_os = "linux")]
+use std::thread::JoinHandle;
+#[cfg(target_os = "linux")]
+use tokio::{runtime::Builder, task::LocalSet};
+
+
+/// The accept backlog used when one is not configured.
+pub const DEFAULT_BACKLOG: u32 = 1024;
+
+/// The number of requests handled at once on a connection when one is not configured.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 32;
+
+/// The wait after a failed accept, doubled for every failure in a row up to `MAX_ACCEPT_BACKOFF`.
+const INITIAL_ACCEPT_BACKOFF: Duration = Duration::from_millis(5);
+
+/// The longest wait after a failed accept.
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+
+/// How a connection is cancelled and the contract that is sent for the requests it was handling.
+///
+/// # Fields
+/// * `token` - Cancels the connection when it is cancelled.
+/// * `error_contract` - Wraps the error sent for each request that was being handled (e.g. `ContractHandler::NanoServiceError`).
+struct Cancellation<'a, H> {
+    token: &'a CancellationToken,
+    error_contract: fn(NanoServiceError) -> H,
+}
+
+
+/// Binds a TCP listener with `SO_REUSEADDR` set so a server can be restarted on the same address straight away
+/// without "address in use" errors from connections left in `TIME_WAIT`.
+///
+/// # Arguments
+/// * `address` - The address to bind to (e.g. `127.0.0.1:8080`).
+/// * `backlog` - The maximum number of pending connections waiting to be accepted.
+///
+/// # Returns
+/// * `Result<TcpListener, NanoServiceError>` - The listener bound to the address.
+pub fn bind_listener(address: &str, backlog: u32) -> Result<TcpListener, NanoServiceError> {
+    bind_socket(address, backlog, false)
+}
+
+
+/// Binds a TCP listener with `SO_REUSEPORT` (as well as `SO_REUSEADDR`) set so several listeners can be bound to
+/// the same address with the kernel load balancing incoming connections between them. This is Linux only as other
+/// platforms either do not support `SO_REUSEPORT` or do not balance connections across the listeners.
+///
+/// # Arguments
+/// * `address` - The address to bind to (e.g. `127.0.0.1:8080`).
+/// * `backlog` - The maximum number of pending connections waiting to be accepted.
+///
+/// # Returns
+/// * `Result<TcpListener, NanoServiceError>` - The listener bound to the address.
+#[cfg(target_os = "linux")]
+pub fn bind_reuseport_listener(address: &str, backlog: u32) -> Result<TcpListener, NanoServiceError> {
+    bind_socket(address, backlog, true)
+}
+
+
+/// Runs a server that spawns the handler for each connection accepted. The bound address is sent on `ready` once
+/// the listener is bound and before any connection is accepted, so orchestration and health checks can wait until
+/// the server is accepting connections. This only returns if the listener could not be bound.
+///
+/// # Arguments
+/// * `address` - The address to bind to (e.g. `127.0.0.1:8080`, or port `0` for any free port).
+/// * `backlog` - The maximum number of pending connections waiting to be accepted.
+/// * `ready` - Sent the address the listener is bound to once it is bound.
+/// * `handler` - Called with each accepted connection and the address of the client.
+///
+/// # Returns
+/// * `Result<(), NanoServiceError>` - The error hit while binding the listener.
+pub async fn run_server<F, Fut>(
+    address: &str,
+    backlog: u32,
+    ready: oneshot::Sender<SocketAddr>,
+    handler: F
+) -> Result<(), NanoServiceError>
+where
+    F: Fn(TcpStream, SocketAddr) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let listener = bind_listener(address, backlog)?;
+    // nobody waiting for the signal is not a reason to stop serving
+    let _ = ready.send(listener.local_addr()?);
+    loop {
+        let (stream, peer) = accept_with_backoff(&listener).await;
+        tokio::spawn(handler(stream, peer));
+    }
+}
+
+
+/// Accepts the next connection on a listener. An accept that fails (e.g. the process is out of file descriptors)
+/// is reported and followed by a wait before the next attempt, doubled for every failure in a row, so the accept
+/// loop does not spin while the cause lasts.
+///
+/// # Arguments
+/// * `listener` - The listener to accept the connection on.
+///
+/// # Returns
+/// * `(TcpStream, SocketAddr)` - The accepted connection and the address of the client.
+async fn accept_with_backoff(listener: &TcpListener) -> (TcpStream, SocketAddr) {
+    let mut backoff = INITIAL_ACCEPT_BACKOFF;
+    loop {
+        match listener.accept().await {
+            Ok(accepted) => return accepted,
+            Err(e) => {
+                let error = NanoServiceError::from_source(e, NanoServiceErrorStatus::ServiceUnavailable);
+                eprintln!("Failed to accept connection, retrying in {:?}: {}", backoff, error);
+            }
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+    }
+}
+
+
+/// Runs a thread-per-core server. Each worker thread has its own single threaded runtime and its own listener
+/// bound with `SO_REUSEPORT` on the same address, so the kernel spreads connections across the workers and a
+/// connection is served on the thread that accepted it. Because the connections never move between threads the
+/// handler futures do not need to be `Send`. This is Linux only (see `bind_reuseport_listener`).
+///
+/// # Notes
+/// The workers stop accepting and their threads finish once `cancel` is cancelled. The connections still being
+/// served are dropped with the runtime of the worker, so pass the same token to `serve_connection_until_cancelled`
+/// in the handler to send clients an error for their requests first.
+///
+/// # Arguments
+/// * `address` - The address every worker binds to (e.g. `127.0.0.1:8080`).
+/// * `workers` - The number of worker threads, normally the number of cores.
+/// * `backlog` - The accept backlog of each worker's listener.
+/// * `cancel` - Stops the workers when it is cancelled.
+/// * `handler` - Called on the worker thread with each accepted connection.
+///
+/// # Returns
+/// * `Result<Vec<JoinHandle<()>>, NanoServiceError>` - The handles of the worker threads once every worker has
+///   bound its listener, or the first error hit while starting the workers (including a worker that panicked).
+///   The workers already started are stopped and joined before the error is returned.
+#[cfg(target_os = "linux")]
+pub fn run_per_core<F, Fut>(
+    address: &str,
+    workers: usize,
+    backlog: u32,
+    cancel: &CancellationToken,
+    handler: F
+) -> Result<Vec<JoinHandle<()>>, NanoServiceError>
+where
+    F: Fn(TcpStream, SocketAddr) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    if workers == 0 {
+        return Err(NanoServiceError::new(
+            "A thread-per-core server needs at least one worker".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ))
+    }
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Result<(), NanoServiceError>>();
+    let mut handles = Vec::with_capacity(workers);
+    // a child token so a server that fails to start stops its own workers without cancelling the caller's token
+    let shutdown = cancel.child_token();
+
+    for worker in 0..workers {
+        let address = address.to_string();
+        let handler = handler.clone();
+        let mut ready = WorkerReady { sender: Some(ready_tx.clone()) };
+        let worker_shutdown = shutdown.clone();
+        let spawned = std::thread::Builder::new()
+            .name(format!("nanoservice-worker-{}", worker))
+            .spawn(move || {
+                let runtime = match Builder::new_current_thread().enable_all().build() {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        ready.send(Err(NanoServiceError::new(
+                            e.to_string(),
+                            NanoServiceErrorStatus::Unknown
+                        )));
+                        return
+                    }
+                };
+                let local = LocalSet::new();
+                local.block_on(&runtime, async move {
+                    let listener = match bind_reuseport_listener(&address, backlog) {
+                        Ok(listener) => listener,
+                        Err(e) => {
+                            ready.send(Err(e));
+                            return
+                        }
+                    };
+                    ready.send(Ok(()));
+                    loop {
+                        let (stream, peer) = tokio::select! {
+                            _ = worker_shutdown.cancelled() => return,
+                            accepted = accept_with_backoff(&listener) => accepted
+                        };
+                        tokio::task::spawn_local(handler(stream, peer));
+                    }
+                });
+            });
+        match spawned {
+            Ok(handle) => handles.push(handle),
+            Err(e) => return Err(stop_workers(
+                &shutdown,
+                handles,
+                NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+            ))
+        }
+    }
+    drop(ready_tx);
+
+    for _ in 0..workers {
+        let ready = ready_rx.recv().map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+        });
+        if let Err(e) = ready.and_then(|ready| ready) {
+            return Err(stop_workers(&shutdown, handles, e))
+        }
+    }
+    Ok(handles)
+}
+
+
+/// Reports whether a worker of a thread-per-core server started. A worker that panics or stops before reporting
+/// sends an error when this is dropped, so `run_per_core` does not wait forever on a worker that never binds.
+///
+/// # Fields
+/// * `sender` - Where the result is sent, `None` once it has been sent.
+#[cfg(target_os = "linux")]
+struct WorkerReady {
+    sender: Option<std::sync::mpsc::Sender<Result<(), NanoServiceError>>>,
+}
+
+#[cfg(target_os = "linux")]
+impl WorkerReady {
+
+    /// Sends whether the worker started. Only the first result is sent.
+    ///
+    /// # Arguments
+    /// * `result` - Whether the worker bound its listener.
+    fn send(&mut self, result: Result<(), NanoServiceError>) {
+        if let Some(sender) = self.sender.take() {
+            // nobody waiting means the server already failed to start
+            let _ = sender.send(result);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for WorkerReady {
+    fn drop(&mut self) {
+        self.send(Err(NanoServiceError::new(
+            "Worker stopped before its listener was bound".to_string(),
+            NanoServiceErrorStatus::Unknown
+        )));
+    }
+}
+
+
+/// Stops the workers of a thread-per-core server that failed to start and waits for their threads to finish, so
+/// no listener is left bound to the address once the error is returned.
+///
+/// # Arguments
+/// * `shutdown` - The token the accept loops of the workers stop on.
+/// * `handles` - The handles of the worker threads started.
+/// * `error` - The error that stopped the server starting.
+///
+/// # Returns
+/// * `NanoServiceError` - The error that stopped the server starting.
+#[cfg(target_os = "linux")]
+fn stop_workers(shutdown: &CancellationToken, handles: Vec<JoinHandle<()>>, error: NanoServiceError) -> NanoServiceError {
+    shutdown.cancel();
+    for handle in handles {
+        let _ = handle.join();
+    }
+    error
+}
+
+
+/// Serves the contracts sent over a connection until the client closes it. Requests pipelined on the connection
+/// are handled concurrently, but once `max_in_flight` requests are being handled the connection is not read from
+/// until one of them completes, so a client flooding requests cannot queue an unbounded number of handlers.
+/// Responses are sent in the order the requests arrived.
+///
+/// # Arguments
+/// * `stream` - The connection to the client.
+/// * `max_in_flight` - The most requests handled at once on the connection, at least one.
+/// * `handler` - Called with each contract received and returns the contract to send back.
+///
+/// # Returns
+/// * `Result<(), NanoServiceError>` - An error if a contract could not be read or a response could not be sent.
+pub async fn serve_connection<S, H, F, Fut>(stream: S, max_in_flight: usize, handler: F) -> Result<(), NanoServiceError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: Serialize + DeserializeOwned,
+    F: Fn(H) -> Fut,
+    Fut: Future<Output = H>,
+{
+    serve(stream, max_in_flight, None, None, handler).await
+}
+
+
+/// Serves the contracts sent over a connection like `serve_connection` until `cancel` is cancelled (e.g. when the
+/// server is shutting down). On cancellation the handlers that are running are dropped and a `ServiceUnavailable`
+/// error is sent for each of their requests before the connection is closed, so clients fail fast instead of
+/// waiting for responses that will never come. Sending the errors is best-effort as the client may have gone.
+///
+/// # Notes
+/// Cancel the connection with the token rather than aborting the task running it, a task that is aborted is
+/// dropped without a chance to send anything.
+///
+/// # Arguments
+/// * `stream` - The connection to the client.
+/// * `max_in_flight` - The most requests handled at once on the connection, at least one.
+/// * `cancel` - Cancels the connection when it is cancelled.
+/// * `error_contract` - Wraps the error sent for each request that was being handled (e.g. `ContractHandler::NanoServiceError`).
+/// * `handler` - Called with each contract received and returns the contract to send back.
+///
+/// # Returns
+/// * `Result<(), NanoServiceError>` - An error if a contract could not be read or a response could not be sent.
+pub async fn serve_connection_until_cancelled<S, H, F, Fut>(
+    stream: S,
+    max_in_flight: usize,
+    cancel: &CancellationToken,
+    error_contract: fn(NanoServiceError) -> H,
+    handler: F
+) -> Result<(), NanoServiceError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: Serialize + DeserializeOwned,
+    F: Fn(H) -> Fut,
+    Fut: Future<Output = H>,
+{
+    let cancellation = Cancellation { token: cancel, error_contract };
+    serve(stream, max_in_flight, None, Some(cancellation), handler).await
+}
+
+
+/// Serves the contracts sent over a connection like `serve_connection`, sending an event for every contract
+/// handled to the subscribers of `events`.
+///
+/// # Arguments
+/// * `stream` - The connection to the client.
+/// * `max_in_flight` - The most requests handled at once on the connection, at least one.
+/// * `events` - The stream the events of handled contracts are sent to.
+/// * `handler` - Called with each contract received and returns the contract to send back.
+///
+/// # Returns
+/// * `Result<(), NanoServiceError>` - An error if a contract could not be read or a response could not be sent.
+pub async fn serve_connection_with_events<S, H, F, Fut>(
+    stream: S,
+    max_in_flight: usize,
+    events: &ContractEvents<H>,
+    handler: F
+) -> Result<(), NanoServiceError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: Serialize + DeserializeOwned,
+    F: Fn(H) -> Fut,
+    Fut: Future<Output = H>,
+{
+    serve(stream, max_in_flight, Some(events), None, handler).await
+}
+
+
+/// Serves the contracts sent over a connection, sending events for the contracts handled if `events` is given and
+/// stopping early if `cancellation` is given and cancelled.
+///
+/// # Arguments
+/// * `stream` - The connection to the client.
+/// * `max_in_flight` - The most requests handled at once on the connection, at least one.
+/// * `events` - The stream the events of handled contracts are sent to, if any.
+/// * `cancellation` - How the connection is cancelled, if it can be.
+/// * `handler` - Called with each contract received and returns the contract to send back.
+///
+/// # Returns
+/// * `Result<(), NanoServiceError>` - An error if a contract could not be read or a response could not be sent.
+async fn serve<S, H, F, Fut>(
+    stream: S,
+    max_in_flight: usize,
+    events: Option<&ContractEvents<H>>,
+    cancellation: Option<Cancellation<'_, H>>,
+    handler: F
+) -> Result<(), NanoServiceError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    H: Serialize + DeserializeOwned,
+    F: Fn(H) -> Fut,
+    Fut: Future<Output = H>,
+{
+    let handler = &handler;
+    // the number of requests read that have not had a response sent yet
+    let pending = &AtomicUsize::new(0);
+    let (mut sink, requests) = Framed::new(stream, BincodeCodec::<H>::new()).split();
+    let mut responses = requests.map(|request| {
+        pending.fetch_add(1, Ordering::SeqCst);
+        async move {
+            let contract = request?;
+            let description = events.and_then(|events| events.describe(&contract));
+            let started = Instant::now();
+            let response = handler(contract).await;
+            if let (Some(events), Some(description)) = (events, description) {
+                events.emit(description, &response, started.elapsed());
+            }
+            Ok::<H, std::io::Error>(response)
+        }
+    }).buffered(max_in_flight.max(1));
+
+    loop {
+        let response = match &cancellation {
+            Some(cancellation) => tokio::select! {
+                response = responses.next() => response,
+                _ = cancellation.token.cancelled() => break,
+            },
+            None => responses.next().await
+        };
+        let response = match response {
+            Some(response) => response.map_err(|e| {
+                NanoServiceError::from_io_error(e, NanoServiceErrorStatus::BadRequest)
+            })?,
+            None => return Ok(())
+        };
+        sink.send(response).await.map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+        })?;
+        pending.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    // the connection was cancelled so the running handlers are dropped and their requests are answered with errors
+    drop(responses);
+    if let Some(cancellation) = cancellation {
+        for _ in 0..pending.load(Ordering::SeqCst) {
+            let error = NanoServiceError::new(
+                "The server is shutting down".to_string(),
+                NanoServiceErrorStatus::ServiceUnavailable
+            );
+            if sink.feed((cancellation.error_contract)(error)).await.is_err() {
+                return Ok(())
+            }
+        }
+        let _ = sink.flush().await;
+    }
+    Ok(())
+}
+
+
+/// Creates, binds and listens on a TCP socket.
+///
+/// # Arguments
+/// * `address` - The address to bind to.
+/// * `backlog` - The maximum number of pending connections waiting to be accepted.
+/// * `reuseport` - Whether to set `SO_REUSEPORT` (only honoured on Linux).
+///
+/// # Returns
+/// * `Result<TcpListener, NanoServiceError>` - The listener bound to the address.
+fn bind_socket(address: &str, backlog: u32, reuseport: bool) -> Result<TcpListener, NanoServiceError> {
+    let address: SocketAddr = address.parse().map_err(|e: std::net::AddrParseError| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+    })?;
+    let socket = match address {
+        SocketAddr::V4(_) => TcpSocket::new_v4(),
+        SocketAddr::V6(_) => TcpSocket::new_v6(),
+    }.map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    })?;
+    socket.set_reuseaddr(true).map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    })?;
+    #[cfg(target_os = "linux")]
+    if reuseport {
+        socket.set_reuseport(true).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+        })?;
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = reuseport;
+    socket.bind(address).map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    })?;
+    socket.listen(backlog).map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::runtime::Builder;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    #[cfg(target_os = "linux")]
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_rebind_after_drop() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8104";
+            let listener = bind_listener(address, DEFAULT_BACKLOG).unwrap();
+
+            // close a connection from the server side so the address has a connection in TIME_WAIT
+            let mut client = TcpStream::connect(address).await.unwrap();
+            let (server_side, _) = listener.accept().await.unwrap();
+            drop(server_side);
+            client.shutdown().await.unwrap();
+            drop(listener);
+
+            let listener = bind_listener(address, DEFAULT_BACKLOG).unwrap();
+            assert_eq!(listener.local_addr().unwrap().to_string(), address);
+
+            assert_eq!(bind_listener("not an address", DEFAULT_BACKLOG).unwrap_err().status, NanoServiceErrorStatus::BadRequest);
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_per_core() {
+        let address = "127.0.0.1:8105";
+        let cancel = CancellationToken::new();
+        let handles = run_per_core(address, 2, DEFAULT_BACKLOG, &cancel, |mut stream: TcpStream, _| async move {
+            // tell the client which worker accepted the connection
+            let name = std::thread::current().name().unwrap_or_default().to_string();
+            stream.write_all(name.as_bytes()).await.unwrap();
+        }).unwrap();
+        assert_eq!(handles.len(), 2);
+        assert!(run_per_core(address, 0, DEFAULT_BACKLOG, &cancel, |_: TcpStream, _| async {}).is_err());
+        // the address is taken by listeners that do not share it, so the workers fail to bind
+        let taken = std::net::TcpListener::bind("127.0.0.1:8136").unwrap();
+        assert!(run_per_core("127.0.0.1:8136", 2, DEFAULT_BACKLOG, &cancel, |_: TcpStream, _| async {}).is_err());
+        drop(taken);
+        // a server failing to start does not cancel the token it was given
+        assert!(!cancel.is_cancelled());
+
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let mut clients = Vec::new();
+            for _ in 0..32 {
+                clients.push(tokio::spawn(async move {
+                    let mut stream = TcpStream::connect(address).await.unwrap();
+                    let mut name = String::new();
+                    stream.read_to_string(&mut name).await.unwrap();
+                    name
+                }));
+            }
+            let mut workers = HashSet::new();
+            for client in clients {
+                workers.insert(client.await.unwrap());
+            }
+            // the kernel hashes each connection to one of the listeners so both workers accept connections
+            assert_eq!(workers.len(), 2);
+            assert!(workers.contains("nanoservice-worker-0"));
+        });
+
+        // cancelling the token stops the workers and releases the address
+        cancel.cancel();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        drop(std::net::TcpListener::bind(address).unwrap());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_worker_panic_is_reported() {
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Result<(), NanoServiceError>>();
+        let ready = WorkerReady { sender: Some(ready_tx) };
+        let worker = std::thread::spawn(move || {
+            let _ready = ready;
+            panic!("worker failed before binding");
+        });
+        assert!(worker.join().is_err());
+        assert_eq!(ready_rx.recv().unwrap().unwrap_err().status, NanoServiceErrorStatus::Unknown);
+    }
+
+    #[test]
+    fn test_serve_connection_caps_in_flight() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8112";
+            let listener = bind_listener(address, DEFAULT_BACKLOG).unwrap();
+            let in_flight = Arc::new(AtomicUsize::new(0));
+            let max_seen = Arc::new(AtomicUsize::new(0));
+
+            let server_in_flight = in_flight.clone();
+            let server_max_seen = max_seen.clone();
+            let _server = tokio::spawn(async move {
+                let (socket, _) = listener.accept().await.unwrap();
+                serve_connection(socket, 4, |request: u32| {
+                    let in_flight = server_in_flight.clone();
+                    let max_seen = server_max_seen.clone();
+                    async move {
+                        let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_seen.fetch_max(current, Ordering::SeqCst);
+                        tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        request * 2
+                    }
+                }).await.unwrap();
+            });
+
+            // send every request before reading any responses
+            let stream = TcpStream::connect(address).await.unwrap();
+            let mut framed = Framed::new(stream, BincodeCodec::<u32>::new());
+            for request in 0..20 {
+                framed.feed(request).await.unwrap();
+            }
+            framed.flush().await.unwrap();
+
+            for request in 0..20 {
+                assert_eq!(framed.next().await.unwrap().unwrap(), request * 2);
+            }
+            assert_eq!(max_seen.load(Ordering::SeqCst), 4);
+        });
+    }
+
+    #[test]
+    fn test_serve_connection_with_events() {
+        use crate::create_contract_handler;
+        use crate::networking::tcp::events::ContractOutcome;
+        use serde::Deserialize;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct Ping {
+            pub count: u32,
+        }
+
+        create_contract_handler!(
+            ContractHandler,
+            Ping
+        );
+
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+
+        runtime.block_on(async {
+            let events = ContractEvents::new(16, ContractHandler::to_string_ref);
+            let mut subscriber = events.subscribe();
+            let (client, server) = tokio::io::duplex(1024);
+
+            let server_events = events.clone();
+            let _server = tokio::spawn(async move {
+                serve_connection_with_events(server, 1, &server_events, |contract: ContractHandler| async move {
+                    match contract.Ping() {
+                        Ok(ping) if ping.count > 0 => ContractHandler::Ping(Ping { count: ping.count + 1 }),
+                        _ => ContractHandler::NanoServiceError(NanoServiceError::new(
+                            "Ping count has to be positive".to_string(),
+                            NanoServiceErrorStatus::BadRequest
+                        )),
+                    }
+                }).await.unwrap();
+            });
+
+            let mut framed = Framed::new(client, BincodeCodec::<ContractHandler>::new());
+            framed.send(ContractHandler::Ping(Ping { count: 1 })).await.unwrap();
+            assert_eq!(framed.next().await.unwrap().unwrap(), ContractHandler::Ping(Ping { count: 2 }));
+            framed.send(ContractHandler::Ping(Ping { count: 0 })).await.unwrap();
+            assert!(framed.next().await.unwrap().unwrap().NanoServiceError().is_ok());
+
+            let event = subscriber.recv().await.unwrap();
+            assert_eq!(event.variant, "ping_contract");
+            assert_eq!(event.size, 8);
+            assert_eq!(event.outcome, ContractOutcome::Success);
+            assert_eq!(subscriber.recv().await.unwrap().outcome, ContractOutcome::Error);
+        });
+    }
+
+    #[test]
+    fn test_cancelled_connection_sends_errors() {
+        use crate::create_contract_handler;
+        use serde::Deserialize;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct Slow {
+            pub id: u32,
+        }
+
+        create_contract_handler!(
+            ContractHandler,
+            Slow
+        );
+
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let cancel = CancellationToken::new();
+            let started = Arc::new(AtomicUsize::new(0));
+            let (client, server) = tokio::io::duplex(1024);
+
+            let server_cancel = cancel.clone();
+            let server_started = started.clone();
+            let server = tokio::spawn(async move {
+                serve_connection_until_cancelled(
+                    server,
+                    4,
+                    &server_cancel,
+                    ContractHandler::NanoServiceError,
+                    |contract: ContractHandler| {
+                        let started = server_started.clone();
+                        async move {
+                            started.fetch_add(1, Ordering::SeqCst);
+                            // the handler never finishes so the connection is cancelled mid-handler
+                            std::future::pending::<()>().await;
+                            contract
+                        }
+                    }
+                ).await
+            });
+
+            let mut framed = Framed::new(client, BincodeCodec::<ContractHandler>::new());
+            framed.send(ContractHandler::Slow(Slow { id: 1 })).await.unwrap();
+            framed.send(ContractHandler::Slow(Slow { id: 2 })).await.unwrap();
+            while started.load(Ordering::SeqCst) < 2 {
+                tokio::time::sleep(tokio::time::Duration::from_millis(5)).await;
+            }
+            cancel.cancel();
+
+            let timeout = tokio::time::Duration::from_secs(1);
+            for _ in 0..2 {
+                let response = tokio::time::timeout(timeout, framed.next()).await.unwrap().unwrap().unwrap();
+                let error = response.NanoServiceError().unwrap();
+                assert_eq!(error.status, NanoServiceErrorStatus::ServiceUnavailable);
+            }
+            // the connection is closed once the errors are sent
+            assert!(tokio::time::timeout(timeout, framed.next()).await.unwrap().is_none());
+            assert!(server.await.unwrap().is_ok());
+        });
+    }
+
+
+    #[test]
+    fn test_run_server_signals_ready() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let (ready_tx, ready_rx) = oneshot::channel();
+            let _server = tokio::spawn(run_server("127.0.0.1:0", DEFAULT_BACKLOG, ready_tx, |mut stream: TcpStream, _| async move {
+                stream.write_all(b"ready").await.unwrap();
+            }));
+            let address = ready_rx.await.unwrap();
+            assert_ne!(address.port(), 0);
+
+            // the listener is bound before the signal is sent so the first connection attempt succeeds
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            let mut greeting = String::new();
+            stream.read_to_string(&mut greeting).await.unwrap();
+            assert_eq!(greeting, "ready");
+
+            let (ready_tx, ready_rx) = oneshot::channel();
+            let error = run_server(&address.to_string(), DEFAULT_BACKLOG, ready_tx, |_: TcpStream, _| async {}).await;
+            assert!(error.is_err());
+            assert!(ready_rx.await.is_err());
+        });
+    }
+
+}
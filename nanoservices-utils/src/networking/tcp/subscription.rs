@@ -0,0 +1,346 @@
+//! Defines a client for subscribing to a stream of contracts that a server pushes over TCP (e.g. live updates). The
+//! client connects, sends a subscribe contract, and then yields every contract the server pushes. If the connection
+//! drops, the client reconnects with an exponential backoff and sends the subscribe contract again, so the stream
+//! carries on without the caller having to manage the connection:
+//!
+//! ```ignore
+//! let client = SubscriptionClient::new("127.0.0.1:8080", || {
+//!     ContractHandler::Subscribe(Subscribe { topic: "prices".to_string() })
+//! });
+//! let mut updates = Box::pin(client.subscribe());
+//! while let Some(update) = updates.next().await {
+//!     match update {
+//!         Ok(contract) => println!("{:?}", contract),
+//!         // the connection dropped and was re-established, so contracts may have been missed
+//!         Err(e) => eprintln!("{}", e.message),
+//!     }
+//! }
+//! ```
+use std::time::Duration;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::net::TcpStream;
+use tokio_util::codec::Framed;
+use futures::{sink::SinkExt, Stream, StreamExt};
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use crate::networking::serialization::codec::BincodeCodec;
+
+
+/// The default time waited before the first reconnect attempt.
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The default longest time waited between reconnect attempts.
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+
+/// A client that keeps a subscription to the contracts pushed by a server alive across reconnects.
+///
+/// # Fields
+/// * `address` - The address of the server.
+/// * `subscribe_contract` - Builds the contract sent to the server to subscribe, called for every connection.
+/// * `initial_backoff` - The time waited before the first reconnect attempt, doubled after every failed attempt.
+/// * `max_backoff` - The longest time waited between reconnect attempts.
+pub struct SubscriptionClient<H> {
+    address: String,
+    subscribe_contract: Box<dyn Fn() -> H + Send + Sync>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl<H> SubscriptionClient<H>
+where
+    H: Serialize + DeserializeOwned + Send + 'static,
+{
+
+    /// Constructs a new `SubscriptionClient`. Nothing is connected until the stream from `subscribe` is polled.
+    ///
+    /// # Arguments
+    /// * `address` - The address of the server.
+    /// * `subscribe_contract` - Builds the contract sent to the server to subscribe.
+    ///
+    /// # Returns
+    /// * `SubscriptionClient<H>` - The new client.
+    pub fn new<F>(address: &str, subscribe_contract: F) -> Self
+    where
+        F: Fn() -> H + Send + Sync + 'static,
+    {
+        SubscriptionClient {
+            address: address.to_string(),
+            subscribe_contract: Box::new(subscribe_contract),
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+
+    /// Sets how long the client waits between reconnect attempts.
+    ///
+    /// # Arguments
+    /// * `initial_backoff` - The time waited before the first reconnect attempt.
+    /// * `max_backoff` - The longest time waited between reconnect attempts.
+    ///
+    /// # Returns
+    /// * `SubscriptionClient<H>` - The client with the new backoff.
+    pub fn with_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff.max(initial_backoff);
+        self
+    }
+
+    /// Subscribes to the contracts pushed by the server.
+    ///
+    /// # Notes
+    /// The stream never ends on its own, it keeps reconnecting until it is dropped. After a reconnect an error with
+    /// the `ServiceUnavailable` status is yielded before the next contract to signal that contracts pushed while the
+    /// client was disconnected were missed.
+    ///
+    /// # Returns
+    /// * `impl Stream<Item = Result<H, NanoServiceError>>` - The contracts pushed by the server.
+    pub fn subscribe(self) -> impl Stream<Item = Result<H, NanoServiceError>> {
+        let state = SubscriptionState {
+            backoff: self.initial_backoff,
+            client: self,
+            framed: None,
+            connected_before: false,
+        };
+        futures::stream::unfold(state, |mut state| async move {
+            let item = state.next_item().await;
+            Some((item, state))
+        })
+    }
+
+    /// Connects to the server and sends the subscribe contract.
+    ///
+    /// # Returns
+    /// * `Result<Framed<TcpStream, BincodeCodec<H>>, NanoServiceError>` - The subscribed connection.
+    async fn connect(&self) -> Result<Framed<TcpStream, BincodeCodec<H>>, NanoServiceError> {
+        let stream = TcpStream::connect(&self.address).await?;
+        let mut framed = Framed::new(stream, BincodeCodec::<H>::new());
+        framed.send((self.subscribe_contract)()).await?;
+        Ok(framed)
+    }
+
+    /// Connects to the server, waiting with an exponential backoff between failed attempts until one succeeds.
+    ///
+    /// # Arguments
+    /// * `backoff` - The time to wait before the next attempt, doubled after every wait.
+    ///
+    /// # Returns
+    /// * `Framed<TcpStream, BincodeCodec<H>>` - The subscribed connection.
+    async fn connect_with_backoff(&self, backoff: &mut Duration) -> Framed<TcpStream, BincodeCodec<H>> {
+        loop {
+            match self.connect().await {
+                Ok(framed) => return framed,
+                Err(e) => eprintln!("Failed to subscribe to {}: {}", self.address, e.message)
+            }
+            self.back_off(backoff).await;
+        }
+    }
+
+    /// Waits for the backoff and then doubles it, up to the longest backoff.
+    ///
+    /// # Arguments
+    /// * `backoff` - The time to wait.
+    async fn back_off(&self, backoff: &mut Duration) {
+        tokio::time::sleep(*backoff).await;
+        *backoff = (*backoff * 2).min(self.max_backoff);
+    }
+}
+
+
+/// The state carried between the items of a subscription stream.
+///
+/// # Fields
+/// * `client` - The client the subscription belongs to.
+/// * `framed` - The subscribed connection, `None` if it has dropped.
+/// * `connected_before` - Whether a connection has been made before, so the next connection is a reconnect.
+/// * `backoff` - The time waited before the next reconnect attempt, only reset once a contract is received.
+struct SubscriptionState<H> {
+    client: SubscriptionClient<H>,
+    framed: Option<Framed<TcpStream, BincodeCodec<H>>>,
+    connected_before: bool,
+    backoff: Duration,
+}
+
+impl<H> SubscriptionState<H>
+where
+    H: Serialize + DeserializeOwned + Send + 'static,
+{
+
+    /// Waits for the next contract pushed by the server, reconnecting if the connection has dropped.
+    ///
+    /// # Returns
+    /// * `Result<H, NanoServiceError>` - The next contract, or an error signalling a gap after a reconnect.
+    async fn next_item(&mut self) -> Result<H, NanoServiceError> {
+        loop {
+            let framed = match self.framed.as_mut() {
+                Some(framed) => framed,
+                None => {
+                    // a reconnect waits as well, so a server that accepts and then closes straight away is retried
+                    // with the backoff rather than in a tight loop
+                    if self.connected_before {
+                        self.client.back_off(&mut self.backoff).await;
+                    }
+                    self.framed = Some(self.client.connect_with_backoff(&mut self.backoff).await);
+                    if self.connected_before {
+                        return Err(NanoServiceError::new(
+                            format!(
+                                "Subscription to {} was reconnected, contracts pushed while disconnected were missed",
+                                self.client.address
+                            ),
+                            NanoServiceErrorStatus::ServiceUnavailable
+                        ))
+                    }
+                    self.connected_before = true;
+                    continue
+                }
+            };
+            match framed.next().await {
+                Some(Ok(contract)) => {
+                    // the subscription is only healthy once the server pushes something
+                    self.backoff = self.client.initial_backoff;
+                    return Ok(contract)
+                },
+                // a frame that cannot be decoded leaves the connection out of step, so it is dropped as well
+                Some(Err(e)) => eprintln!("Subscription to {} dropped: {}", self.client.address, e),
+                None => {}
+            }
+            self.framed = None;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::create_contract_handler;
+    use serde::Deserialize;
+    use tokio::net::TcpListener;
+    use tokio::runtime::Builder;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Subscribe;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Update {
+        pub value: u32,
+    }
+
+    create_contract_handler!(
+        ContractHandler,
+        Subscribe,
+        Update
+    );
+
+    /// Pushes a batch of updates to each subscriber, dropping every connection but the last after its batch.
+    async fn run_push_server(addr: &str, batches: Vec<Vec<u32>>) {
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let batch_count = batches.len();
+        for (index, batch) in batches.into_iter().enumerate() {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
+            let subscribe = framed.next().await.unwrap().unwrap();
+            assert_eq!(subscribe, ContractHandler::Subscribe(Subscribe));
+            for value in batch {
+                framed.send(ContractHandler::Update(Update { value })).await.unwrap();
+            }
+            if index + 1 == batch_count {
+                // the last connection is kept open so the subscriber is not disconnected again
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+
+    #[test]
+    fn test_resubscribes_after_disconnect() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let addr = "127.0.0.1:8117";
+            let _server = tokio::spawn(run_push_server(addr, vec![vec![1, 2, 3], vec![4, 5]]));
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            let client = SubscriptionClient::new(addr, || ContractHandler::Subscribe(Subscribe))
+                .with_backoff(Duration::from_millis(10), Duration::from_millis(50));
+            let updates = client.subscribe();
+            futures::pin_mut!(updates);
+
+            for value in 1..4 {
+                assert_eq!(updates.next().await.unwrap().unwrap(), ContractHandler::Update(Update { value }));
+            }
+            let gap = updates.next().await.unwrap().unwrap_err();
+            assert_eq!(gap.status, NanoServiceErrorStatus::ServiceUnavailable);
+            for value in 4..6 {
+                assert_eq!(updates.next().await.unwrap().unwrap(), ContractHandler::Update(Update { value }));
+            }
+        });
+    }
+
+    #[test]
+    fn test_retries_until_server_is_up() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let addr = "127.0.0.1:8118";
+            let client = SubscriptionClient::new(addr, || ContractHandler::Subscribe(Subscribe))
+                .with_backoff(Duration::from_millis(10), Duration::from_millis(50));
+            let updates = client.subscribe();
+            futures::pin_mut!(updates);
+
+            // the server only starts listening after the client has started trying to connect
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                run_push_server(addr, vec![vec![7]]).await;
+            });
+            // the first connection is not a reconnect so there is no gap
+            assert_eq!(updates.next().await.unwrap().unwrap(), ContractHandler::Update(Update { value: 7 }));
+        });
+    }
+
+    #[test]
+    fn test_backs_off_when_server_closes_after_accept() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            use std::sync::Arc;
+
+            // a server that accepts every connection and closes it straight away
+            let addr = "127.0.0.1:8134";
+            let accepted = Arc::new(AtomicUsize::new(0));
+            let listener = TcpListener::bind(addr).await.unwrap();
+            let server_accepted = accepted.clone();
+            tokio::spawn(async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    server_accepted.fetch_add(1, Ordering::SeqCst);
+                    drop(socket);
+                }
+            });
+
+            let client = SubscriptionClient::new(addr, || ContractHandler::Subscribe(Subscribe))
+                .with_backoff(Duration::from_millis(20), Duration::from_secs(1));
+            let updates = client.subscribe();
+            futures::pin_mut!(updates);
+
+            let mut gaps = 0;
+            let _ = tokio::time::timeout(Duration::from_millis(400), async {
+                while let Some(item) = updates.next().await {
+                    assert_eq!(item.unwrap_err().status, NanoServiceErrorStatus::ServiceUnavailable);
+                    gaps += 1;
+                }
+            }).await;
+            // waits of 20, 40, 80, and 160ms fit in the window rather than hundreds of reconnects
+            assert!(accepted.load(Ordering::SeqCst) <= 6, "{} connections", accepted.load(Ordering::SeqCst));
+            assert!(gaps <= 5, "{} gaps", gaps);
+        });
+    }
+}
@@ -0,0 +1,265 @@
+//! Defines sending and serving data contracts over TLS for services that talk across an untrusted network. The
+//! contracts are framed with the same `BincodeCodec` as plain TCP, only the transport under the framing is swapped
+//! from a `TcpStream` for a TLS stream. The `rustls` crate is re-exported so the client and server configs are built
+//! with the version the transport uses.
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::net::TcpStream;
+use tokio_rustls::{client, server, TlsAcceptor, TlsConnector};
+use tokio_rustls::rustls::{pki_types::ServerName, ClientConfig};
+use tokio_util::codec::Framed;
+use futures::{sink::SinkExt, StreamExt};
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use crate::networking::serialization::codec::BincodeCodec;
+
+pub use tokio_rustls::rustls;
+
+
+/// The default longest time a server waits for a client to complete the TLS handshake.
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// Sends a data contract over TLS to the specified address.
+///
+/// # Arguments
+/// * `contract` - The contract to send.
+/// * `address` - The address to send the contract to.
+/// * `server_name` - The name the certificate of the server is verified against (e.g. `auth.internal`).
+/// * `config` - The TLS config of the client holding the trusted root certificates.
+///
+/// # Returns
+/// * `Result<T, NanoServiceError>` - The response from the server which is either the contract or an Error.
+pub async fn send_data_contract_over_tls<T>(
+    contract: T,
+    address: &str,
+    server_name: &str,
+    config: Arc<ClientConfig>
+) -> Result<T, NanoServiceError>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let mut framed = connect_tls::<T>(address, server_name, config).await?;
+    framed.send(contract).await?;
+    let response = match framed.next().await {
+        Some(response) => response,
+        None => return Err(NanoServiceError::new("No response from server.".to_string(), NanoServiceErrorStatus::BadRequest))
+    };
+    Ok(response?)
+}
+
+
+/// Connects to a server over TLS and frames the connection with the `BincodeCodec`.
+///
+/// # Arguments
+/// * `address` - The address of the server.
+/// * `server_name` - The name the certificate of the server is verified against.
+/// * `config` - The TLS config of the client.
+///
+/// # Returns
+/// * `Result<Framed<client::TlsStream<TcpStream>, BincodeCodec<T>>, NanoServiceError>` - The framed connection.
+pub async fn connect_tls<T>(
+    address: &str,
+    server_name: &str,
+    config: Arc<ClientConfig>
+) -> Result<Framed<client::TlsStream<TcpStream>, BincodeCodec<T>>, NanoServiceError> {
+    let server_name = ServerName::try_from(server_name.to_string()).map_err(|e| {
+        NanoServiceError::new(format!("Invalid server name {}: {}", server_name, e), NanoServiceErrorStatus::BadRequest)
+    })?;
+    let stream = TcpStream::connect(address).await?;
+    let stream = TlsConnector::from(config).connect(server_name, stream).await?;
+    Ok(Framed::new(stream, BincodeCodec::<T>::new()))
+}
+
+
+/// Completes the TLS handshake of a connection accepted by a server and frames the connection with the
+/// `BincodeCodec`. The framed connection can be served like a plain TCP one, and as the TLS stream is
+/// `AsyncRead + AsyncWrite` it can also be passed to `serve_connection` after `into_inner`.
+///
+/// # Notes
+/// Accept connections with `TcpListener::accept` and spawn the handshake of each one rather than awaiting it in the
+/// accept loop, otherwise a client that connects and never sends its hello stops the server accepting anyone else.
+/// The timeout bounds how long the spawned task holds on to such a client. An error is returned if the handshake
+/// fails (e.g. a client sending plain TCP) or times out, so it should be logged and the connection dropped.
+///
+/// # Arguments
+/// * `stream` - The connection accepted by the server.
+/// * `acceptor` - The acceptor holding the TLS config of the server.
+/// * `timeout` - The longest time to wait for the handshake to complete (e.g. `DEFAULT_HANDSHAKE_TIMEOUT`).
+///
+/// # Returns
+/// * `Result<Framed<server::TlsStream<TcpStream>, BincodeCodec<T>>, NanoServiceError>` - The framed connection, or a
+///   `ServiceUnavailable` error if the handshake timed out.
+pub async fn tls_handshake<T>(
+    stream: TcpStream,
+    acceptor: TlsAcceptor,
+    timeout: Duration
+) -> Result<Framed<server::TlsStream<TcpStream>, BincodeCodec<T>>, NanoServiceError> {
+    let stream = match tokio::time::timeout(timeout, acceptor.accept(stream)).await {
+        Ok(stream) => stream?,
+        Err(_) => return Err(NanoServiceError::new(
+            format!("TLS handshake not completed within {:?}.", timeout),
+            NanoServiceErrorStatus::ServiceUnavailable
+        ))
+    };
+    Ok(Framed::new(stream, BincodeCodec::<T>::new()))
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::create_contract_handler;
+    use serde::Deserialize;
+    use tokio::net::TcpListener;
+    use tokio::runtime::Builder;
+    use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+    use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Ping {
+        pub count: u32,
+    }
+
+    create_contract_handler!(
+        ContractHandler,
+        Ping
+    );
+
+    /// Builds a server config with a self-signed certificate for `localhost`, and a client config trusting it.
+    fn tls_configs() -> (Arc<ServerConfig>, Arc<ClientConfig>) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert = CertificateDer::from(certified.cert.der().to_vec());
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+
+        let server = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.clone()], key)
+            .unwrap();
+        let mut roots = RootCertStore::empty();
+        roots.add(cert).unwrap();
+        let client = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        (Arc::new(server), Arc::new(client))
+    }
+
+    /// Answers every ping sent over each connection with the count incremented.
+    async fn tls_server(listener: TcpListener, config: Arc<ServerConfig>, handshake_timeout: Duration) {
+        let acceptor = TlsAcceptor::from(config);
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let mut framed = match tls_handshake::<ContractHandler>(stream, acceptor, handshake_timeout).await {
+                    Ok(framed) => framed,
+                    Err(e) => {
+                        eprintln!("TLS handshake failed: {}", e.message);
+                        return
+                    }
+                };
+                while let Some(Ok(ContractHandler::Ping(ping))) = framed.next().await {
+                    framed.send(ContractHandler::Ping(Ping { count: ping.count + 1 })).await.unwrap();
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn test_send_data_contract_over_tls() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let address = "127.0.0.1:8130";
+            let (server_config, client_config) = tls_configs();
+            let listener = TcpListener::bind(address).await.unwrap();
+            let _server = tokio::spawn(tls_server(listener, server_config, DEFAULT_HANDSHAKE_TIMEOUT));
+
+            let response = send_data_contract_over_tls(
+                ContractHandler::Ping(Ping { count: 1 }),
+                address,
+                "localhost",
+                client_config.clone()
+            ).await.unwrap();
+            assert_eq!(response, ContractHandler::Ping(Ping { count: 2 }));
+
+            // the framing is unchanged so several contracts can be sent over one TLS connection
+            let mut framed = connect_tls::<ContractHandler>(address, "localhost", client_config).await.unwrap();
+            for count in 0..3 {
+                framed.send(ContractHandler::Ping(Ping { count })).await.unwrap();
+                let response = framed.next().await.unwrap().unwrap();
+                assert_eq!(response, ContractHandler::Ping(Ping { count: count + 1 }));
+            }
+        });
+    }
+
+    #[test]
+    fn test_untrusted_server_is_rejected() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let address = "127.0.0.1:8131";
+            let (server_config, _) = tls_configs();
+            // a client trusting a different certificate
+            let (_, client_config) = tls_configs();
+            let listener = TcpListener::bind(address).await.unwrap();
+            let _server = tokio::spawn(tls_server(listener, server_config, DEFAULT_HANDSHAKE_TIMEOUT));
+
+            let result = send_data_contract_over_tls(
+                ContractHandler::Ping(Ping { count: 1 }),
+                address,
+                "localhost",
+                client_config
+            ).await;
+            assert!(result.is_err());
+
+            let error = match connect_tls::<ContractHandler>(address, "not a server name", tls_configs().1).await {
+                Ok(_) => panic!("an invalid server name should not connect"),
+                Err(e) => e
+            };
+            assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+        });
+    }
+
+    #[test]
+    fn test_silent_client_does_not_block_handshakes() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let address = "127.0.0.1:8132";
+            let (server_config, client_config) = tls_configs();
+            let listener = TcpListener::bind(address).await.unwrap();
+            let _server = tokio::spawn(tls_server(listener, server_config.clone(), DEFAULT_HANDSHAKE_TIMEOUT));
+
+            // a client that connects and never sends its hello
+            let _silent = TcpStream::connect(address).await.unwrap();
+            let response = send_data_contract_over_tls(
+                ContractHandler::Ping(Ping { count: 1 }),
+                address,
+                "localhost",
+                client_config
+            ).await.unwrap();
+            assert_eq!(response, ContractHandler::Ping(Ping { count: 2 }));
+
+            // the handshake of the silent client gives up after the timeout
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let _silent = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+            let (stream, _) = listener.accept().await.unwrap();
+            let acceptor = TlsAcceptor::from(server_config);
+            let error = match tls_handshake::<ContractHandler>(stream, acceptor, Duration::from_millis(50)).await {
+                Ok(_) => panic!("a client that sends nothing should not complete the handshake"),
+                Err(e) => e
+            };
+            assert_eq!(error.status, NanoServiceErrorStatus::ServiceUnavailable);
+        });
+    }
+}
@@ -1,153 +1,465 @@
-//! This code is currently parked for now to enable a release for the layered lib.
+//! Defines a proxy that accepts contracts over TCP and passes them to a WASM server running as a child process
+//! under a WASM runtime (`wasmtime` by default). Messages to and from the child are framed the same way guests
+//! read them with `read_contract_frame`, a 4 byte big endian length followed by exactly that many bytes, and the
+//! bytes of a frame are a 4 byte message type followed by the serialized contract. The child and the listener run
+//! until `shutdown` is called so the proxy can be torn down deterministically in tests and on redeploys.
+//!
+//! Connections are served concurrently but the WASM server handles one message at a time, so the pipes of the
+//! child are locked for the whole of a request and only one request is ever in flight. The request is written
+//! while its response is read so a child that starts answering before it has read the whole request can not
+//! deadlock the proxy with both pipe buffers full.
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::io::{BufRead, BufReader, Write};
-use std::process::{Command, Stdio};
-use std::future::Future;
-use std::pin::Pin;
-use std::fmt::Debug;
-use tokio::net::TcpListener;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+use tokio::task::{JoinHandle, JoinSet};
 use tokio_util::codec::Framed;
-use tokio_util::codec::{Decoder, Encoder};
-use tokio::net::TcpStream;
 use futures::{sink::SinkExt, StreamExt};
 
 use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
 use crate::networking::serialization::codec::BincodeCodec;
+use crate::networking::serialization::DEFAULT_MAX_MESSAGE_SIZE;
+use crate::networking::utils::check_payload_size;
 
 
-// pub struct TcpToWasmProxy<T: DeserializeOwned + Debug + Serialize> {
+/// The runtime that runs the WASM server when one is not configured.
+pub const DEFAULT_RUNTIME: &str = "wasmtime";
+
+/// The message type of a contract, will add things like type 2 for data storage later.
+const CONTRACT_MESSAGE_TYPE: u32 = 1;
+
+/// The number of bytes used to record the message type at the start of a frame.
+const MESSAGE_TYPE_BYTES: usize = 4;
+
+/// The stdin and stdout of the WASM server, locked for the whole of a request so one request is in flight at a time.
+type WasmPipes = Arc<Mutex<(ChildStdin, BufReader<ChildStdout>)>>;
+
+
+/// Proxies contracts received over TCP to a WASM server running as a child process.
+///
+/// # Fields
+/// * `address` - The address the proxy listens on.
+/// * `wasm_path` - The path to the WASM server.
+/// * `runtime` - The command that runs the WASM server.
+/// * `child` - The WASM server process, `None` if the proxy has not been started.
+/// * `listener` - The task accepting connections, `None` if the proxy has not been started.
 pub struct TcpToWasmProxy {
     pub address: String,
     pub wasm_path: String,
-    // pub handler: T,
-    // pub handle_func: fn(T) -> Pin<Box<dyn Future<Output = Result<T, NanoServiceError>> + Send>>,
+    pub runtime: String,
+    child: Option<Child>,
+    listener: Option<JoinHandle<()>>,
 }
 
 
-// impl <T: DeserializeOwned + Debug + Serialize> TcpToWasmProxy<T> {
 impl TcpToWasmProxy {
-    pub fn new(address: String, wasm_path: String, 
-        // handler: T, 
-        //handle_func: fn(T) -> Pin<Box<dyn Future<Output = Result<T, NanoServiceError>> + Send>>
-    ) -> Self {
+
+    /// Constructs a new `TcpToWasmProxy` that runs the WASM server with `wasmtime`.
+    ///
+    /// # Arguments
+    /// * `address` - The address the proxy listens on.
+    /// * `wasm_path` - The path to the WASM server.
+    ///
+    /// # Returns
+    /// * `TcpToWasmProxy` - The proxy, which does nothing until `start` is called.
+    pub fn new(address: String, wasm_path: String) -> Self {
         TcpToWasmProxy {
             address,
             wasm_path,
-            // handler,
-            // handle_func
+            runtime: DEFAULT_RUNTIME.to_string(),
+            child: None,
+            listener: None,
         }
     }
 
-    pub async fn start<T: DeserializeOwned + Debug + Serialize>(&self) -> Result<(), NanoServiceError> {
-        // start the wasm server
-        let mut child = Command::new("wasmtime")
-        .arg(self.wasm_path.as_str())
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .expect("Failed to start WASM process");
-
-        let stdin = child.stdin.as_mut().expect("Failed to open stdin");
-        let stdout = child.stdout.as_mut().expect("Failed to open stdout");
-        let mut reader = BufReader::new(stdout);
-
-        // start the tcp server
-        let listener = TcpListener::bind(self.address.clone()).await.map_err(|e| {
-            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    /// Sets the command that runs the WASM server, the path to the WASM server is passed as its only argument.
+    ///
+    /// # Arguments
+    /// * `runtime` - The command that runs the WASM server.
+    ///
+    /// # Returns
+    /// * `TcpToWasmProxy` - The proxy with the runtime set.
+    pub fn with_runtime(mut self, runtime: String) -> Self {
+        self.runtime = runtime;
+        self
+    }
+
+    /// Gets the process ID of the WASM server.
+    ///
+    /// # Returns
+    /// * `Option<u32>` - The process ID, `None` if the proxy has not been started or the process has been reaped.
+    pub fn child_id(&self) -> Option<u32> {
+        self.child.as_ref().and_then(|child| child.id())
+    }
+
+    /// Starts the WASM server and the listener. This returns once the proxy is listening, and the proxy keeps
+    /// handling contracts in the background until `shutdown` is called.
+    ///
+    /// # Returns
+    /// * `Result<(), NanoServiceError>` - An error if the proxy is already started, or the listener or the WASM
+    ///   server could not be started.
+    pub async fn start<T>(&mut self) -> Result<(), NanoServiceError>
+    where
+        T: DeserializeOwned + Serialize + Send + 'static,
+    {
+        if self.child.is_some() {
+            return Err(NanoServiceError::new(
+                "The proxy has already been started".to_string(),
+                NanoServiceErrorStatus::BadRequest
+            ))
+        }
+        let listener = TcpListener::bind(self.address.as_str()).await?;
+
+        let mut child = Command::new(self.runtime.as_str())
+            .arg(self.wasm_path.as_str())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                NanoServiceError::new(format!("Failed to start WASM process: {}", e), NanoServiceErrorStatus::Unknown)
+            })?;
+        let stdin = child.stdin.take().ok_or_else(|| {
+            NanoServiceError::new("Failed to open stdin".to_string(), NanoServiceErrorStatus::Unknown)
+        })?;
+        let stdout = child.stdout.take().ok_or_else(|| {
+            NanoServiceError::new("Failed to open stdout".to_string(), NanoServiceErrorStatus::Unknown)
         })?;
 
-        while let Ok((socket, _)) = listener.accept().await {
-            let mut framed = Framed::new(socket, BincodeCodec::<T>::new());
-    
-            while let Some(result) = framed.next().await {
-                match result {
-                    Ok(data) => {
-                        println!("Received: {:?}", data);
-                        // message type 1 is the contract, will add things like type 2 for data storage later
-                        let message_type: u32 = 1;
-                        let message_type_bytes = message_type.to_be_bytes();
-
-                        // pack the message with the message type
-                        let buf = bincode::serialize(&data).unwrap();
-                        let mut encoded_message: Vec<u8> = Vec::with_capacity(message_type_bytes.len() + buf.len());
-                        encoded_message.extend_from_slice(&message_type_bytes);
-                        encoded_message.extend_from_slice(&buf);
-
-                        // Send the message to the child process
-                        stdin.write_all(&encoded_message).unwrap();
-                        stdin.write_all(b"\n").unwrap();
-                        stdin.flush().unwrap();
-
-                        // Read the response for each message
-                        let mut output = Vec::new();
-                        reader.read_until(b'\n', &mut output).unwrap();
-
-                        // process the response
-                        let (type_prefix, message_data) = output.split_at(4);
-                        let _request_type = u32::from_be_bytes([type_prefix[0], type_prefix[1], type_prefix[2], type_prefix[3]]);
-                        let response: T = bincode::deserialize(&message_data).unwrap();
-
-                        // return the response via TCP without any processing
-                        framed.send(response).await.unwrap();
-                        break;
-                    },
-                    Err(e) => {
-                        eprintln!("Error processing data: {}", e);
-                        break;
-                    }
+        self.listener = Some(tokio::spawn(serve_connections::<T>(listener, stdin, BufReader::new(stdout))));
+        self.child = Some(child);
+        Ok(())
+    }
+
+    /// Stops the listener and kills the WASM server, waiting for the process to exit so it is reaped.
+    ///
+    /// # Returns
+    /// * `Result<(), NanoServiceError>` - An error if the WASM server could not be killed.
+    pub async fn shutdown(mut self) -> Result<(), NanoServiceError> {
+        if let Some(listener) = self.listener.take() {
+            listener.abort();
+            // the task has stopped and dropped the listener once this resolves
+            let _ = listener.await;
+        }
+        if let Some(mut child) = self.child.take() {
+            child.kill().await?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Accepts connections and serves each one in its own task. The connection tasks are aborted with this task so
+/// stopping the listener also stops the connections.
+///
+/// # Arguments
+/// * `listener` - The listener accepting connections.
+/// * `stdin` - The stdin of the WASM server.
+/// * `stdout` - The stdout of the WASM server.
+async fn serve_connections<T>(listener: TcpListener, stdin: ChildStdin, stdout: BufReader<ChildStdout>)
+where
+    T: DeserializeOwned + Serialize + Send + 'static,
+{
+    let pipes: WasmPipes = Arc::new(Mutex::new((stdin, stdout)));
+    let mut connections = JoinSet::new();
+    while let Ok((socket, _)) = listener.accept().await {
+        // reap the connections that have closed so the set does not grow with every connection
+        while connections.try_join_next().is_some() {}
+        connections.spawn(serve_connection::<T>(socket, pipes.clone()));
+    }
+}
+
+
+/// Passes each contract received on a connection to the WASM server and sends back the response, until the
+/// connection is closed or a contract fails.
+///
+/// # Arguments
+/// * `socket` - The connection to serve.
+/// * `pipes` - The stdin and stdout of the WASM server.
+async fn serve_connection<T>(socket: TcpStream, pipes: WasmPipes)
+where
+    T: DeserializeOwned + Serialize,
+{
+    let mut framed = Framed::new(socket, BincodeCodec::<T>::new());
+    while let Some(result) = framed.next().await {
+        let contract = match result {
+            Ok(contract) => contract,
+            Err(e) => {
+                eprintln!("Error processing data: {}", e);
+                return
+            }
+        };
+        let response = {
+            let mut pipes = pipes.lock().await;
+            let (stdin, stdout) = &mut *pipes;
+            forward_contract(contract, stdin, stdout).await
+        };
+        match response {
+            Ok(response) => {
+                if let Err(e) = framed.send(response).await {
+                    eprintln!("Error sending response: {}", e);
+                    return
                 }
+            },
+            Err(e) => {
+                eprintln!("Error forwarding contract to the WASM process: {}", e);
+                return
             }
         }
+    }
+}
 
-        // loop {
-        //     // Asynchronously wait for an inbound socket.
-        //     let (mut socket, _) = listener.accept().await.map_err(|e| {
-        //         NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
-        //     })?;
 
-        //     let mut buf = vec![0; 1024];
+/// Sends a contract to the WASM server and reads its response.
+///
+/// # Arguments
+/// * `contract` - The contract to send.
+/// * `stdin` - The stdin of the WASM server.
+/// * `stdout` - The stdout of the WASM server.
+///
+/// # Returns
+/// * `Result<T, NanoServiceError>` - The response of the WASM server.
+async fn forward_contract<T>(contract: T, stdin: &mut ChildStdin, stdout: &mut BufReader<ChildStdout>)
+    -> Result<T, NanoServiceError>
+where
+    T: DeserializeOwned + Serialize,
+{
+    // pack the message with the message type
+    let contract_bytes = bincode::serialize(&contract).map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+    })?;
+    let mut message = Vec::with_capacity(MESSAGE_TYPE_BYTES + contract_bytes.len());
+    message.extend_from_slice(&CONTRACT_MESSAGE_TYPE.to_be_bytes());
+    message.extend_from_slice(&contract_bytes);
 
-        //     // In a loop, read data from the socket and write the data back.
-        //     loop {
-        //         let n = socket
-        //             .read(&mut buf)
-        //             .await
-        //             .expect("failed to read data from socket");
+    // the response is read while the message is written so neither side blocks on a full pipe
+    let (_, output) = tokio::try_join!(
+        write_contract_frame(stdin, &message),
+        read_contract_frame(stdout, DEFAULT_MAX_MESSAGE_SIZE)
+    )?;
+    let output = output.ok_or_else(|| {
+        NanoServiceError::new("The WASM process closed its stdout".to_string(), NanoServiceErrorStatus::Unknown)
+    })?;
+    if output.len() < MESSAGE_TYPE_BYTES {
+        return Err(NanoServiceError::new(
+            "The WASM process sent a frame without a message type".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ))
+    }
+    let (_message_type, message_data) = output.split_at(MESSAGE_TYPE_BYTES);
+    bincode::deserialize(message_data).map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+    })
+}
 
-        //         if n == 0 {
-        //             break
-        //         }
 
-        //     }
+/// Reads a frame written by a WASM server with `write_contract_frame`, a 4 byte big endian length followed by
+/// exactly that many bytes. This is the async version of `wasm::routing::read_contract_frame` for the host side
+/// of the pipe.
+///
+/// # Arguments
+/// * `reader` - The stream to read the frame from (e.g. the stdout of the WASM server).
+/// * `max_size` - The largest frame accepted, larger frames are rejected before their bytes are read.
+///
+/// # Returns
+/// * `Result<Option<Vec<u8>>, NanoServiceError>` - The bytes of the frame, `None` if the stream ended before a frame.
+pub async fn read_contract_frame<R>(reader: &mut R, max_size: usize) -> Result<Option<Vec<u8>>, NanoServiceError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut length_bytes = [0; 4];
+    match reader.read_exact(&mut length_bytes).await {
+        Ok(_) => {},
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown))
+    }
+    let length = u32::from_be_bytes(length_bytes) as usize;
+    check_payload_size(length, max_size)?;
+    let mut frame = vec![0; length];
+    reader.read_exact(&mut frame).await.map_err(|e| {
+        NanoServiceError::new(
+            format!("Frame ended after fewer than {} bytes: {}", length, e),
+            NanoServiceErrorStatus::BadRequest
+        )
+    })?;
+    Ok(Some(frame))
+}
+
 
-        //     // message type 1 is the contract, will add things like type 2 for data storage later
-        //     let message_type: u32 = 1;
-        //     let message_type_bytes = message_type.to_be_bytes();
+/// Writes bytes framed as a 4 byte big endian length followed by the bytes, and flushes the stream so the frame
+/// is not left sitting in a buffer. This is the async version of `wasm::routing::write_contract_frame` for the
+/// host side of the pipe.
+///
+/// # Arguments
+/// * `writer` - The stream to write the frame to (e.g. the stdin of the WASM server).
+/// * `bytes` - The bytes of the frame.
+///
+/// # Returns
+/// * `Result<(), NanoServiceError>` - An error if the bytes are too large to frame or could not be written.
+pub async fn write_contract_frame<W>(writer: &mut W, bytes: &[u8]) -> Result<(), NanoServiceError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let length = u32::try_from(bytes.len()).map_err(|_| {
+        NanoServiceError::new(
+            "Contract is too large for a 4 byte length".to_string(),
+            NanoServiceErrorStatus::PayloadTooLarge
+        )
+    })?;
+    writer.write_all(&length.to_be_bytes()).await?;
+    writer.write_all(bytes).await?;
+    writer.flush().await?;
+    Ok(())
+}
 
-        //     // pack with message type
-        //     let mut encoded_message: Vec<u8> = Vec::with_capacity(message_type_bytes.len() + buf.len());
-        //     encoded_message.extend_from_slice(&message_type_bytes);
-        //     encoded_message.extend_from_slice(&buf);
 
-        //     // Send the message to the child process
-        //     stdin.write_all(&encoded_message).unwrap();
-        //     stdin.write_all(b"\n").unwrap();
-        //     stdin.flush().unwrap();
+#[cfg(test)]
+mod tests {
 
-        //     // Read the response for each message
-        //     let mut output = Vec::new();
-        //     reader.read_until(b'\n', &mut output).unwrap();
+    use super::*;
+    use serde::Deserialize;
+    use tokio::net::TcpStream;
+    use tokio::runtime::Builder;
 
-        //     // process the response
-        //     let (type_prefix, message_data) = output.split_at(4);
-        //     let _request_type = u32::from_be_bytes([type_prefix[0], type_prefix[1], type_prefix[2], type_prefix[3]]);
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Echo {
+        pub message: String,
+    }
 
-        //     // return the response via TCP without any processing
-        //     socket.write_all(&message_data).await.unwrap();
-        // }
-        Ok(())
-        
+    #[test]
+    fn test_start_and_shutdown() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8116";
+            // `cat -` writes each message back so it stands in for a WASM server echoing contracts
+            let mut proxy = TcpToWasmProxy::new(address.to_string(), "-".to_string())
+                .with_runtime("cat".to_string());
+            proxy.start::<Echo>().await.unwrap();
+            let pid = proxy.child_id().unwrap();
+
+            let stream = TcpStream::connect(address).await.unwrap();
+            let mut framed = Framed::new(stream, BincodeCodec::<Echo>::new());
+            framed.send(Echo { message: "hello".to_string() }).await.unwrap();
+            let response = framed.next().await.unwrap().unwrap();
+            assert_eq!(response, Echo { message: "hello".to_string() });
+
+            proxy.shutdown().await.unwrap();
+
+            // signal 0 only checks that the process exists, which fails once it has been killed and reaped
+            let alive = std::process::Command::new("kill")
+                .args(["-0", &pid.to_string()])
+                .stderr(Stdio::null())
+                .status()
+                .unwrap();
+            assert!(!alive.success());
+            assert!(TcpStream::connect(address).await.is_err());
+        });
     }
+
+    #[test]
+    fn test_contract_containing_newlines() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8141";
+            let mut proxy = TcpToWasmProxy::new(address.to_string(), "-".to_string())
+                .with_runtime("cat".to_string());
+            proxy.start::<Echo>().await.unwrap();
+
+            // a 10 byte string has a length prefix starting with 0x0A, which cut newline framed responses short
+            let contracts = [
+                Echo { message: "0123456789".to_string() },
+                Echo { message: "\n\n\n".to_string() },
+            ];
+            assert!(bincode::serialize(&contracts[0]).unwrap().contains(&b'\n'));
+            for contract in contracts {
+                let stream = TcpStream::connect(address).await.unwrap();
+                let mut framed = Framed::new(stream, BincodeCodec::<Echo>::new());
+                framed.send(Echo { message: contract.message.clone() }).await.unwrap();
+                let response = framed.next().await.unwrap().unwrap();
+                assert_eq!(response, contract);
+            }
+
+            proxy.shutdown().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_contract_frames() {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let mut pipe = Vec::new();
+            write_contract_frame(&mut pipe, &[10; 64]).await.unwrap();
+            assert_eq!(&pipe[..4], 64u32.to_be_bytes());
+            assert_eq!(read_contract_frame(&mut pipe.as_slice(), 64).await.unwrap(), Some(vec![10; 64]));
+
+            let error = read_contract_frame(&mut pipe.as_slice(), 32).await.unwrap_err();
+            assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+            let error = read_contract_frame(&mut &pipe[..40], 64).await.unwrap_err();
+            assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+            assert_eq!(read_contract_frame(&mut &pipe[..0], 64).await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn test_many_requests_are_answered_in_order() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(4)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8142";
+            let mut proxy = TcpToWasmProxy::new(address.to_string(), "-".to_string())
+                .with_runtime("cat".to_string());
+            proxy.start::<Echo>().await.unwrap();
+
+            let mut clients = JoinSet::new();
+            for client in 0..16 {
+                clients.spawn(async move {
+                    let stream = TcpStream::connect(address).await.unwrap();
+                    let mut framed = Framed::new(stream, BincodeCodec::<Echo>::new());
+                    for request in 0..20 {
+                        let message = format!("{}-{}", client, request);
+                        framed.send(Echo { message: message.clone() }).await.unwrap();
+                        let response = framed.next().await.unwrap().unwrap();
+                        assert_eq!(response, Echo { message });
+                    }
+                });
+            }
+            // larger than a pipe buffer so writing it before reading the echo would block both sides
+            clients.spawn(async move {
+                let stream = TcpStream::connect(address).await.unwrap();
+                let mut framed = Framed::new(stream, BincodeCodec::<Echo>::new());
+                let message = "A".repeat(1024 * 1024);
+                framed.send(Echo { message: message.clone() }).await.unwrap();
+                let response = framed.next().await.unwrap().unwrap();
+                assert_eq!(response, Echo { message });
+            });
+
+            let all_answered = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+                while let Some(result) = clients.join_next().await {
+                    result.unwrap();
+                }
+            }).await;
+            assert!(all_answered.is_ok(), "the proxy deadlocked");
+
+            proxy.shutdown().await.unwrap();
+        });
+    }
+
 }
@@ -0,0 +1,156 @@
+//! Defines sending contracts over UDP for fire-and-forget contracts such as telemetry where losing the odd contract
+//! is cheaper than the overhead of a connection. A contract is sent as a single datagram framed like the
+//! `BincodeContractWrapper` (the length of the contract followed by the contract) and no response is sent back.
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use crate::networking::serialization::wrappers::bincode::BincodeContractWrapper;
+use crate::networking::utils::check_payload_size;
+use serde::{de::DeserializeOwned, Serialize};
+use std::future::Future;
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+
+
+/// The largest payload of a UDP datagram over IPv4 (65,535 bytes less the IP and UDP headers).
+pub const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+
+/// Serializes a contract into a datagram.
+///
+/// # Arguments
+/// * `contract` - The contract to serialize.
+///
+/// # Returns
+/// * `Result<Vec<u8>, NanoServiceError>` - The datagram, or a `PayloadTooLarge` error if it does not fit in one.
+pub fn encode_datagram<T: Serialize + DeserializeOwned>(contract: T) -> Result<Vec<u8>, NanoServiceError> {
+    let wrapper = BincodeContractWrapper::new(contract)?;
+    let mut datagram = Vec::new();
+    wrapper.blocking_send(&mut datagram)?;
+    check_payload_size(datagram.len(), MAX_DATAGRAM_SIZE)?;
+    Ok(datagram)
+}
+
+
+/// Deserializes a contract from a datagram.
+///
+/// # Arguments
+/// * `datagram` - The bytes of the datagram.
+///
+/// # Returns
+/// * `Result<T, NanoServiceError>` - The contract, or an error if the datagram is truncated or malformed.
+pub fn decode_datagram<T: Serialize + DeserializeOwned>(datagram: &[u8]) -> Result<T, NanoServiceError> {
+    let mut wrapper = BincodeContractWrapper::<T>::empty();
+    wrapper.blocking_receive(&mut &datagram[..]).map_err(|e| {
+        NanoServiceError::new(format!("Malformed datagram: {}", e.message), NanoServiceErrorStatus::BadRequest)
+    })?;
+    wrapper.contract.ok_or_else(|| {
+        NanoServiceError::new("Datagram did not hold a contract".to_string(), NanoServiceErrorStatus::BadRequest)
+    })
+}
+
+
+/// Sends a contract over UDP to the specified address without waiting for a response. The contract is not
+/// guaranteed to arrive.
+///
+/// # Arguments
+/// * `contract` - The contract to send.
+/// * `address` - The address to send the contract to.
+///
+/// # Returns
+/// * `Result<(), NanoServiceError>` - An error if the contract does not fit in a datagram or could not be sent.
+pub async fn send_data_contract_over_udp<T>(contract: T, address: &str) -> Result<(), NanoServiceError>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let datagram = encode_datagram(contract)?;
+    let address: SocketAddr = tokio::net::lookup_host(address).await?.next().ok_or_else(|| {
+        NanoServiceError::new(format!("Could not resolve {}", address), NanoServiceErrorStatus::BadRequest)
+    })?;
+    let local_address = match address {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    };
+    let socket = UdpSocket::bind(local_address).await?;
+    socket.send_to(&datagram, address).await?;
+    Ok(())
+}
+
+
+/// Receives contracts on a UDP socket and spawns the handler for each one. Datagrams that cannot be decoded are
+/// logged and dropped. This only returns if the socket fails.
+///
+/// # Arguments
+/// * `socket` - The socket bound to the address contracts are sent to.
+/// * `handler` - Called with each contract received and the address it was sent from.
+///
+/// # Returns
+/// * `Result<(), NanoServiceError>` - The error that stopped the socket receiving.
+pub async fn run_udp_server<T, F, Fut>(socket: UdpSocket, handler: F) -> Result<(), NanoServiceError>
+where
+    T: Serialize + DeserializeOwned,
+    F: Fn(T, SocketAddr) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+    loop {
+        let (len, sender) = socket.recv_from(&mut buffer).await?;
+        match decode_datagram::<T>(&buffer[..len]) {
+            Ok(contract) => {
+                tokio::spawn(handler(contract, sender));
+            },
+            Err(e) => eprintln!("Dropped datagram from {}: {}", sender, e)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde::Deserialize;
+    use tokio::runtime::Builder;
+    use tokio::sync::mpsc;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Reading {
+        pub sensor: String,
+        pub value: f64,
+    }
+
+    #[test]
+    fn test_send_data_contract_over_udp() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8113";
+            let socket = UdpSocket::bind(address).await.unwrap();
+            let (sender, mut receiver) = mpsc::unbounded_channel();
+            let _server = tokio::spawn(run_udp_server(socket, move |reading: Reading, _| {
+                let sender = sender.clone();
+                async move {
+                    sender.send(reading).unwrap();
+                }
+            }));
+
+            let reading = Reading { sensor: "temperature".to_string(), value: 21.5 };
+            send_data_contract_over_udp(reading.clone(), address).await.unwrap();
+            let received = tokio::time::timeout(tokio::time::Duration::from_secs(1), receiver.recv()).await;
+            assert_eq!(received.unwrap().unwrap(), reading);
+        });
+    }
+
+    #[test]
+    fn test_datagram_limits() {
+        let reading = Reading { sensor: "x".repeat(MAX_DATAGRAM_SIZE), value: 0.0 };
+        let error = encode_datagram(reading).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+
+        let datagram = encode_datagram(Reading { sensor: "humidity".to_string(), value: 0.4 }).unwrap();
+        assert!(decode_datagram::<Reading>(&datagram[..datagram.len() - 1]).is_err());
+    }
+
+}
@@ -1,5 +1,6 @@
 //! Basic utils module that can be used in any networking related code.
 use std::net::{TcpListener, SocketAddr};
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
 
 
 /// Find an available port on the system.
@@ -17,6 +18,26 @@ pub fn find_available_port() -> Option<u32> {
 }
 
 
+/// Checks that a payload is within a size limit so oversized payloads are reported as `PayloadTooLarge` rather
+/// than as malformed.
+///
+/// # Arguments
+/// * `len` - The length of the payload in bytes.
+/// * `limit` - The maximum length allowed in bytes.
+///
+/// # Returns
+/// * `Result<(), NanoServiceError>` - A `PayloadTooLarge` error if the payload is over the limit.
+pub fn check_payload_size(len: usize, limit: usize) -> Result<(), NanoServiceError> {
+    if len > limit {
+        return Err(NanoServiceError::new(
+            format!("Payload of {} bytes exceeds the limit of {} bytes", len, limit),
+            NanoServiceErrorStatus::PayloadTooLarge
+        ))
+    }
+    Ok(())
+}
+
+
 #[cfg(test)]
 mod tests {
 
@@ -25,7 +46,15 @@ mod tests {
     #[test]
     fn test_find_available_port() {
         let port = find_available_port().unwrap();
-        assert!(port >= 8000 && port <= 65535);
+        assert!((8000..=65535).contains(&port));
+    }
+
+    #[test]
+    fn test_check_payload_size() {
+        assert_eq!(check_payload_size(10, 10), Ok(()));
+        let error = check_payload_size(11, 10).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+        assert_eq!(error.message, "Payload of 11 bytes exceeds the limit of 10 bytes");
     }
 
 }
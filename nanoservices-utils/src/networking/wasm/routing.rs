@@ -1,22 +1,46 @@
+//! Defines the routing of contracts to handler functions inside a WASM guest. Errors are passed back to the host
+//! rather than panicking the guest, with a negative length in the returned `ContractPointer` signalling that the
+//! bytes are a serialized `NanoServiceError` instead of the contract.
+//!
+//! Guests that receive contracts over stdin instead of through exported functions read them with
+//! `read_contract_frame`, which reads a 4 byte big endian length followed by exactly that many bytes no matter how
+//! the pipe splits them up, and write responses back with `write_contract_frame`.
+use serde::{Serialize, de::DeserializeOwned};
+use std::io::{ErrorKind, Read, Write};
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+use crate::networking::utils::check_payload_size;
+
+
+/// The largest contract read from a stream by `read_contract_frame` when a limit is not given (16 MiB).
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
 
 
 #[macro_export]
 macro_rules! register_wasm_contract_routes {
-    ($handler_enum:ident, $fn_name:ident, $( $contract:ident => $handler_fn:path ),*) => {
+    ($handler_enum:ident, $fn_name:ident, $( $( $contract:ident )|+ => $handler_fn:path ),*) => {
         fn $fn_name(received_msg: $handler_enum) -> Result<$handler_enum, NanoServiceError> {
             match received_msg {
                 msg => match msg {
                     $(
-                        $handler_enum::$contract(inner) => {
-                            // need to add error handling
-                            let executed_contract = $handler_fn(inner)?;
-                            return Ok($handler_enum::$contract(executed_contract));
-                        }
+                        $(
+                            $handler_enum::$contract(inner) => {
+                                // need to add error handling
+                                let executed_contract = $handler_fn(inner)?;
+                                return Ok($handler_enum::$contract(executed_contract));
+                            }
+                        )+
                     )*
-                    _ => Err(NanoServiceError::new(
-                            "Received unknown contract type.".to_string(),
-                            NanoServiceErrorStatus::ContractNotSupported
-                        )),
+                    // an error contract is passed back as the error rather than reported as an unknown contract
+                    $handler_enum::NanoServiceError(error) => Err(error),
+                    #[allow(unreachable_patterns)]
+                    unsupported => {
+                        let format = $crate::networking::wire_ref::WireRefFormat::CURRENT;
+                        let supported_refs = vec![ $( $( format.contract_ref(stringify!($contract)), )+ )* ];
+                        Err($crate::networking::wire_ref::contract_not_supported(
+                            &unsupported.to_string_ref(),
+                            &supported_refs
+                        ))
+                    },
                 },
             }
         }
@@ -42,7 +66,7 @@ macro_rules! register_wasm_contract_routes {
         /// 
         /// # Fields
         /// - `ptr` - The pointer to the serialized data memory address
-        /// - `len` - The length of the serialized data
+        /// - `len` - The length of the serialized data, negative if the data is a serialized `NanoServiceError`
         #[repr(C)]
         pub struct ContractPointer {
             ptr: i32,
@@ -50,24 +74,328 @@ macro_rules! register_wasm_contract_routes {
         }
 
         $(
-            paste! {
-                #[no_mangle]
-                pub extern "C" fn [<$contract:lower _contract>](ptr: *const u8, len: usize) -> *const ContractPointer {
-                    let bytes = unsafe { std::slice::from_raw_parts(ptr, len) };
-                    let contract: $contract = bincode::deserialize(bytes).unwrap();
-                    let result = $handler_fn(contract).unwrap();
-
-                    let serialized_data = bincode::serialize(&result).unwrap();
-                    let len = serialized_data.len();
-                    let out_ptr = serialized_data.leak().as_ptr();
-
-                    let result = Box::new(ContractPointer{
-                        ptr: out_ptr as i32,
-                        len: len as i32
-                    });
-                    Box::into_raw(result) as *const ContractPointer
+            $(
+                paste! {
+                    #[no_mangle]
+                    pub unsafe extern "C" fn [<$contract:lower _contract>](ptr: *const u8, len: usize) -> *const ContractPointer {
+                        let bytes = unsafe { std::slice::from_raw_parts(ptr, len) };
+                        let (serialized_data, is_error) = match $crate::networking::wasm::routing::handle_wasm_contract_bytes::<$contract, _>(
+                            bytes,
+                            $handler_fn
+                        ) {
+                            Ok(data) => (data, false),
+                            Err(error_data) => (error_data, true)
+                        };
+                        let len = serialized_data.len() as i32;
+                        let out_ptr = serialized_data.leak().as_ptr();
+
+                        let result = Box::new(ContractPointer{
+                            ptr: out_ptr as i32,
+                            len: if is_error { -len } else { len }
+                        });
+                        Box::into_raw(result) as *const ContractPointer
+                    }
                 }
-            }
+            )+
         )*
     };
 }
+
+
+/// Registers the same handler functions for both a TCP server and a WASM guest so the `Contract => fn` list is
+/// only declared once. The handlers are sync functions as required by the WASM guest, the TCP routing function
+/// is async and calls the sync handlers. This generates everything `register_wasm_contract_routes!` generates
+/// plus the async routing function.
+///
+/// # Arguments
+/// * `handler_enum` - The contract handler enum.
+/// * `tcp_fn_name` - The name of the async routing function for the TCP server.
+/// * `wasm_fn_name` - The name of the sync routing function for the WASM guest.
+/// * `contract => handler_fn` - The contracts and the sync functions that handle them. Several contracts can share
+///   a generic handler with `ContractOne | ContractTwo => handler_fn`.
+#[macro_export]
+macro_rules! register_tcp_and_wasm_contract_routes {
+    ($handler_enum:ident, $tcp_fn_name:ident, $wasm_fn_name:ident, $( $( $contract:ident )|+ => $handler_fn:path ),*) => {
+        pub async fn $tcp_fn_name(received_msg: $handler_enum) -> Result<$handler_enum, NanoServiceError> {
+            $wasm_fn_name(received_msg)
+        }
+
+        $crate::register_wasm_contract_routes!(
+            $handler_enum,
+            $wasm_fn_name,
+            $( $( $contract )|+ => $handler_fn ),*
+        );
+    };
+}
+
+
+/// Deserializes a contract from the bytes passed in by the host, runs the handler, and serializes the response.
+///
+/// # Arguments
+/// * `bytes` - The serialized contract from the host.
+/// * `handler` - The function that handles the contract.
+///
+/// # Returns
+/// * `Result<Vec<u8>, Vec<u8>>` - The serialized response or the serialized `NanoServiceError` if anything failed.
+pub fn handle_wasm_contract_bytes<C, F>(bytes: &[u8], handler: F) -> Result<Vec<u8>, Vec<u8>>
+where
+    C: Serialize + DeserializeOwned,
+    F: FnOnce(C) -> Result<C, NanoServiceError>,
+{
+    let result = bincode::deserialize::<C>(bytes).map_err(|e| {
+        NanoServiceError::new(
+            format!("Failed to deserialize contract: {}", e),
+            NanoServiceErrorStatus::BadRequest
+        )
+    }).and_then(handler).and_then(|contract| {
+        bincode::serialize(&contract).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+        })
+    });
+    result.map_err(|error| {
+        // serializing the error struct cannot fail as it is only strings and a unit enum
+        bincode::serialize(&error).unwrap_or_default()
+    })
+}
+
+
+/// Reads a contract framed as a 4 byte big endian length followed by the contract bytes. The bytes are read until
+/// the whole contract has been received, so a contract split over many reads (e.g. a large contract over stdin)
+/// is received in full.
+///
+/// # Arguments
+/// * `reader` - The stream to read the contract from (e.g. stdin).
+/// * `max_size` - The largest contract accepted, larger contracts are rejected before their bytes are read.
+///
+/// # Returns
+/// * `Result<Option<Vec<u8>>, NanoServiceError>` - The contract bytes, `None` if the stream ended before a contract.
+pub fn read_contract_frame<R: Read>(reader: &mut R, max_size: usize) -> Result<Option<Vec<u8>>, NanoServiceError> {
+    let mut length_bytes = [0; 4];
+    match reader.read_exact(&mut length_bytes) {
+        Ok(()) => {},
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown))
+    }
+    let length = u32::from_be_bytes(length_bytes) as usize;
+    check_payload_size(length, max_size)?;
+    let mut contract_bytes = vec![0; length];
+    reader.read_exact(&mut contract_bytes).map_err(|e| {
+        NanoServiceError::new(
+            format!("Contract ended after fewer than {} bytes: {}", length, e),
+            NanoServiceErrorStatus::BadRequest
+        )
+    })?;
+    Ok(Some(contract_bytes))
+}
+
+
+/// Writes contract bytes framed as a 4 byte big endian length followed by the bytes, and flushes the stream so the
+/// contract is not left sitting in a buffer.
+///
+/// # Arguments
+/// * `writer` - The stream to write the contract to (e.g. stdout).
+/// * `contract_bytes` - The serialized contract.
+///
+/// # Returns
+/// * `Result<(), NanoServiceError>` - An error if the contract is too large to frame or could not be written.
+pub fn write_contract_frame<W: Write>(writer: &mut W, contract_bytes: &[u8]) -> Result<(), NanoServiceError> {
+    let length = u32::try_from(contract_bytes.len()).map_err(|_| {
+        NanoServiceError::new(
+            "Contract is too large for a 4 byte length".to_string(),
+            NanoServiceErrorStatus::PayloadTooLarge
+        )
+    })?;
+    writer.write_all(&length.to_be_bytes()).and_then(|_| {
+        writer.write_all(contract_bytes)
+    }).and_then(|_| {
+        writer.flush()
+    }).map_err(|e| {
+        NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    pub struct ContractOne {
+        pub name: String,
+    }
+
+    fn handle_contract_one(mut contract: ContractOne) -> Result<ContractOne, NanoServiceError> {
+        if contract.name.is_empty() {
+            return Err(NanoServiceError::new("Name is empty".to_string(), NanoServiceErrorStatus::BadRequest))
+        }
+        contract.name = "Bob".to_string();
+        Ok(contract)
+    }
+
+    #[test]
+    fn test_handle_wasm_contract_bytes() {
+        let bytes = bincode::serialize(&ContractOne { name: "Alice".to_string() }).unwrap();
+        let response = handle_wasm_contract_bytes(&bytes, handle_contract_one).unwrap();
+        let contract: ContractOne = bincode::deserialize(&response).unwrap();
+        assert_eq!(contract, ContractOne { name: "Bob".to_string() });
+    }
+
+    #[test]
+    fn test_handle_wasm_contract_bytes_malformed() {
+        let error_bytes = handle_wasm_contract_bytes(&[255, 255, 255], handle_contract_one).unwrap_err();
+        let error: NanoServiceError = bincode::deserialize(&error_bytes).unwrap();
+        assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+        assert!(error.message.starts_with("Failed to deserialize contract"));
+    }
+
+    #[test]
+    fn test_handle_wasm_contract_bytes_handler_error() {
+        let bytes = bincode::serialize(&ContractOne { name: "".to_string() }).unwrap();
+        let error_bytes = handle_wasm_contract_bytes(&bytes, handle_contract_one).unwrap_err();
+        let error: NanoServiceError = bincode::deserialize(&error_bytes).unwrap();
+        assert_eq!(error, NanoServiceError::new("Name is empty".to_string(), NanoServiceErrorStatus::BadRequest));
+    }
+
+    /// A pipe that hands out at most a few bytes per read like a pipe under load.
+    struct TrickleReader {
+        bytes: Vec<u8>,
+        position: usize,
+    }
+
+    impl Read for TrickleReader {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            let end = (self.position + 7).min(self.bytes.len()).min(self.position + buffer.len());
+            let read = end - self.position;
+            buffer[..read].copy_from_slice(&self.bytes[self.position..end]);
+            self.position = end;
+            Ok(read)
+        }
+    }
+
+    #[test]
+    fn test_large_contract_frame_over_stdin() {
+        let contract = ContractOne { name: "A".repeat(1024 * 1024) };
+        let bytes = bincode::serialize(&contract).unwrap();
+        let mut pipe = Vec::new();
+        write_contract_frame(&mut pipe, &bytes).unwrap();
+        write_contract_frame(&mut pipe, &bytes).unwrap();
+
+        let mut reader = TrickleReader { bytes: pipe, position: 0 };
+        for _ in 0..2 {
+            let received = read_contract_frame(&mut reader, DEFAULT_MAX_FRAME_SIZE).unwrap().unwrap();
+            assert_eq!(received.len(), bytes.len());
+            let response = handle_wasm_contract_bytes(&received, handle_contract_one).unwrap();
+            let contract: ContractOne = bincode::deserialize(&response).unwrap();
+            assert_eq!(contract, ContractOne { name: "Bob".to_string() });
+        }
+        assert_eq!(read_contract_frame(&mut reader, DEFAULT_MAX_FRAME_SIZE).unwrap(), None);
+    }
+
+    #[test]
+    fn test_contract_frame_limits() {
+        let mut pipe = Vec::new();
+        write_contract_frame(&mut pipe, &[1; 64]).unwrap();
+        let error = read_contract_frame(&mut pipe.as_slice(), 32).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+
+        let error = read_contract_frame(&mut &pipe[..40], DEFAULT_MAX_FRAME_SIZE).unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+    }
+
+    mod combined {
+        use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+        use crate::create_contract_handler;
+        use serde::{Serialize, Deserialize};
+        use paste::paste;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct ContractOne {
+            pub name: String,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        pub struct ContractTwo;
+
+        create_contract_handler!(
+            ContractHandler,
+            ContractOne,
+            ContractTwo
+        );
+
+        pub fn handle_contract_one(mut contract: ContractOne) -> Result<ContractOne, NanoServiceError> {
+            contract.name = "Bob".to_string();
+            Ok(contract)
+        }
+
+        register_tcp_and_wasm_contract_routes!(
+            ContractHandler,
+            handle_tcp_contract,
+            handle_wasm_contract,
+            ContractOne => handle_contract_one
+        );
+
+        pub fn route_wasm_contract(contract: ContractHandler) -> Result<ContractHandler, NanoServiceError> {
+            handle_wasm_contract(contract)
+        }
+    }
+
+    #[cfg(feature = "tcp-messaging")]
+    #[test]
+    fn test_tcp_and_wasm_contract_routes() {
+        use combined::{ContractHandler, ContractOne, ContractTwo, handle_tcp_contract, route_wasm_contract};
+        use crate::networking::serialization::codec::BincodeCodec;
+        use crate::networking::tcp::client::send_data_contract_over_tcp;
+        use futures::{sink::SinkExt, StreamExt};
+        use tokio::net::TcpListener;
+        use tokio_util::codec::Framed;
+
+        async fn tcp_server(addr: &str) {
+            let listener = TcpListener::bind(addr).await.unwrap();
+
+            while let Ok((socket, _)) = listener.accept().await {
+                let mut framed = Framed::new(socket, BincodeCodec::<ContractHandler>::new());
+
+                if let Some(Ok(data)) = framed.next().await {
+                    let response = match handle_tcp_contract(data).await {
+                        Ok(response) => response,
+                        Err(e) => ContractHandler::NanoServiceError(e)
+                    };
+                    framed.send(response).await.unwrap();
+                }
+            }
+        }
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let address = "127.0.0.1:8103";
+            let _server = tokio::spawn(tcp_server(address));
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let contract = ContractHandler::ContractOne(ContractOne { name: "Alice".to_string() });
+            let response = send_data_contract_over_tcp(contract, address).await.unwrap();
+            assert_eq!(response.ContractOne().unwrap(), ContractOne { name: "Bob".to_string() });
+
+            let response = send_data_contract_over_tcp(ContractHandler::ContractTwo(ContractTwo), address).await.unwrap();
+            assert_eq!(response.NanoServiceError().unwrap().status, NanoServiceErrorStatus::ContractNotSupported);
+
+            let error = NanoServiceError::new("Client failed".to_string(), NanoServiceErrorStatus::BadRequest);
+            assert_eq!(route_wasm_contract(ContractHandler::NanoServiceError(error.clone())), Err(error));
+        });
+
+        let contract = ContractHandler::ContractOne(ContractOne { name: "Alice".to_string() });
+        let response = route_wasm_contract(contract).unwrap();
+        assert_eq!(response.ContractOne().unwrap(), ContractOne { name: "Bob".to_string() });
+
+        let bytes = bincode::serialize(&ContractOne { name: "Alice".to_string() }).unwrap();
+        let response = handle_wasm_contract_bytes(&bytes, combined::handle_contract_one).unwrap();
+        let contract: ContractOne = bincode::deserialize(&response).unwrap();
+        assert_eq!(contract, ContractOne { name: "Bob".to_string() });
+    }
+
+}
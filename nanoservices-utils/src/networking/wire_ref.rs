@@ -0,0 +1,129 @@
+//! Defines the formats of the wire refs that name the contract being sent alongside its bytes. The format of a ref
+//! can change without breaking peers because receivers accept every known format, so a new format is rolled out by
+//! first deploying receivers that accept it and then switching `WireRefFormat::CURRENT` so senders use it.
+//!
+//! The formats are:
+//! * `Legacy` - `contractone_contract` for a contract and `nanoService_error` for an error.
+//! * `V1` - a version prefix followed by the variant name, `v1:contractone` for a contract and
+//!   `v1:nanoserviceerror` for an error.
+use crate::errors::{NanoServiceError, NanoServiceErrorStatus};
+
+
+/// The legacy wire ref of the error variant of a contract handler.
+pub const LEGACY_ERROR_REF: &str = "nanoService_error";
+
+/// The name of the error variant of a contract handler used by versioned wire refs.
+const ERROR_VARIANT: &str = "nanoserviceerror";
+
+
+/// A format of the wire ref of a contract.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WireRefFormat {
+    Legacy,
+    V1,
+}
+
+impl WireRefFormat {
+
+    /// The format that contract handlers send. This stays `Legacy` until every peer accepts `V1`.
+    pub const CURRENT: WireRefFormat = WireRefFormat::Legacy;
+
+    /// Builds the wire ref of a contract.
+    ///
+    /// # Arguments
+    /// * `variant` - The name of the variant in the contract handler.
+    ///
+    /// # Returns
+    /// * `String` - The wire ref of the contract in this format.
+    pub fn contract_ref(&self, variant: &str) -> String {
+        match self {
+            WireRefFormat::Legacy => format!("{}_contract", variant.to_lowercase()),
+            WireRefFormat::V1 => format!("v1:{}", variant.to_lowercase()),
+        }
+    }
+
+    /// Builds the wire ref of an error.
+    ///
+    /// # Returns
+    /// * `String` - The wire ref of the error in this format.
+    pub fn error_ref(&self) -> String {
+        match self {
+            WireRefFormat::Legacy => LEGACY_ERROR_REF.to_string(),
+            WireRefFormat::V1 => format!("v1:{}", ERROR_VARIANT),
+        }
+    }
+}
+
+
+/// Extracts the variant named by a wire ref in any known format.
+///
+/// # Arguments
+/// * `string_ref` - The wire ref received.
+///
+/// # Returns
+/// * `Option<String>` - The lowercase name of the variant, `None` if the ref is not in a known format.
+pub fn variant_of(string_ref: &str) -> Option<String> {
+    if string_ref == LEGACY_ERROR_REF {
+        return Some(ERROR_VARIANT.to_string())
+    }
+    if let Some(variant) = string_ref.strip_prefix("v1:") {
+        return Some(variant.to_string())
+    }
+    string_ref.strip_suffix("_contract").map(|variant| variant.to_string())
+}
+
+
+/// Builds the error returned for a contract that is not supported, listing the wire refs that are so the sender
+/// can see what the receiver accepts.
+///
+/// # Arguments
+/// * `string_ref` - The wire ref of the contract received.
+/// * `supported_refs` - The wire refs of the contracts that are supported.
+///
+/// # Returns
+/// * `NanoServiceError` - A `ContractNotSupported` error naming the contract and the supported contracts.
+pub fn contract_not_supported(string_ref: &str, supported_refs: &[String]) -> NanoServiceError {
+    NanoServiceError::new(
+        format!("Contract not supported: {}. Supported contracts: {}", string_ref, supported_refs.join(", ")),
+        NanoServiceErrorStatus::ContractNotSupported
+    )
+}
+
+
+/// Checks if a wire ref in any known format names an error.
+///
+/// # Arguments
+/// * `string_ref` - The wire ref to check.
+///
+/// # Returns
+/// * `bool` - Whether the ref names the error variant.
+pub fn is_error_ref(string_ref: &str) -> bool {
+    variant_of(string_ref).as_deref() == Some(ERROR_VARIANT)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(WireRefFormat::Legacy.contract_ref("ContractOne"), "contractone_contract");
+        assert_eq!(WireRefFormat::V1.contract_ref("ContractOne"), "v1:contractone");
+        assert_eq!(WireRefFormat::Legacy.error_ref(), "nanoService_error");
+        assert_eq!(WireRefFormat::V1.error_ref(), "v1:nanoserviceerror");
+    }
+
+    #[test]
+    fn test_variant_of() {
+        for format in [WireRefFormat::Legacy, WireRefFormat::V1] {
+            assert_eq!(variant_of(&format.contract_ref("ContractOne")).as_deref(), Some("contractone"));
+            assert!(is_error_ref(&format.error_ref()));
+            assert!(!is_error_ref(&format.contract_ref("ContractOne")));
+        }
+        assert_eq!(variant_of("v2:contractone"), None);
+        assert_eq!(variant_of("contractone"), None);
+    }
+
+}
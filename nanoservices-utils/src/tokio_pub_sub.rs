@@ -1,3 +1,318 @@
+//! Defines the event bus for tokio based pub/sub. An `EventBus` can be constructed directly for isolated buses
+//! (e.g. in tests) and the `config_tokio_event_runtime!` macro generates a module holding a default global bus
+//! that the `subscribe_to_event` and `publish_event` macros use.
+//!
+//! Each bus drops published events whose data is over its size limit, set with `EventBus::set_event_size_limit`,
+//! before they reach the subscribers. Subscribers deserialize the data of an event with `deserialize_event`, which
+//! rejects a length in the data that goes past the end of the data before allocating for it.
+//!
+//! Events published with `publish_with_key` carry an idempotency key, and an event whose key was already dispatched
+//! within the dedup window of the bus is dropped, so a publisher that retries after a crash does not run the
+//! subscribers twice.
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use tokio::task::JoinSet;
+
+
+/// A function that handles the serialized data of an event.
+pub type EventFunction = fn(Vec<u8>) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// The functions subscribed to an event.
+pub type EventFunctionBuffer = Vec<EventFunction>;
+
+/// The largest event data in bytes that a bus dispatches when a limit is not set.
+pub const DEFAULT_EVENT_SIZE_LIMIT: u64 = 10 * 1024 * 1024;
+
+/// The time an idempotency key is remembered for when a dedup window is not set.
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(60);
+
+/// Deserializes the bincode data of an event for a subscriber. The data is read with its own length as the limit
+/// so a length in the data that goes past the end of the data is rejected before anything is allocated for it.
+/// Data over the size limit of the bus never reaches the subscriber. A rejected event is logged rather than
+/// panicking the subscriber task.
+///
+/// # Arguments
+/// * `name` - The name of the event, used in the log.
+/// * `data` - The serialized data of the event.
+///
+/// # Returns
+/// * `Option<T>` - The event, or `None` if the data could not be deserialized.
+pub fn deserialize_event<T: DeserializeOwned>(name: &str, data: &[u8]) -> Option<T> {
+    let result = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(data.len() as u64)
+        .deserialize(data);
+    match result {
+        Ok(event) => Some(event),
+        Err(e) => {
+            eprintln!("Rejected event {}: {}", name, e);
+            None
+        }
+    }
+}
+
+
+/// The state shared between the clones of an `EventBus`.
+///
+/// # Fields
+/// * `subscribers` - The functions subscribed to each event.
+/// * `tasks` - The subscriber tasks that have been spawned by `publish` and not reaped yet.
+/// * `dedup` - The idempotency keys dispatched within the dedup window.
+/// * `event_size_limit` - The largest event data in bytes that is dispatched to the subscribers.
+struct EventBusInner {
+    subscribers: RwLock<HashMap<String, EventFunctionBuffer>>,
+    tasks: Mutex<JoinSet<()>>,
+    dedup: Mutex<DedupWindow>,
+    event_size_limit: AtomicU64,
+}
+
+impl Default for EventBusInner {
+    fn default() -> Self {
+        EventBusInner {
+            subscribers: RwLock::default(),
+            tasks: Mutex::default(),
+            dedup: Mutex::default(),
+            event_size_limit: AtomicU64::new(DEFAULT_EVENT_SIZE_LIMIT),
+        }
+    }
+}
+
+
+/// The idempotency keys of the keyed events dispatched by a bus.
+///
+/// # Fields
+/// * `window` - How long a key is remembered for after its event is dispatched.
+/// * `seen` - When each event name and key pair was dispatched.
+struct DedupWindow {
+    window: Duration,
+    seen: HashMap<(String, String), Instant>,
+}
+
+impl Default for DedupWindow {
+    fn default() -> Self {
+        DedupWindow {
+            window: DEFAULT_DEDUP_WINDOW,
+            seen: HashMap::new(),
+        }
+    }
+}
+
+impl DedupWindow {
+
+    /// Records the key of an event unless it was already recorded within the window. Keys that have fallen out of
+    /// the window are forgotten so the map only holds the keys of recent events.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the event.
+    /// * `key` - The idempotency key of the event.
+    /// * `now` - The time the event is being dispatched.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if the event has not been seen within the window and should be dispatched.
+    fn record(&mut self, name: &str, key: &str, now: Instant) -> bool {
+        let window = self.window;
+        self.seen.retain(|_, dispatched| now.duration_since(*dispatched) < window);
+        let id = (name.to_string(), key.to_string());
+        if self.seen.contains_key(&id) {
+            return false
+        }
+        self.seen.insert(id, now);
+        true
+    }
+}
+
+
+impl EventBusInner {
+
+    // A panic while a lock is held poisons it. The subscribers and tasks are still consistent as every update is
+    // a single insert or spawn, so the guard is recovered rather than breaking every future publish.
+
+    fn read_subscribers(&self) -> RwLockReadGuard<'_, HashMap<String, EventFunctionBuffer>> {
+        self.subscribers.read().unwrap_or_else(|poisoned| {
+            eprintln!("Event bus subscribers lock was poisoned, recovering");
+            self.subscribers.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    fn write_subscribers(&self) -> RwLockWriteGuard<'_, HashMap<String, EventFunctionBuffer>> {
+        self.subscribers.write().unwrap_or_else(|poisoned| {
+            eprintln!("Event bus subscribers lock was poisoned, recovering");
+            self.subscribers.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    fn lock_tasks(&self) -> MutexGuard<'_, JoinSet<()>> {
+        self.tasks.lock().unwrap_or_else(|poisoned| {
+            eprintln!("Event bus tasks lock was poisoned, recovering");
+            self.tasks.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    fn lock_dedup(&self) -> MutexGuard<'_, DedupWindow> {
+        self.dedup.lock().unwrap_or_else(|poisoned| {
+            eprintln!("Event bus dedup lock was poisoned, recovering");
+            self.dedup.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+}
+
+
+/// An event bus that runs the functions subscribed to an event when the event is published. Clones share the
+/// same subscribers.
+///
+/// # Fields
+/// * `inner` - The state shared between the clones of the bus.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    inner: Arc<EventBusInner>,
+}
+
+impl EventBus {
+
+    /// Constructs a new `EventBus` with no subscribers.
+    ///
+    /// # Returns
+    /// * `EventBus` - The new bus.
+    pub fn new() -> Self {
+        EventBus::default()
+    }
+
+    /// Subscribes a function to an event. Subscribers are dispatched in the order they were subscribed and
+    /// subscribing the same function to the same event more than once is a no-op so it only runs once.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the event.
+    /// * `func` - The function that handles the event.
+    pub fn subscribe(&self, name: String, func: EventFunction) {
+        let mut subscribers = self.inner.write_subscribers();
+        let buffer = subscribers.entry(name).or_default();
+        if buffer.iter().any(|registered| std::ptr::fn_addr_eq(*registered, func)) {
+            return
+        }
+        buffer.push(func);
+    }
+
+    /// Gets the functions subscribed to an event.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the event.
+    ///
+    /// # Returns
+    /// * `Option<EventFunctionBuffer>` - The subscribed functions or `None` if there are no subscribers.
+    pub fn subscribers(&self, name: &str) -> Option<EventFunctionBuffer> {
+        self.inner.read_subscribers().get(name).cloned()
+    }
+
+    /// Spawns a task for each function subscribed to the event. This must be called inside a tokio runtime. Data
+    /// over the event size limit of the bus is logged and dropped.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the event.
+    /// * `data` - The serialized data of the event.
+    pub fn publish(&self, name: &str, data: Vec<u8>) {
+        let limit = self.event_size_limit();
+        if data.len() as u64 > limit {
+            eprintln!("Rejected event {}: {} bytes exceeds the limit of {} bytes", name, data.len(), limit);
+            return
+        }
+        let buffer = match self.subscribers(name) {
+            Some(b) => b,
+            None => {
+                println!("No subscribers for event: {}", name);
+                return
+            }
+        };
+        let mut tasks = self.inner.lock_tasks();
+        // reap the finished tasks so the set does not grow with every event
+        while tasks.try_join_next().is_some() {}
+        for f in buffer {
+            let boxed_future = f(data.clone());
+            tasks.spawn(async move {
+                boxed_future.await;
+            });
+        }
+    }
+
+    /// Publishes an event with an idempotency key. The event is dropped if an event with the same name and key was
+    /// dispatched within the dedup window, so retrying a publish that may already have gone through is safe.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the event.
+    /// * `key` - The idempotency key of the event, unique to each distinct event.
+    /// * `data` - The serialized data of the event.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if the event was dispatched, `false` if it was dropped as a duplicate.
+    pub fn publish_with_key(&self, name: &str, key: &str, data: Vec<u8>) -> bool {
+        if !self.inner.lock_dedup().record(name, key, Instant::now()) {
+            return false
+        }
+        self.publish(name, data);
+        true
+    }
+
+    /// Sets how long the idempotency key of an event is remembered for after it is dispatched. The window is
+    /// shared by the clones of the bus.
+    ///
+    /// # Arguments
+    /// * `window` - The dedup window.
+    pub fn set_dedup_window(&self, window: Duration) {
+        self.inner.lock_dedup().window = window;
+    }
+
+    /// Gets how long the idempotency key of an event is remembered for after it is dispatched.
+    ///
+    /// # Returns
+    /// * `Duration` - The dedup window.
+    pub fn dedup_window(&self) -> Duration {
+        self.inner.lock_dedup().window
+    }
+
+    /// Sets the largest event data in bytes that the bus dispatches. Larger events are dropped by `publish`. The
+    /// limit is shared by the clones of the bus.
+    ///
+    /// # Arguments
+    /// * `limit` - The limit in bytes.
+    pub fn set_event_size_limit(&self, limit: u64) {
+        self.inner.event_size_limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// Gets the largest event data in bytes that the bus dispatches.
+    ///
+    /// # Returns
+    /// * `u64` - The limit in bytes.
+    pub fn event_size_limit(&self) -> u64 {
+        self.inner.event_size_limit.load(Ordering::Relaxed)
+    }
+
+    /// Waits for all the subscriber tasks spawned by `publish` to finish. Call this before shutting down the
+    /// runtime so in-flight events are not dropped. Events published by subscribers while draining are also
+    /// waited on.
+    pub async fn drain(&self) {
+        loop {
+            let mut tasks = std::mem::take(&mut *self.inner.lock_tasks());
+            if tasks.is_empty() {
+                return
+            }
+            while let Some(result) = tasks.join_next().await {
+                if let Err(e) = result {
+                    eprintln!("Event subscriber failed: {}", e);
+                }
+            }
+        }
+    }
+}
 
 
 #[macro_export]
@@ -5,46 +320,295 @@ macro_rules! config_tokio_event_runtime {
     () => {
         pub mod tokio_event_adapter_runtime {
 
-            use std::sync::{Arc, RwLock, LazyLock};
-            use std::collections::HashMap;
-            use serde::{Serialize, Deserialize};
-            use std::future::Future;
-            use std::pin::Pin;
+            use std::sync::LazyLock;
+            use $crate::tokio_pub_sub::EventBus;
 
-            pub type EventFunctionBuffer = Vec<EventFunction>;
-            pub type EventFunction = fn(Vec<u8>) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+            pub use $crate::tokio_pub_sub::{EventFunction, EventFunctionBuffer};
 
-            static HASHMAP: LazyLock<Arc<RwLock<HashMap<String, EventFunctionBuffer>>>> = LazyLock::new(|| {
-                Arc::new(RwLock::new(HashMap::new()))
-            });
+            /// The default global bus used by the `subscribe_to_event` and `publish_event` macros.
+            pub static EVENT_BUS: LazyLock<EventBus> = LazyLock::new(EventBus::new);
 
+            /// Registers a subscriber for an event on the global bus. Subscribers are dispatched in the order they
+            /// were registered and registering the same function for the same event more than once is a no-op.
             pub fn insert_into_hashmap(name: String, func: EventFunction) -> () {
-                let mut buffer = get_from_hashmap(&name).unwrap_or_else(|| vec![]);
-                buffer.push(func);
-                let mut map = HASHMAP.write().unwrap();
-                map.insert(name, buffer);
+                EVENT_BUS.subscribe(name, func)
             }
 
             pub fn get_from_hashmap(name: &str) -> Option<EventFunctionBuffer> {
-                HASHMAP.read().unwrap().get(name).cloned()
+                EVENT_BUS.subscribers(name)
             }
 
             pub fn publish_event(name: &str, data: Vec<u8>) -> () {
-                let buffer = match get_from_hashmap(name) {
-                    Some(b) => b,
-                    None => {
-                        println!("No subscribers for event: {}", name);
-                        return
-                    }
-                };
-                for f in buffer {
-                    let boxed_future = f(data.clone());
-                    tokio::spawn(async move {
-                        boxed_future.await;
-                    });
-                }
+                EVENT_BUS.publish(name, data)
+            }
+
+            /// Publishes an event with an idempotency key on the global bus, dropping it if the key was already
+            /// dispatched within the dedup window.
+            pub fn publish_keyed_event(name: &str, key: &str, data: Vec<u8>) -> bool {
+                EVENT_BUS.publish_with_key(name, key, data)
+            }
+
+            /// Sets the largest event data in bytes that the global bus dispatches to the subscribers.
+            pub fn set_event_size_limit(limit: u64) -> () {
+                EVENT_BUS.set_event_size_limit(limit)
+            }
+
+            /// Waits for all the subscriber tasks spawned by `publish_event` to finish. Call this before shutting
+            /// down the runtime so in-flight events are not dropped.
+            pub async fn drain_events() -> () {
+                EVENT_BUS.drain().await
             }
 
         }
     };
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::runtime::Builder;
+
+    config_tokio_event_runtime!();
+
+    use tokio_event_adapter_runtime::{insert_into_hashmap, get_from_hashmap, publish_event, drain_events};
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    static SLOW_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static POISONED_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static BUS_ONE_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static BUS_TWO_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static LIMITED_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static KEYED_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn keyed_event(_data: Vec<u8>) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(async move {
+            KEYED_CALLS.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    // mirrors the router generated by `subscribe_to_event`
+    fn limited_event(data: Vec<u8>) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(async move {
+            let payload: Vec<u8> = match deserialize_event("limited", &data) {
+                Some(payload) => payload,
+                None => return
+            };
+            LIMITED_CALLS.fetch_add(payload.len(), Ordering::SeqCst);
+        })
+    }
+
+    fn count_poisoned_event(_data: Vec<u8>) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(async move {
+            POISONED_CALLS.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    fn bus_one_event(_data: Vec<u8>) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(async move {
+            BUS_ONE_CALLS.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    fn bus_two_event(_data: Vec<u8>) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(async move {
+            BUS_TWO_CALLS.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    fn slow_event(_data: Vec<u8>) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(async move {
+            tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+            SLOW_CALLS.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    fn count_event(_data: Vec<u8>) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(async move {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    fn first_subscriber(_data: Vec<u8>) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(async move {})
+    }
+
+    fn second_subscriber(_data: Vec<u8>) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(async move {})
+    }
+
+    #[test]
+    fn test_duplicate_subscriber_runs_once() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        insert_into_hashmap("count".to_string(), count_event);
+        insert_into_hashmap("count".to_string(), count_event);
+        assert_eq!(get_from_hashmap("count").unwrap().len(), 1);
+
+        runtime.block_on(async {
+            publish_event("count", vec![]);
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        });
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_subscribers_keep_registration_order() {
+        insert_into_hashmap("ordered".to_string(), second_subscriber);
+        insert_into_hashmap("ordered".to_string(), first_subscriber);
+        insert_into_hashmap("ordered".to_string(), second_subscriber);
+
+        let expected: Vec<EventFunction> = vec![second_subscriber, first_subscriber];
+        let buffer = get_from_hashmap("ordered").unwrap();
+        assert_eq!(buffer.len(), expected.len());
+        for (registered, expected) in buffer.iter().zip(expected.iter()) {
+            assert!(std::ptr::fn_addr_eq(*registered, *expected));
+        }
+    }
+
+    #[test]
+    fn test_drain_events() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        insert_into_hashmap("slow".to_string(), slow_event);
+
+        runtime.block_on(async {
+            for _ in 0..5 {
+                publish_event("slow", vec![]);
+            }
+            assert_eq!(SLOW_CALLS.load(Ordering::SeqCst), 0);
+            drain_events().await;
+            assert_eq!(SLOW_CALLS.load(Ordering::SeqCst), 5);
+        });
+    }
+
+    #[test]
+    fn test_independent_buses() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let bus_one = EventBus::new();
+        let bus_two = EventBus::new();
+        bus_one.subscribe("shared".to_string(), bus_one_event);
+        bus_two.subscribe("shared".to_string(), bus_two_event);
+
+        runtime.block_on(async {
+            bus_one.publish("shared", vec![]);
+            bus_one.publish("shared", vec![]);
+            bus_one.drain().await;
+            assert_eq!(BUS_ONE_CALLS.load(Ordering::SeqCst), 2);
+            assert_eq!(BUS_TWO_CALLS.load(Ordering::SeqCst), 0);
+
+            // clones share the subscribers of the bus they were cloned from
+            let bus_two_clone = bus_two.clone();
+            bus_two_clone.publish("shared", vec![]);
+            bus_two.drain().await;
+            assert_eq!(BUS_ONE_CALLS.load(Ordering::SeqCst), 2);
+            assert_eq!(BUS_TWO_CALLS.load(Ordering::SeqCst), 1);
+        });
+        assert!(get_from_hashmap("shared").is_none());
+    }
+
+    #[test]
+    fn test_publish_after_poisoned_lock() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let bus = EventBus::new();
+        let poisoning_bus = bus.clone();
+        let result = std::thread::spawn(move || {
+            let _guard = poisoning_bus.inner.subscribers.write().unwrap();
+            panic!("subscriber registration failed");
+        }).join();
+        assert!(result.is_err());
+        assert!(bus.inner.subscribers.is_poisoned());
+
+        bus.subscribe("poisoned".to_string(), count_poisoned_event);
+        runtime.block_on(async {
+            bus.publish("poisoned", vec![]);
+            bus.drain().await;
+        });
+        assert_eq!(POISONED_CALLS.load(Ordering::SeqCst), 1);
+        assert!(!bus.inner.subscribers.is_poisoned());
+    }
+
+    #[test]
+    fn test_oversize_event_is_rejected() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let bus = EventBus::new();
+        assert_eq!(bus.event_size_limit(), DEFAULT_EVENT_SIZE_LIMIT);
+        bus.set_event_size_limit(1024);
+        bus.subscribe("limited".to_string(), limited_event);
+
+        runtime.block_on(async {
+            bus.publish("limited", bincode::serialize(&vec![1u8; 100]).unwrap());
+            bus.publish("limited", bincode::serialize(&vec![1u8; 2048]).unwrap());
+            bus.drain().await;
+        });
+        assert_eq!(LIMITED_CALLS.load(Ordering::SeqCst), 100);
+
+        // the limit belongs to the bus so other buses still dispatch large events
+        let other_bus = EventBus::new();
+        other_bus.subscribe("limited".to_string(), limited_event);
+        runtime.block_on(async {
+            other_bus.publish("limited", bincode::serialize(&vec![1u8; 2048]).unwrap());
+            other_bus.drain().await;
+        });
+        assert_eq!(LIMITED_CALLS.load(Ordering::SeqCst), 2148);
+
+        // a small event that claims to hold 1 GiB is rejected on the length rather than allocating for it
+        let claimed_len = bincode::serialize(&(1u64 << 30)).unwrap();
+        assert_eq!(deserialize_event::<Vec<u8>>("limited", &claimed_len), None);
+    }
+
+    #[test]
+    fn test_duplicate_keyed_event_is_dispatched_once() {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let bus = EventBus::new();
+        assert_eq!(bus.dedup_window(), DEFAULT_DEDUP_WINDOW);
+        bus.subscribe("keyed".to_string(), keyed_event);
+
+        runtime.block_on(async {
+            // the publisher retries the same event after not knowing if the first publish went through
+            assert!(bus.publish_with_key("keyed", "order-1", vec![]));
+            assert!(!bus.clone().publish_with_key("keyed", "order-1", vec![]));
+            assert!(bus.publish_with_key("keyed", "order-2", vec![]));
+            bus.drain().await;
+            assert_eq!(KEYED_CALLS.load(Ordering::SeqCst), 2);
+
+            // once the key has fallen out of the window the event is dispatched again
+            bus.set_dedup_window(Duration::from_millis(20));
+            tokio::time::sleep(Duration::from_millis(40)).await;
+            assert!(bus.publish_with_key("keyed", "order-1", vec![]));
+            bus.drain().await;
+            assert_eq!(KEYED_CALLS.load(Ordering::SeqCst), 3);
+        });
+    }
+
+}
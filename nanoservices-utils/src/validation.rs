@@ -0,0 +1,76 @@
+//! Defines the validation of the fields of contracts so input can be checked before it is handled. The checks
+//! are declared on the fields of a contract with the `ContractValidate` derive:
+//!
+//! ```rust
+//! use nanoservices_utils::validation::ContractValidate;
+//!
+//! #[derive(ContractValidate)]
+//! pub struct CreateUser {
+//!     #[validate(non_empty)]
+//!     pub name: String,
+//!     #[validate(range(min = 0, max = 150))]
+//!     pub age: i32,
+//! }
+//!
+//! let user = CreateUser { name: "John".to_string(), age: 200 };
+//! assert_eq!(user.validate().unwrap_err().message, "age must be between 0 and 150, got 200");
+//! ```
+use crate::errors::NanoServiceError;
+
+pub use nan_serve_contract_validate::ContractValidate;
+
+
+/// A contract whose fields can be checked before it is handled.
+pub trait ContractValidate {
+
+    /// Checks the fields of the contract.
+    ///
+    /// # Returns
+    /// * `Result<(), NanoServiceError>` - A `BadRequest` error naming the first field that fails.
+    fn validate(&self) -> Result<(), NanoServiceError>;
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::errors::NanoServiceErrorStatus;
+
+    #[derive(ContractValidate)]
+    pub struct CreateUser {
+        #[validate(non_empty)]
+        pub name: String,
+        #[validate(range(min = 0, max = 150))]
+        pub age: i32,
+        #[validate(range(min = 1))]
+        pub seats: u32,
+    }
+
+    #[test]
+    fn test_valid_contract() {
+        let user = CreateUser { name: "John".to_string(), age: 32, seats: 1 };
+        assert!(user.validate().is_ok());
+    }
+
+    #[test]
+    fn test_out_of_range_field() {
+        let user = CreateUser { name: "John".to_string(), age: 151, seats: 1 };
+        let error = user.validate().unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+        assert_eq!(error.message, "age must be between 0 and 150, got 151");
+
+        let user = CreateUser { name: "John".to_string(), age: 32, seats: 0 };
+        assert_eq!(user.validate().unwrap_err().message, "seats must be at least 1, got 0");
+    }
+
+    #[test]
+    fn test_empty_field() {
+        // the first failing field is named when several fail
+        let user = CreateUser { name: "".to_string(), age: -1, seats: 1 };
+        let error = user.validate().unwrap_err();
+        assert_eq!(error.status, NanoServiceErrorStatus::BadRequest);
+        assert_eq!(error.message, "name must not be empty");
+    }
+
+}
@@ -0,0 +1,15 @@
+//! Checks that contract handlers reject contract types that can not be serialized with a clear error.
+//!
+//! The handler macros check their contract types with `__assert_contract_bounds!` before generating anything, so the
+//! cases call it directly and the snapshots only hold the error naming the contract type, not the follow on errors
+//! from the generated code.
+// the snapshots list the trait implementations in scope, which depend on the enabled features, so they are only
+// checked with the feature set they were generated with
+#![cfg(feature = "full")]
+
+#[test]
+fn test_contract_type_bounds() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/contract_missing_serialize.rs");
+    cases.compile_fail("tests/ui/bitcode_contract_missing_encode.rs");
+}
@@ -0,0 +1,44 @@
+//! Checks that events over the size limit of the global bus do not reach the subscribers generated by
+//! `subscribe_to_event`.
+#![cfg(feature = "tokio-pub-sub")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use nanoservices_utils::bincode;
+use nanoservices_utils::config_tokio_event_runtime;
+use nanoservices_utils::{publish_event, subscribe_to_event};
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Builder;
+
+config_tokio_event_runtime!();
+
+static RECEIVED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LimitedEvent {
+    pub payload: Vec<u8>,
+}
+
+#[subscribe_to_event]
+async fn handle_limited_event(event: LimitedEvent) {
+    RECEIVED_BYTES.fetch_add(event.payload.len(), Ordering::SeqCst);
+}
+
+#[test]
+fn test_subscriber_rejects_oversize_event() {
+    let runtime = Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .unwrap();
+
+    tokio_event_adapter_runtime::set_event_size_limit(1024);
+    runtime.block_on(async {
+        let small = LimitedEvent { payload: vec![1; 100] };
+        publish_event!(small);
+        // dropped by the bus before it reaches the subscriber
+        let large = LimitedEvent { payload: vec![1; 2048] };
+        publish_event!(large);
+        tokio_event_adapter_runtime::drain_events().await;
+    });
+    assert_eq!(RECEIVED_BYTES.load(Ordering::SeqCst), 100);
+}
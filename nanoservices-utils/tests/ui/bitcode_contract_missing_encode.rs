@@ -0,0 +1,13 @@
+use bitcode::{Decode, Encode};
+
+#[derive(Debug, PartialEq, Encode, Decode)]
+pub struct ContractOne;
+
+// only `Encode` is missing so the check reports a single error
+#[derive(Debug, PartialEq, Decode)]
+pub struct NotEncodable;
+
+// the check `create_bitcode_contract_handler!` runs before generating the handler
+nanoservices_utils::__assert_contract_bounds!(BitcodeContract; ContractOne, NotEncodable);
+
+fn main() {}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ContractOne;
+
+// only `Serialize` is missing so the check reports a single error
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct NotSerializable;
+
+// the check `create_contract_handler!` runs before generating the handler
+nanoservices_utils::__assert_contract_bounds!(SerdeContract; ContractOne, NotSerializable);
+
+fn main() {}
@@ -0,0 +1,69 @@
+//! Counts the allocations made by the codecs when encoding. The counting allocator is the global allocator of this
+//! test binary only, so it does not replace the allocator of the library's own tests.
+#![cfg(feature = "networking")]
+
+use bytes::BytesMut;
+use nanoservices_utils::networking::serialization::codec::BincodeCodec;
+use nanoservices_utils::networking::serialization::zero_copy_codec::ZeroCopyBincodeCodec;
+use serde::{Deserialize, Serialize};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use tokio_util::codec::Encoder;
+
+/// Counts the allocations made on the current thread so the count is not affected by other tests.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn count_allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.with(|count| count.get());
+    f();
+    ALLOCATIONS.with(|count| count.get()) - before
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TestStruct {
+    field1: u32,
+    field2: String,
+}
+
+#[test]
+fn test_encode_allocates_less_than_bincode_codec() {
+    let test_struct = TestStruct {
+        field1: 42,
+        field2: "hello".to_string(),
+    };
+    let mut zero_copy_codec = ZeroCopyBincodeCodec::<TestStruct>::new();
+    let mut bincode_codec = BincodeCodec::<TestStruct>::new();
+    let mut zero_copy_buf = BytesMut::with_capacity(1024);
+    let mut bincode_buf = BytesMut::with_capacity(1024);
+
+    let zero_copy_allocations = count_allocations(|| {
+        for _ in 0..10 {
+            zero_copy_codec.encode(test_struct.clone(), &mut zero_copy_buf).unwrap();
+        }
+    });
+    let bincode_allocations = count_allocations(|| {
+        for _ in 0..10 {
+            bincode_codec.encode(test_struct.clone(), &mut bincode_buf).unwrap();
+        }
+    });
+    // both count the clone of the string, only the bincode codec allocates a `Vec` per message
+    assert!(zero_copy_allocations < bincode_allocations, "{} >= {}", zero_copy_allocations, bincode_allocations);
+}
@@ -0,0 +1,12 @@
+#!/usr/bin/env bash
+
+# builds the errors core of nanoservices-utils for a target without `std`, which fails if anything in the core or
+# its dependencies needs `std`
+set -e
+
+SCRIPTPATH="$( cd "$(dirname "$0")" ; pwd -P )"
+cd $SCRIPTPATH
+cd ..
+
+rustup target add thumbv7em-none-eabi
+cargo build -p no-std-core --target thumbv7em-none-eabi
@@ -0,0 +1,14 @@
+#!/usr/bin/env bash
+
+# nanoservices-utils depends on the macro crates in crates/ by path and by version, so they have to be on
+# crates.io before nanoservices-utils is published. Bump the version of a macro crate and its requirement in
+# nanoservices-utils/Cargo.toml whenever the code it generates changes.
+set -e
+
+SCRIPTPATH="$( cd "$(dirname "$0")" ; pwd -P )"
+cd $SCRIPTPATH
+cd ..
+
+cargo publish -p nan-serve-contract-validate
+cargo publish -p nan-serve-event-subscriber
+cargo publish -p nanoservices-utils
@@ -0,0 +1,39 @@
+//! Uses the errors core of `nanoservices-utils` from a `no_std` crate so the core is checked to build without `std`.
+#![no_std]
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use nanoservices_utils::errors::{NanoServiceError, NanoServiceErrorStatus};
+
+
+/// Looks up a reading from a sensor, returning the error that a `no_std` service would pass to its peers.
+///
+/// # Arguments
+/// * `readings` - The readings of the sensors.
+/// * `sensor` - The index of the sensor to read.
+///
+/// # Returns
+/// * `Result<u16, NanoServiceError>` - The reading or a `NotFound` error with a code.
+pub fn read_sensor(readings: &[u16], sensor: usize) -> Result<u16, NanoServiceError> {
+    readings.get(sensor).copied().ok_or_else(|| {
+        NanoServiceError::new(
+            "sensor not found".to_string(),
+            NanoServiceErrorStatus::NotFound
+        ).with_code("sensor.not_found")
+    })
+}
+
+
+/// Describes an error the way a `no_std` service would log it.
+///
+/// # Arguments
+/// * `error` - The error to describe.
+///
+/// # Returns
+/// * `String` - The error with its status, plus whether the request can be retried.
+pub fn describe(error: &NanoServiceError) -> String {
+    match error.is_retryable() {
+        true => alloc::format!("{} (retryable)", error),
+        false => error.to_string()
+    }
+}
@@ -0,0 +1,338 @@
+//! A host client that sends contracts to a WASM guest and reads the contracts it returns.
+use wasmtime::{Engine, Instance, Linker, Module, Store, Config, Trap};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::WasiCtxBuilder;
+use nanoservices_utils::errors::{NanoServiceError, NanoServiceErrorStatus};
+use kernel::ContractHandler;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+
+/// How long a call into the guest can run before it is interrupted, unless set with `with_timeout`.
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The largest response (in bytes) that the host will allocate a buffer for, unless set with `with_max_response_len`.
+pub const DEFAULT_MAX_RESPONSE_LEN: usize = 16 * 1024 * 1024;
+
+/// The size of the `ContractPointer` (`ptr: i32`, `len: i32`) that the guest entry point returns a pointer to.
+const CONTRACT_POINTER_SIZE: usize = 8;
+
+
+/// Sends contracts to the entry points of an instantiated WASM guest.
+///
+/// # Fields
+/// * `engine` - The engine the guest runs on, used to interrupt a guest that runs past the timeout.
+/// * `store` - The store holding the state of the guest.
+/// * `instance` - The instantiated guest.
+/// * `timeout` - How long a call into the guest can run before it is interrupted.
+/// * `max_response_len` - The largest response (in bytes) that the host will allocate a buffer for.
+///
+/// # Notes
+/// A guest that is interrupted can be left in an inconsistent state, so the client should be dropped after a call
+/// times out.
+pub struct WasmContractClient {
+    engine: Engine,
+    store: Store<WasiP1Ctx>,
+    instance: Instance,
+    timeout: Duration,
+    max_response_len: usize,
+}
+
+impl WasmContractClient {
+
+    /// Loads and instantiates the guest from a `.wasm` file.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the `.wasm` file.
+    ///
+    /// # Returns
+    /// * `Result<WasmContractClient, NanoServiceError>` - The client for the instantiated guest.
+    pub async fn from_file(path: &str) -> Result<Self, NanoServiceError> {
+        let engine = Self::engine()?;
+        let module = Module::from_file(&engine, path).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })?;
+        Self::instantiate(engine, module).await
+    }
+
+    /// Compiles and instantiates the guest from the bytes of a `.wasm` file or WebAssembly text.
+    ///
+    /// # Arguments
+    /// * `bytes` - The guest module.
+    ///
+    /// # Returns
+    /// * `Result<WasmContractClient, NanoServiceError>` - The client for the instantiated guest.
+    pub async fn from_bytes(bytes: impl AsRef<[u8]>) -> Result<Self, NanoServiceError> {
+        let engine = Self::engine()?;
+        let module = Module::new(&engine, bytes).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })?;
+        Self::instantiate(engine, module).await
+    }
+
+    /// Sets how long a call into the guest can run before it is interrupted.
+    ///
+    /// # Arguments
+    /// * `timeout` - The timeout for a call.
+    ///
+    /// # Returns
+    /// * `WasmContractClient` - The client with the timeout set.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the largest response (in bytes) that the host will allocate a buffer for.
+    ///
+    /// # Arguments
+    /// * `max_response_len` - The maximum length of a response.
+    ///
+    /// # Returns
+    /// * `WasmContractClient` - The client with the maximum response length set.
+    pub fn with_max_response_len(mut self, max_response_len: usize) -> Self {
+        self.max_response_len = max_response_len;
+        self
+    }
+
+    /// Sends a contract to the guest entry point named after it and decodes the contract the guest returns.
+    ///
+    /// # Arguments
+    /// * `contract` - The contract to send.
+    ///
+    /// # Returns
+    /// * `Result<ContractHandler, NanoServiceError>` - The contract returned by the guest.
+    pub async fn send_contract(&mut self, contract: ContractHandler) -> Result<ContractHandler, NanoServiceError> {
+        let name_ref = contract.to_string_ref();
+        let serialized = contract.to_contract_bytes()?;
+        let response = self.call(&name_ref, &serialized).await?;
+        ContractHandler::from_contract_bytes(&response, name_ref)
+    }
+
+    /// Calls a guest entry point with the contract bytes and returns the bytes of the contract it returns.
+    ///
+    /// # Arguments
+    /// * `entry_point` - The name of the exported entry point.
+    /// * `contract_bytes` - The serialized contract to pass to the entry point.
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>, NanoServiceError>` - The serialized contract returned by the guest, the error the guest
+    ///   returned, a `ServiceUnavailable` error if the call ran past the timeout, or a `PayloadTooLarge` error if the
+    ///   guest reported a response longer than the maximum response length.
+    pub async fn call(&mut self, entry_point: &str, contract_bytes: &[u8]) -> Result<Vec<u8>, NanoServiceError> {
+        // the watchdog interrupts the guest if it is still running when the timeout elapses
+        self.store.set_epoch_deadline(1);
+        let _watchdog = Self::watchdog(self.engine.clone(), self.timeout);
+        self.exchange(entry_point, contract_bytes).await
+    }
+
+    /// Maps an error returned by a call into the guest, turning the trap raised when the watchdog interrupts the
+    /// guest into a `ServiceUnavailable` error.
+    ///
+    /// # Arguments
+    /// * `error` - The error returned by the call.
+    ///
+    /// # Returns
+    /// * `NanoServiceError` - The mapped error.
+    fn guest_error(&self, error: wasmtime::Error) -> NanoServiceError {
+        match error.downcast_ref::<Trap>() {
+            Some(Trap::Interrupt) => NanoServiceError::new(
+                format!("Guest call did not finish within {:?}.", self.timeout),
+                NanoServiceErrorStatus::ServiceUnavailable
+            ),
+            _ => NanoServiceError::new(error.to_string(), NanoServiceErrorStatus::Unknown)
+        }
+    }
+
+    /// Builds an engine that supports async calls and interrupting guests.
+    ///
+    /// # Returns
+    /// * `Result<Engine, NanoServiceError>` - The engine.
+    fn engine() -> Result<Engine, NanoServiceError> {
+        let mut config = Config::new();
+        config.async_support(true);
+        config.epoch_interruption(true);
+        Engine::new(&config).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+        })
+    }
+
+    /// Links WASI into the guest module and instantiates it.
+    ///
+    /// # Arguments
+    /// * `engine` - The engine the module was compiled with.
+    /// * `module` - The guest module.
+    ///
+    /// # Returns
+    /// * `Result<WasmContractClient, NanoServiceError>` - The client for the instantiated guest.
+    async fn instantiate(engine: Engine, module: Module) -> Result<Self, NanoServiceError> {
+        let mut linker: Linker<WasiP1Ctx> = Linker::new(&engine);
+        preview1::add_to_linker_async(&mut linker, |t| t).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+        })?;
+        let pre = linker.instantiate_pre(&module).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })?;
+        let wasi_ctx = WasiCtxBuilder::new()
+            .inherit_stdio()
+            .inherit_env()
+            .build_p1();
+        let mut store = Store::new(&engine, wasi_ctx);
+        // the epoch only moves when a watchdog fires, so a deadline of one tick never interrupts instantiation
+        store.set_epoch_deadline(1);
+        let instance = pre.instantiate_async(&mut store).await.map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::Unknown)
+        })?;
+        Ok(WasmContractClient {
+            engine,
+            store,
+            instance,
+            timeout: DEFAULT_CALL_TIMEOUT,
+            max_response_len: DEFAULT_MAX_RESPONSE_LEN,
+        })
+    }
+
+    /// Starts a thread that increments the epoch of the engine, interrupting the running guest, once the timeout
+    /// elapses. A thread is used rather than a task as the guest blocks the runtime thread while it runs.
+    ///
+    /// # Arguments
+    /// * `engine` - The engine the guest runs on.
+    /// * `timeout` - How long the guest can run.
+    ///
+    /// # Returns
+    /// * `Sender<()>` - Stops the watchdog when it is dropped.
+    fn watchdog(engine: Engine, timeout: Duration) -> Sender<()> {
+        let (stop, stopped) = channel::<()>();
+        thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(timeout) {
+                engine.increment_epoch();
+            }
+        });
+        stop
+    }
+
+    /// Writes the contract bytes into the guest, calls the entry point, and reads the response back out.
+    ///
+    /// # Arguments
+    /// * `entry_point` - The name of the exported entry point.
+    /// * `contract_bytes` - The serialized contract to pass to the entry point.
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>, NanoServiceError>` - The serialized contract returned by the guest.
+    async fn exchange(&mut self, entry_point: &str, contract_bytes: &[u8]) -> Result<Vec<u8>, NanoServiceError> {
+        let input_len = i32::try_from(contract_bytes.len()).map_err(|_| {
+            NanoServiceError::new(
+                format!("Contract of {} bytes is too large for the guest.", contract_bytes.len()),
+                NanoServiceErrorStatus::PayloadTooLarge
+            )
+        })?;
+
+        // allocate the memory for the input data and write the contract to it
+        let malloc = self.instance.get_typed_func::<(i32, i32), i32>(&mut self.store, "ns_malloc").map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })?;
+        let free = self.instance.get_typed_func::<(i32, i32, i32), ()>(&mut self.store, "ns_free").map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })?;
+        let memory = self.instance.get_memory(&mut self.store, "memory").ok_or(NanoServiceError::new(
+            "Guest does not export its memory.".to_string(),
+            NanoServiceErrorStatus::BadRequest
+        ))?;
+        let input_data_ptr = malloc.call_async(&mut self.store, (input_len, 0)).await.map_err(|e| self.guest_error(e))?;
+        memory.write(&mut self.store, input_data_ptr as usize, contract_bytes).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })?;
+
+        // call the entry point and read the pointer and length of the response
+        let entry_point = self.instance.get_typed_func::<(i32, i32), i32>(&mut self.store, entry_point).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::ContractNotSupported)
+        })?;
+        let ret = entry_point.call_async(&mut self.store, (input_data_ptr, input_len)).await.map_err(|e| self.guest_error(e))?;
+        let mut contract_pointer = [0; CONTRACT_POINTER_SIZE];
+        memory.read(&self.store, ret as usize, &mut contract_pointer).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })?;
+        let result_ptr = i32::from_le_bytes([contract_pointer[0], contract_pointer[1], contract_pointer[2], contract_pointer[3]]);
+        let result_len = i32::from_le_bytes([contract_pointer[4], contract_pointer[5], contract_pointer[6], contract_pointer[7]]);
+
+        // the length is checked before the buffer is allocated so a guest cannot make the host allocate any amount
+        let response_len = result_len.unsigned_abs() as usize;
+        if response_len > self.max_response_len {
+            return Err(NanoServiceError::new(
+                format!(
+                    "Guest response of {} bytes exceeds the limit of {} bytes.",
+                    response_len, self.max_response_len
+                ),
+                NanoServiceErrorStatus::PayloadTooLarge
+            ))
+        }
+        let mut response = vec![0; response_len];
+        memory.read(&self.store, result_ptr as usize, &mut response).map_err(|e| {
+            NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+        })?;
+
+        free.call_async(&mut self.store, (input_data_ptr, input_len, 0)).await.map_err(|e| self.guest_error(e))?;
+        free.call_async(&mut self.store, (result_ptr, response_len as i32, 0)).await.map_err(|e| self.guest_error(e))?;
+        free.call_async(&mut self.store, (ret, CONTRACT_POINTER_SIZE as i32, 0)).await.map_err(|e| self.guest_error(e))?;
+
+        // a negative length means the guest returned a serialized error instead of the contract
+        if result_len < 0 {
+            let error: NanoServiceError = bincode::deserialize(&response).map_err(|e| {
+                NanoServiceError::new(e.to_string(), NanoServiceErrorStatus::BadRequest)
+            })?;
+            return Err(error)
+        }
+        Ok(response)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::runtime::Builder;
+
+    /// A guest whose entry point never returns.
+    const LOOPING_GUEST: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "ns_malloc") (param i32 i32) (result i32) i32.const 1024)
+            (func (export "ns_free") (param i32 i32 i32))
+            (func (export "entry") (param i32 i32) (result i32)
+                (loop $spin (br $spin))
+                i32.const 0)
+        )
+    "#;
+
+    /// A guest whose entry point returns a `ContractPointer` at 16 reporting a response of `i32::MAX` bytes.
+    const OVERSIZED_GUEST: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (data (i32.const 16) "\00\08\00\00\ff\ff\ff\7f")
+            (func (export "ns_malloc") (param i32 i32) (result i32) i32.const 1024)
+            (func (export "ns_free") (param i32 i32 i32))
+            (func (export "entry") (param i32 i32) (result i32) i32.const 16)
+        )
+    "#;
+
+    #[test]
+    fn test_call_times_out() {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut client = WasmContractClient::from_bytes(LOOPING_GUEST).await.unwrap()
+                .with_timeout(Duration::from_millis(100));
+            let error = client.call("entry", &[1, 2, 3]).await.unwrap_err();
+            assert_eq!(error.status, NanoServiceErrorStatus::ServiceUnavailable);
+        });
+    }
+
+    #[test]
+    fn test_oversized_response_is_rejected() {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut client = WasmContractClient::from_bytes(OVERSIZED_GUEST).await.unwrap();
+            let error = client.call("entry", &[1, 2, 3]).await.unwrap_err();
+            assert_eq!(error.status, NanoServiceErrorStatus::PayloadTooLarge);
+        });
+    }
+}
@@ -10,9 +10,11 @@ use kernel::{
 
 #[tokio::main]
 async fn main() {
-    let proxy = TcpToWasmProxy::new(
+    let mut proxy = TcpToWasmProxy::new(
         "0.0.0.0:8001".to_string(),
         "../wasi-server/wasi-server.wasm".to_string(),
     );
-    let outcome = proxy.start::<ContractHandler>().await.unwrap();
+    proxy.start::<ContractHandler>().await.unwrap();
+    tokio::signal::ctrl_c().await.unwrap();
+    proxy.shutdown().await.unwrap();
 }
@@ -1,4 +1,4 @@
-use nanoservices_utils::errors::{NanoServiceError, NanoServiceErrorStatus};
+use nanoservices_utils::errors::NanoServiceError;
 use kernel::{
     ContractHandler,
     ContractOne,